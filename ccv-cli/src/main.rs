@@ -1,17 +1,75 @@
-use ccv_core::{schema::ConfigKind, validator::Validator};
+use ccv_core::{
+    cache::{format_age, SchemaCache},
+    jinja::InstanceData,
+    lifecycle::{CloudInitVersion, DeprecationPolicy},
+    schema::{source_from_spec, ConfigKind, NetworkVersion, Schema, SchemaSource, UrlSource},
+    userdata::UserData,
+    validator::Validator,
+    yaml::YamlVersion,
+};
 use clap::builder::TypedValueParser as _;
 use clap::Parser;
 use std::{
     fs,
-    io::{self, Read},
+    io::{self, IsTerminal, Read},
     path::{Path, PathBuf},
     process,
+    sync::Arc,
+    time::Duration,
 };
 
+mod report;
+
 #[derive(Parser)]
 #[command(name = "ccv", author, version, about, long_about = None)]
 enum CCVCli {
     Validate(ValidateArgs),
+    /// Manage the JSON Schemas
+    #[command(subcommand)]
+    Schema(SchemaCommand),
+}
+
+#[derive(clap::Subcommand)]
+enum SchemaCommand {
+    /// Show the JSON Schemas cached on disk
+    Cache(CacheArgs),
+    /// Print a JSON Schema with its references resolved, as bundled releases are
+    Resolve(ResolveArgs),
+}
+
+#[derive(clap::Args)]
+struct ResolveArgs {
+    #[arg(
+        long,
+        default_value_t = ConfigKind::CloudConfig,
+        value_parser = clap::builder::PossibleValuesParser::new(
+            ["cloudconfig", "networkconfig", "networkconfigv2"]
+        )
+        .map(|s| s.parse::<ConfigKind>().unwrap()),
+    )]
+    kind: ConfigKind,
+
+    /// Where to load the JSON Schema from, as `validate --schema`, e.g. a cloud-init source
+    /// checkout.
+    #[arg(long, value_parser = source_from_spec)]
+    schema: Arc<dyn SchemaSource>,
+}
+
+#[derive(clap::Args)]
+struct CacheArgs {
+    /// Directory where the JSON Schemas fetched over HTTP are cached.
+    #[arg(long, env = "CCV_CACHE_DIR", default_value_os_t = SchemaCache::default_dir())]
+    cache_dir: PathBuf,
+
+    /// Seconds after which a cached JSON Schema is revalidated against its source.
+    #[arg(long, env = "CCV_CACHE_TTL", default_value_t = SchemaCache::DEFAULT_TTL.as_secs())]
+    cache_ttl: u64,
+}
+
+impl CacheArgs {
+    fn cache(&self) -> SchemaCache {
+        SchemaCache::new(&self.cache_dir).with_ttl(Duration::from_secs(self.cache_ttl))
+    }
 }
 
 #[derive(clap::Args)]
@@ -26,19 +84,101 @@ struct ValidateArgs {
             .map(|s| s.parse::<ConfigKind>().unwrap()),
     )]
     kind: ConfigKind,
+
+    /// Network-config version. Defaults to the one declared by the payload, or 1.
+    #[arg(
+        long,
+        value_parser = clap::builder::PossibleValuesParser::new(["1", "2"])
+            .map(|s| s.parse::<NetworkVersion>().unwrap()),
+    )]
+    network_version: Option<NetworkVersion>,
+
+    /// YAML version to load the payload with. Defaults to 1.1 for cloud-configs, as cloud-init,
+    /// and to 1.2 for network-configs.
+    #[arg(
+        long,
+        value_parser = clap::builder::PossibleValuesParser::new(["1.1", "1.2"])
+            .map(|s| s.parse::<YamlVersion>().unwrap()),
+    )]
+    yaml_version: Option<YamlVersion>,
+
+    /// Where to load the JSON Schema from: `vendored`, `vendored:<release>` for a bundled cloud-init
+    /// release such as `23.1`, an `http(s)://` URL, a cloud-init source checkout or schemas
+    /// directory, or a schema file. Defaults to cloud-init's `main` branch.
+    #[arg(long, value_parser = source_from_spec)]
+    schema: Option<Arc<dyn SchemaSource>>,
+
+    /// Validate the cloud-config cloud-init merges from the user-data parts too.
+    #[arg(long)]
+    merged: bool,
+
+    /// Vendor-data merged with the user-data, implies `--merged`.
+    #[arg(long, value_name = "FILE")]
+    vendor_data: Option<PathBuf>,
+
+    /// Instance-data JSON, such as `/run/cloud-init/instance-data.json`, to render jinja templates
+    /// with before validating them.
+    #[arg(long, value_name = "FILE")]
+    instance_data: Option<PathBuf>,
+
+    /// How to report deprecated and changed keys and values: as errors, as warnings or not at all.
+    #[arg(
+        long,
+        default_value_t = DeprecationPolicy::Warn,
+        value_parser = clap::builder::PossibleValuesParser::new(["error", "warn", "ignore"])
+            .map(|s| s.parse::<DeprecationPolicy>().unwrap()),
+    )]
+    deprecations: DeprecationPolicy,
+
+    /// Check the compatibility with a cloud-init version, such as `22.4`: keys and values too new
+    /// for it or already removed by it, and the minimum version the file requires.
+    #[arg(long, value_name = "VERSION", value_parser = clap::value_parser!(CloudInitVersion))]
+    target_cloud_init: Option<CloudInitVersion>,
+
+    /// Output format. Defaults to `human` when stdout is a terminal and to `json` otherwise.
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+
+    /// List, in the `human` format, the errors of every `oneOf`/`anyOf` branch each reported
+    /// error was collapsed from.
+    #[arg(long)]
+    verbose: bool,
+
+    #[command(flatten)]
+    cache: CacheArgs,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Format {
+    Human,
+    Json,
 }
 
 #[tokio::main]
 async fn main() -> process::ExitCode {
-    let CCVCli::Validate(args) = CCVCli::parse();
+    match CCVCli::parse() {
+        CCVCli::Validate(args) => validate(args).await,
+        CCVCli::Schema(SchemaCommand::Cache(args)) => show_cache(args),
+        CCVCli::Schema(SchemaCommand::Resolve(args)) => resolve(args).await,
+    }
+}
+
+async fn validate(args: ValidateArgs) -> process::ExitCode {
+    let format = args.format.unwrap_or_else(|| {
+        if io::stdout().is_terminal() {
+            Format::Human
+        } else {
+            Format::Json
+        }
+    });
 
     let payload = if Path::new("-") == args.file {
         let mut buffer = Vec::new();
         io::stdin().read_to_end(&mut buffer).unwrap();
-        String::from_utf8(buffer).unwrap()
+        buffer
     } else {
-        let f = args.file;
-        match fs::read_to_string(&f) {
+        let f = &args.file;
+        match fs::read(f) {
             Ok(p) => p,
             Err(e) => {
                 eprintln!("Error reading {:?}: {}", f, e);
@@ -46,22 +186,169 @@ async fn main() -> process::ExitCode {
             }
         }
     };
-    let validator = match Validator::new(args.kind).await {
-        Err(e) => panic!("Error reading the JsonSchema: {}", e),
-        Ok(v) => v,
+    let vendor_data = match &args.vendor_data {
+        Some(f) => match fs::read(f) {
+            Ok(p) => Some(UserData::detect(&p)),
+            Err(e) => {
+                eprintln!("Error reading {:?}: {}", f, e);
+                return process::ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+    let instance_data = match &args.instance_data {
+        Some(f) => match fs::read_to_string(f)
+            .map_err(|e| e.to_string())
+            .and_then(|json| InstanceData::from_json(&json).map_err(|e| e.to_string()))
+        {
+            Ok(instance_data) => Some(instance_data),
+            Err(e) => {
+                eprintln!("Error reading {:?}: {}", f, e);
+                return process::ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+    let source = args
+        .schema
+        .unwrap_or_else(|| Arc::new(UrlSource::default()));
+    // User-data may be compressed or encoded, network-configs are plain YAML.
+    let user_data = match args.kind {
+        ConfigKind::CloudConfig => Some(UserData::detect(&payload)),
+        _ => None,
+    };
+    let payload = match &user_data {
+        Some(user_data) => user_data.content.clone(),
+        None => String::from_utf8_lossy(&payload).into_owned(),
+    };
+    let kind = match args.kind {
+        ConfigKind::NetworkConfig => args
+            .network_version
+            .or_else(|| NetworkVersion::detect(&payload))
+            .unwrap_or_default()
+            .kind(),
+        kind => kind,
+    };
+    let schema = args.cache.cache().load(source.as_ref(), kind).await;
+    let validator = match schema.and_then(|(schema, status)| {
+        if let Some(warning) = status.warning() {
+            eprintln!("Warning: {}", warning);
+        }
+        Validator::from_schema(schema)
+    }) {
+        Err(e) => {
+            eprintln!("Error reading the JsonSchema: {}", e);
+            return process::ExitCode::FAILURE;
+        }
+        Ok(v) => match args.yaml_version {
+            Some(yaml_version) => v.with_yaml_version(yaml_version),
+            None => v,
+        },
     };
 
     // TODO handle error
-    let validation = validator.validate_yaml(&(payload)).unwrap();
-    let exit_code = if validation.is_valid {
+    let mut validation = match (&user_data, &instance_data) {
+        (Some(user_data), Some(instance_data)) => {
+            validator.validate_templated_user_data(user_data, instance_data)
+        }
+        (Some(user_data), None) => validator.validate_user_data(user_data),
+        (None, _) => validator.validate_yaml(&payload),
+    }
+    .unwrap();
+    if let Some(user_data) = user_data.filter(|_| args.merged || vendor_data.is_some()) {
+        validation =
+            validation.with_merged(validator.validate_merged(&user_data, vendor_data.as_ref()));
+    }
+    if let Some(target) = args.target_cloud_init {
+        validation = validation.with_compatibility(target);
+    }
+    let validation = validation.with_deprecation_policy(args.deprecations);
+    let is_compatible = validation
+        .compatibility()
+        .is_none_or(|compatibility| compatibility.is_compatible);
+    let exit_code = if validation.is_valid && is_compatible {
         process::ExitCode::SUCCESS
     } else {
         // XXX: Unique exit code? 2 os used by clap when bad used
         process::ExitCode::FAILURE
     };
 
-    let res = serde_json::to_value(&validation).expect("Validation must be JSON serializable");
-    println!("{}", res);
+    match format {
+        Format::Json => println!(
+            "{}",
+            serde_json::to_value(&validation).expect("Validation must be JSON serializable")
+        ),
+        Format::Human => {
+            let file_name = if Path::new("-") == args.file {
+                "<stdin>".to_string()
+            } else {
+                args.file.display().to_string()
+            };
+            report::render(
+                &mut anstream::stdout(),
+                &file_name,
+                &payload,
+                &validation,
+                args.verbose,
+            )
+            .expect("Error writing to stdout");
+        }
+    }
 
     exit_code
 }
+
+async fn resolve(args: ResolveArgs) -> process::ExitCode {
+    match Schema::from_source(args.schema.as_ref(), args.kind).await {
+        Ok(schema) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(schema.schema()).expect("valid JSON")
+            );
+            process::ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error reading the JsonSchema: {}", e);
+            process::ExitCode::FAILURE
+        }
+    }
+}
+
+fn show_cache(args: CacheArgs) -> process::ExitCode {
+    let cache = args.cache();
+    let entries = match cache.entries() {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Error reading the cache: {}", e);
+            return process::ExitCode::FAILURE;
+        }
+    };
+
+    println!("Cache directory: {}", cache.dir().display());
+    println!("TTL: {}", format_age(cache.ttl()));
+    if entries.is_empty() {
+        println!("No cached JSON Schemas");
+    }
+    for entry in entries {
+        let age = entry.age();
+        let state = if age < cache.ttl() {
+            "fresh"
+        } else {
+            "expired"
+        };
+        println!();
+        println!("{} {}", entry.kind, entry.url);
+        println!("  fetched {} ago ({})", format_age(age), state);
+        for document in entry.documents {
+            print!("  - {}", document.url);
+            if let Some(etag) = document.etag {
+                print!(" etag: {}", etag);
+            }
+            if let Some(last_modified) = document.last_modified {
+                print!(" last-modified: {}", last_modified);
+            }
+            println!();
+        }
+    }
+    process::ExitCode::SUCCESS
+}
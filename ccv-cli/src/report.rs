@@ -0,0 +1,746 @@
+//! Human-readable rendering of a `Validation`.
+use std::io::{self, Write};
+
+use anstyle::{AnsiColor, Color, Style};
+use ccv_core::code::{Code, Severity};
+use ccv_core::lifecycle::{Compatibility, Lifecycle};
+use ccv_core::span::Span;
+use ccv_core::userdata::PartRef;
+use ccv_core::validator::{ConfigAnnotation, ConfigError, ErrorDetail, Suggestion, Validation};
+
+const ERROR: Style = Style::new()
+    .fg_color(Some(Color::Ansi(AnsiColor::Red)))
+    .bold();
+const WARNING: Style = Style::new()
+    .fg_color(Some(Color::Ansi(AnsiColor::Yellow)))
+    .bold();
+const INFO: Style = Style::new()
+    .fg_color(Some(Color::Ansi(AnsiColor::Cyan)))
+    .bold();
+const SUCCESS: Style = Style::new()
+    .fg_color(Some(Color::Ansi(AnsiColor::Green)))
+    .bold();
+const GUTTER: Style = Style::new()
+    .fg_color(Some(Color::Ansi(AnsiColor::Blue)))
+    .bold();
+const BOLD: Style = Style::new().bold();
+
+/// Maximum number of source lines shown per snippet.
+const MAX_SNIPPET_LINES: usize = 3;
+
+/// Writes every error and annotation of `validation` followed by a summary line, then the merged
+/// cloud-config and its validation, if any. `verbose` lists the errors every error was collapsed
+/// from.
+pub fn render(
+    out: &mut impl Write,
+    file_name: &str,
+    payload: &str,
+    validation: &Validation,
+    verbose: bool,
+) -> io::Result<()> {
+    render_validation(out, file_name, file_name, payload, validation, verbose)?;
+
+    if let Some(merged) = validation.merged() {
+        writeln!(out)?;
+        writeln!(out, "{BOLD}merged cloud-config:{BOLD:#}")?;
+        // JSON is YAML too.
+        writeln!(out, "{:#}", merged.config)?;
+        writeln!(out)?;
+        render_validation(
+            out,
+            file_name,
+            &format!("{} (merged)", file_name),
+            payload,
+            &merged.validation,
+            verbose,
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes every error and annotation of `validation` followed by a summary line about `subject`.
+fn render_validation(
+    out: &mut impl Write,
+    file_name: &str,
+    subject: &str,
+    payload: &str,
+    validation: &Validation,
+    verbose: bool,
+) -> io::Result<()> {
+    let errors: Vec<Item> = validation.errors().map(Item::from).collect();
+    let annotations: Vec<&ConfigAnnotation> = validation.annotations().collect();
+
+    for error in &errors {
+        render_item(out, file_name, payload, error, verbose)?;
+    }
+    for annotation in &annotations {
+        render_item(out, file_name, payload, &Item::from(*annotation), verbose)?;
+    }
+
+    let style = summary_style(errors.len(), annotations.len());
+    let deprecations = annotations
+        .iter()
+        .filter(|annotation| annotation.is_deprecation())
+        .count();
+    // Informational annotations are not counted.
+    let warnings = annotations
+        .iter()
+        .filter(|annotation| {
+            !annotation.is_deprecation() && annotation.severity() == Severity::Warning
+        })
+        .count();
+    write!(
+        out,
+        "{style}{}: {}, {}",
+        subject,
+        plural(errors.len(), "error"),
+        plural(deprecations, "deprecation"),
+    )?;
+    if warnings > 0 {
+        write!(out, ", {}", plural(warnings, "warning"))?;
+    }
+    writeln!(out, "{style:#}")?;
+
+    match validation.compatibility() {
+        Some(compatibility) => {
+            writeln!(out)?;
+            render_compatibility(out, file_name, subject, payload, compatibility, verbose)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Writes every incompatibility with the target cloud-init version followed by a summary line
+/// about `subject`.
+fn render_compatibility(
+    out: &mut impl Write,
+    file_name: &str,
+    subject: &str,
+    payload: &str,
+    compatibility: &Compatibility,
+    verbose: bool,
+) -> io::Result<()> {
+    let errors: Vec<Item> = compatibility.errors().map(Item::from).collect();
+    let annotations: Vec<Item> = compatibility.annotations().map(Item::from).collect();
+
+    for item in errors.iter().chain(&annotations) {
+        render_item(out, file_name, payload, item, verbose)?;
+    }
+
+    let style = summary_style(errors.len(), annotations.len());
+    write!(
+        out,
+        "{style}{} on cloud-init {}: {}, {}",
+        subject,
+        compatibility.target(),
+        plural(errors.len(), "error"),
+        plural(annotations.len(), "change"),
+    )?;
+    if let Some(minimum_version) = compatibility.minimum_version() {
+        write!(out, ", requires cloud-init {} or later", minimum_version)?;
+    }
+    writeln!(out, "{style:#}")
+}
+
+fn summary_style(errors: usize, annotations: usize) -> Style {
+    if errors > 0 {
+        ERROR
+    } else if annotations > 0 {
+        WARNING
+    } else {
+        SUCCESS
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {}", noun),
+        _ => format!("{} {}s", count, noun),
+    }
+}
+
+/// What is shown of an error or annotation.
+struct Item<'a> {
+    code: Code,
+    severity: Severity,
+    description: &'a str,
+    instance_path: &'a str,
+    span: Option<Span>,
+    part: Option<&'a PartRef>,
+    schema_description: Option<&'a str>,
+    lifecycle: Option<&'a Lifecycle>,
+    suggestions: &'a [Suggestion],
+    detail: &'a [ErrorDetail],
+}
+
+impl<'a> From<&'a ConfigError> for Item<'a> {
+    fn from(error: &'a ConfigError) -> Self {
+        Self {
+            code: error.code(),
+            severity: error.severity(),
+            description: error.description(),
+            instance_path: error.instance_path(),
+            span: error.span(),
+            part: error.part(),
+            schema_description: error.schema_description(),
+            // Deprecations reported as errors do not keep their lifecycle.
+            lifecycle: None,
+            suggestions: error.suggestions(),
+            detail: error.detail(),
+        }
+    }
+}
+
+impl<'a> From<&'a ConfigAnnotation> for Item<'a> {
+    fn from(annotation: &'a ConfigAnnotation) -> Self {
+        Self {
+            code: annotation.code(),
+            severity: annotation.severity(),
+            description: annotation.description(),
+            instance_path: annotation.instance_path(),
+            span: annotation.span(),
+            part: annotation.part(),
+            schema_description: None,
+            lifecycle: Some(annotation.lifecycle()),
+            suggestions: &[],
+            detail: &[],
+        }
+    }
+}
+
+fn render_item(
+    out: &mut impl Write,
+    file_name: &str,
+    payload: &str,
+    item: &Item,
+    verbose: bool,
+) -> io::Result<()> {
+    // Deprecations may be reported as errors, and annotations may be informational only.
+    let style = match item.severity {
+        Severity::Error => ERROR,
+        Severity::Warning => WARNING,
+        Severity::Info => INFO,
+    };
+    writeln!(
+        out,
+        "{style}{}[{}]{style:#}{BOLD}: {}{BOLD:#}",
+        item.severity, item.code, item.description
+    )?;
+
+    let lines: Vec<&str> = payload.lines().collect();
+    let last_line = item.span.map_or(0, |span| last_line(&span, &lines));
+    let width = last_line.to_string().len();
+    let pad = " ".repeat(width);
+
+    match item.span {
+        Some(span) => writeln!(
+            out,
+            "{pad}{GUTTER}-->{GUTTER:#} {}:{}:{}",
+            file_name, span.start.line, span.start.column
+        )?,
+        None => writeln!(out, "{pad}{GUTTER}-->{GUTTER:#} {}", file_name)?,
+    }
+
+    if let Some(span) = item.span {
+        writeln!(out, "{pad} {GUTTER}|{GUTTER:#}")?;
+        let shown_lines = (span.start.line..=last_line).take(MAX_SNIPPET_LINES);
+        for line_number in shown_lines {
+            let line = lines.get(line_number - 1).copied().unwrap_or_default();
+            let from = if line_number == span.start.line {
+                span.start.column
+            } else {
+                line.chars().take_while(|c| c.is_whitespace()).count() + 1
+            };
+            let to = if line_number == span.end.line {
+                span.end.column
+            } else {
+                line.chars().count() + 1
+            };
+            writeln!(
+                out,
+                "{GUTTER}{:>width$} |{GUTTER:#} {}",
+                line_number,
+                line,
+                width = width
+            )?;
+            writeln!(
+                out,
+                "{pad} {GUTTER}|{GUTTER:#} {}{style}{}{style:#}",
+                " ".repeat(from - 1),
+                "^".repeat(to.saturating_sub(from).max(1)),
+            )?;
+        }
+        if last_line - span.start.line >= MAX_SNIPPET_LINES {
+            writeln!(out, "{pad}{GUTTER}...{GUTTER:#}")?;
+        }
+    }
+
+    if let Some(part) = item.part {
+        writeln!(
+            out,
+            "{pad} {GUTTER}={GUTTER:#} part: {} ({})",
+            part.index, part.filename
+        )?;
+    }
+    if !item.instance_path.is_empty() {
+        writeln!(
+            out,
+            "{pad} {GUTTER}={GUTTER:#} path: {}",
+            item.instance_path
+        )?;
+    }
+    if let Some(expected) = item.schema_description {
+        writeln!(out, "{pad} {GUTTER}={GUTTER:#} expected: {}", expected)?;
+    }
+    if let Some(lifecycle) = item.lifecycle {
+        if let Some(deprecated) = &lifecycle.deprecated_version {
+            write!(
+                out,
+                "{pad} {GUTTER}={GUTTER:#} note: deprecated in {}",
+                deprecated
+            )?;
+            if let Some(removal) = &lifecycle.removal_version {
+                write!(out, ", scheduled to be removed in {}", removal)?;
+            }
+            writeln!(out)?;
+        }
+    }
+    for suggestion in item.suggestions {
+        let candidates: Vec<String> = suggestion
+            .candidates
+            .iter()
+            .map(|candidate| format!("`{}`", candidate))
+            .collect();
+        writeln!(
+            out,
+            "{pad} {GUTTER}={GUTTER:#} help: did you mean {} instead of `{}`?",
+            alternatives(&candidates),
+            suggestion.found
+        )?;
+    }
+    if verbose {
+        for error in item.detail {
+            writeln!(
+                out,
+                "{pad} {GUTTER}={GUTTER:#} detail: {}: {}",
+                error.instance_path, error.description
+            )?;
+        }
+    } else if !item.detail.is_empty() {
+        writeln!(
+            out,
+            "{pad} {GUTTER}={GUTTER:#} note: collapsed from {}, see --verbose",
+            plural(item.detail.len(), "error")
+        )?;
+    }
+    writeln!(out)
+}
+
+/// Joins `choices` as `a, b or c`.
+fn alternatives(choices: &[String]) -> String {
+    match choices {
+        [] => String::new(),
+        [choice] => choice.clone(),
+        [rest @ .., last] => format!("{} or {}", rest.join(", "), last),
+    }
+}
+
+/// Last line with content covered by `span`.
+fn last_line(span: &Span, lines: &[&str]) -> usize {
+    // Spans of block nodes end at the beginning of the following line.
+    let line = if span.end.column == 1 && span.end.line > span.start.line {
+        span.end.line - 1
+    } else {
+        span.end.line
+    };
+    line.clamp(span.start.line, lines.len().max(span.start.line))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn render_to_string(payload: &str, validation: &Value) -> String {
+        render_to_string_verbose(payload, validation, false)
+    }
+
+    fn render_to_string_verbose(payload: &str, validation: &Value, verbose: bool) -> String {
+        let validation: Validation = serde_json::from_value(validation.clone()).unwrap();
+        let mut out = anstream::StripStream::new(Vec::new());
+        render(&mut out, "user-data.yaml", payload, &validation, verbose).unwrap();
+        String::from_utf8(out.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn errors_and_deprecations() {
+        let payload = "#cloud-config\nusers:\n  - name: a\n    uid: \"1743\"\nbogus: 1\n";
+        let validation = json!({
+            "annotations": [{
+                "code": "CCV-W-CHANGED",
+                "severity": "warning",
+                "description": "Changed in version 22.3. Use an ``integer`` instead.",
+                "instance_path": "/users/0/uid",
+                "span": {
+                    "start": {"line": 4, "column": 10, "offset": 42},
+                    "end": {"line": 4, "column": 16, "offset": 48}
+                }
+            }],
+            "errors": [{
+                "code": "CCV-E-TYPE",
+                "severity": "error",
+                "description": "1 is not of type \"string\"",
+                "instance_path": "/bogus",
+                "schema_description": "A bogus key.",
+                "span": {
+                    "start": {"line": 5, "column": 8, "offset": 56},
+                    "end": {"line": 5, "column": 9, "offset": 57}
+                }
+            }],
+            "is_valid": false
+        });
+
+        assert_eq!(
+            r#"error[CCV-E-TYPE]: 1 is not of type "string"
+ --> user-data.yaml:5:8
+  |
+5 | bogus: 1
+  |        ^
+  = path: /bogus
+  = expected: A bogus key.
+
+warning[CCV-W-CHANGED]: Changed in version 22.3. Use an ``integer`` instead.
+ --> user-data.yaml:4:10
+  |
+4 |     uid: "1743"
+  |          ^^^^^^
+  = path: /users/0/uid
+
+user-data.yaml: 1 error, 1 deprecation
+"#,
+            render_to_string(payload, &validation)
+        );
+    }
+
+    #[test]
+    fn warnings() {
+        let payload = "#cloud-config\npackages: [curl, curl]\nbogus: 1\n";
+        let annotation = |code: &str, severity: &str, description: &str| {
+            json!({
+                "code": code,
+                "severity": severity,
+                "description": description,
+                "instance_path": "/packages/1",
+                "span": {
+                    "start": {"line": 2, "column": 18, "offset": 31},
+                    "end": {"line": 2, "column": 22, "offset": 35}
+                }
+            })
+        };
+        let validation = json!({
+            "annotations": [
+                annotation("CCV-W-DEPRECATED", "warning", "Deprecated in version 22.2."),
+                annotation("CCV-W-CONFLICT", "warning", "Package \"curl\" is listed twice."),
+                annotation("CCV-W-ORDER", "warning", "Packages are installed in order."),
+                annotation("CCV-I-NEW", "info", "New in version 22.2."),
+            ],
+            "errors": [],
+            "is_valid": true
+        });
+
+        assert!(render_to_string(payload, &validation)
+            .ends_with("user-data.yaml: 0 errors, 1 deprecation, 2 warnings\n"));
+    }
+
+    #[test]
+    fn suggestions() {
+        let payload = "#cloud-config\npower_state:\n  mode: reboto\n";
+        let validation = json!({
+            "annotations": [],
+            "errors": [{
+                "code": "CCV-E-ENUM",
+                "severity": "error",
+                "description": "\"reboto\" is not one of [\"poweroff\",\"reboot\",\"halt\"]",
+                "instance_path": "/power_state/mode",
+                "suggestions": [{"found": "reboto", "candidates": ["reboot", "halt"]}],
+                "span": {
+                    "start": {"line": 3, "column": 9, "offset": 34},
+                    "end": {"line": 3, "column": 15, "offset": 40}
+                }
+            }],
+            "is_valid": false
+        });
+
+        assert_eq!(
+            r#"error[CCV-E-ENUM]: "reboto" is not one of ["poweroff","reboot","halt"]
+ --> user-data.yaml:3:9
+  |
+3 |   mode: reboto
+  |         ^^^^^^
+  = path: /power_state/mode
+  = help: did you mean `reboot` or `halt` instead of `reboto`?
+
+user-data.yaml: 1 error, 0 deprecations
+"#,
+            render_to_string(payload, &validation)
+        );
+    }
+
+    #[test]
+    fn deprecations_as_errors() {
+        let payload = "#cloud-config\napt_update: true\n";
+        let validation = json!({
+            "annotations": [],
+            "errors": [{
+                "code": "CCV-W-DEPRECATED",
+                "severity": "error",
+                "description": "Deprecated in version 22.2. Use ``package_update`` instead.",
+                "instance_path": "/apt_update",
+                "span": {
+                    "start": {"line": 2, "column": 13, "offset": 26},
+                    "end": {"line": 2, "column": 17, "offset": 30}
+                }
+            }],
+            "is_valid": false
+        });
+
+        assert_eq!(
+            r#"error[CCV-W-DEPRECATED]: Deprecated in version 22.2. Use ``package_update`` instead.
+ --> user-data.yaml:2:13
+  |
+2 | apt_update: true
+  |             ^^^^
+  = path: /apt_update
+
+user-data.yaml: 1 error, 0 deprecations
+"#,
+            render_to_string(payload, &validation)
+        );
+    }
+
+    #[test]
+    fn collapsed_errors() {
+        let payload = "#cloud-config\nruncmd: [1]\n";
+        let validation = json!({
+            "annotations": [],
+            "errors": [{
+                "code": "CCV-E-TYPE",
+                "severity": "error",
+                "description": "1 is not of type \"array\" or \"string\"",
+                "instance_path": "/runcmd/0",
+                "detail": [
+                    {
+                        "description": "1 is not of type \"array\"",
+                        "instance_path": "/runcmd/0",
+                        "keyword_location": "/properties/runcmd/items/oneOf/0/type"
+                    },
+                    {
+                        "description": "1 is not of type \"string\"",
+                        "instance_path": "/runcmd/0",
+                        "keyword_location": "/properties/runcmd/items/oneOf/1/type"
+                    }
+                ],
+                "span": {
+                    "start": {"line": 2, "column": 10, "offset": 23},
+                    "end": {"line": 2, "column": 11, "offset": 24}
+                }
+            }],
+            "is_valid": false
+        });
+
+        assert_eq!(
+            r#"error[CCV-E-TYPE]: 1 is not of type "array" or "string"
+ --> user-data.yaml:2:10
+  |
+2 | runcmd: [1]
+  |          ^
+  = path: /runcmd/0
+  = note: collapsed from 2 errors, see --verbose
+
+user-data.yaml: 1 error, 0 deprecations
+"#,
+            render_to_string(payload, &validation)
+        );
+        assert_eq!(
+            r#"error[CCV-E-TYPE]: 1 is not of type "array" or "string"
+ --> user-data.yaml:2:10
+  |
+2 | runcmd: [1]
+  |          ^
+  = path: /runcmd/0
+  = detail: /runcmd/0: 1 is not of type "array"
+  = detail: /runcmd/0: 1 is not of type "string"
+
+user-data.yaml: 1 error, 0 deprecations
+"#,
+            render_to_string_verbose(payload, &validation, true)
+        );
+    }
+
+    #[test]
+    fn multiline_span() {
+        let payload = "#cloud-config\nusers:\n  - a\n  - b\n  - c\n  - d\n";
+        let validation = json!({
+            "annotations": [],
+            "errors": [{
+                "code": "CCV-E-SIZE",
+                "severity": "error",
+                "description": "Too many users",
+                "instance_path": "/users",
+                "span": {
+                    "start": {"line": 3, "column": 3, "offset": 23},
+                    "end": {"line": 7, "column": 1, "offset": 47}
+                }
+            }],
+            "is_valid": false
+        });
+
+        assert_eq!(
+            r#"error[CCV-E-SIZE]: Too many users
+ --> user-data.yaml:3:3
+  |
+3 |   - a
+  |   ^^^
+4 |   - b
+  |   ^^^
+5 |   - c
+  |   ^^^
+ ...
+  = path: /users
+
+user-data.yaml: 1 error, 0 deprecations
+"#,
+            render_to_string(payload, &validation)
+        );
+    }
+
+    #[test]
+    fn multipart() {
+        let payload = "MIME-Version: 1.0\n\n--b\n\nbogus: 1\n--b--\n";
+        let validation = json!({
+            "annotations": [],
+            "errors": [{
+                "code": "CCV-E-ADDITIONAL-PROPERTY",
+                "severity": "error",
+                "description": "Additional properties are not allowed ('bogus' was unexpected)",
+                "instance_path": "",
+                "part": {"index": 0, "filename": "part-000"},
+                "span": {
+                    "start": {"line": 5, "column": 1, "offset": 24},
+                    "end": {"line": 5, "column": 9, "offset": 32}
+                }
+            }],
+            "is_valid": false
+        });
+
+        assert_eq!(
+            r#"error[CCV-E-ADDITIONAL-PROPERTY]: Additional properties are not allowed ('bogus' was unexpected)
+ --> user-data.yaml:5:1
+  |
+5 | bogus: 1
+  | ^^^^^^^^
+  = part: 0 (part-000)
+
+user-data.yaml: 1 error, 0 deprecations
+"#,
+            render_to_string(payload, &validation)
+        );
+    }
+
+    #[test]
+    fn merged() {
+        let payload = "#cloud-config\npackage_update: 1\n";
+        let validation = json!({
+            "annotations": [],
+            "errors": [],
+            "is_valid": false,
+            "merged": {
+                "config": {"package_update": 1},
+                "validation": {
+                    "annotations": [],
+                    "errors": [{
+                        "code": "CCV-E-TYPE",
+                        "severity": "error",
+                        "description": "1 is not of type \"boolean\"",
+                        "instance_path": "/package_update",
+                        "span": {
+                            "start": {"line": 2, "column": 17, "offset": 30},
+                            "end": {"line": 2, "column": 18, "offset": 31}
+                        }
+                    }],
+                    "is_valid": false
+                }
+            }
+        });
+
+        assert_eq!(
+            r#"user-data.yaml: 0 errors, 0 deprecations
+
+merged cloud-config:
+{
+  "package_update": 1
+}
+
+error[CCV-E-TYPE]: 1 is not of type "boolean"
+ --> user-data.yaml:2:17
+  |
+2 | package_update: 1
+  |                 ^
+  = path: /package_update
+
+user-data.yaml (merged): 1 error, 0 deprecations
+"#,
+            render_to_string(payload, &validation)
+        );
+    }
+
+    #[test]
+    fn compatibility() {
+        let payload = "#cloud-config\ndoas: []\n";
+        let validation = json!({
+            "annotations": [],
+            "errors": [],
+            "is_valid": true,
+            "compatibility": {
+                "is_compatible": false,
+                "target": "22.4",
+                "minimum_version": "23.2",
+                "errors": [{
+                    "code": "CCV-E-UNSUPPORTED",
+                    "severity": "error",
+                    "description": "Requires cloud-init 23.2 or later, not 22.4",
+                    "instance_path": "/doas",
+                    "span": {
+                        "start": {"line": 2, "column": 7, "offset": 20},
+                        "end": {"line": 2, "column": 9, "offset": 22}
+                    }
+                }],
+                "annotations": []
+            }
+        });
+
+        assert_eq!(
+            r#"user-data.yaml: 0 errors, 0 deprecations
+
+error[CCV-E-UNSUPPORTED]: Requires cloud-init 23.2 or later, not 22.4
+ --> user-data.yaml:2:7
+  |
+2 | doas: []
+  |       ^^
+  = path: /doas
+
+user-data.yaml on cloud-init 22.4: 1 error, 0 changes, requires cloud-init 23.2 or later
+"#,
+            render_to_string(payload, &validation)
+        );
+    }
+
+    #[test]
+    fn valid() {
+        let validation = json!({"annotations": [], "errors": [], "is_valid": true});
+        assert_eq!(
+            "user-data.yaml: 0 errors, 0 deprecations\n",
+            render_to_string("#cloud-config\n", &validation)
+        );
+    }
+}
@@ -1,4 +1,3 @@
-use assert_cmd::prelude::*;
 use assert_cmd::Command;
 use assert_fs::prelude::*;
 use predicates::prelude::*;
@@ -33,11 +32,12 @@ network:
   config:
     - type: bond
       name: a
-      mac_address: aa:bb
-      mtu: 1
+      mac_address: aa:bb:cc:dd:ee:ff
+      mtu: 1500
       subnets:
-        - type: dhcp6
+        - type: static
           control: manual
+          address: 10.0.0.2
           netmask: 255.255.255.0
           gateway: 10.0.0.1
           dns_nameservers:
@@ -46,14 +46,15 @@ network:
             - find.me
           routes:
             - type: route
-              destination: 10.20.0.0/8
-              gateway: a.b.c.d
+              destination: 10.0.0.0/8
+              gateway: 10.0.0.1
               metric: 200"#,
     )?;
 
     let mut cmd = Command::cargo_bin("ccv-cli")?;
     cmd.arg("validate")
         .args(["--kind", "networkconfig"])
+        .args(["--schema", "vendored"])
         .arg(file.path());
     cmd.assert().success().stdout(predicate::str::contains(
         r#"{"annotations":[],"errors":[],"is_valid":true}"#,
@@ -70,11 +71,12 @@ network:
   config:
     - type: bond
       name: a
-      mac_address: aa:bb
-      mtu: 1
+      mac_address: aa:bb:cc:dd:ee:ff
+      mtu: 1500
       subnets:
-        - type: dhcp6
+        - type: static
           control: manual
+          address: 10.0.0.2
           netmask: 255.255.255.0
           gateway: 10.0.0.1
           dns_nameservers:
@@ -83,13 +85,14 @@ network:
             - find.me
           routes:
             - type: route
-              destination: 10.20.0.0/8
-              gateway: a.b.c.d
+              destination: 10.0.0.0/8
+              gateway: 10.0.0.1
               metric: 200"#;
 
     let mut cmd = Command::cargo_bin("ccv-cli")?;
     cmd.arg("validate")
         .args(["--kind", "networkconfig"])
+        .args(["--schema", "vendored"])
         .args(["-"]);
     cmd.write_stdin(content);
     cmd.assert().success().stdout(predicate::str::contains(
@@ -98,3 +101,534 @@ network:
 
     Ok(())
 }
+
+#[test]
+fn network_config_checks() -> Result<(), Box<dyn std::error::Error>> {
+    let content = r#"network:
+  version: 1
+  config:
+    - type: bond
+      name: a
+      mac_address: aa:bb
+      subnets:
+        - type: dhcp6
+          gateway: 10.0.0.1
+          routes:
+            - destination: 10.20.0.0/8
+              gateway: a.b.c.d
+"#;
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--kind", "networkconfig"])
+        .args(["--schema", "vendored"])
+        .args(["--format", "human"])
+        .arg("-");
+    cmd.write_stdin(content);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "error[CCV-E-MALFORMED]: MAC address \"aa:bb\" is not 6 bytes",
+        ))
+        .stdout(predicate::str::contains("<stdin>:6:20"))
+        .stdout(predicate::str::contains(
+            "error[CCV-E-MALFORMED]: Destination \"10.20.0.0/8\" has host bits set",
+        ))
+        .stdout(predicate::str::contains(
+            "error[CCV-E-MALFORMED]: Gateway \"a.b.c.d\" is not an IPv4 nor an IPv6 address",
+        ))
+        .stdout(predicate::str::contains(
+            "warning[CCV-W-ADDRESS-FAMILY]: Gateway \"10.0.0.1\" is an IPv4 address",
+        ))
+        .stdout(predicate::str::contains("<stdin>:9:20"));
+
+    Ok(())
+}
+
+#[test]
+fn schema_file() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = assert_fs::NamedTempFile::new("schema.json")?;
+    schema.write_str(r#"{"properties": {"a": {"type": "integer"}}}"#)?;
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--format", "json"])
+        .arg("--schema")
+        .arg(schema.path())
+        .arg("-");
+    cmd.write_stdin("#cloud-config\na: b\n");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(r#""instance_path":"/a""#))
+        .stdout(predicate::str::contains(r#""is_valid":false"#));
+
+    Ok(())
+}
+
+#[test]
+fn schema_file_doesnt_exist() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "test/schema/doesnt/exist.json"])
+        .arg("-");
+    cmd.write_stdin("#cloud-config\n");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "cannot read test/schema/doesnt/exist.json",
+    ));
+
+    Ok(())
+}
+
+#[cfg(feature = "release-23-1")]
+#[test]
+fn bundled_release() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--format", "json"])
+        .args(["--schema", "vendored:23.1"])
+        .arg("-");
+    cmd.write_stdin("#cloud-config\nssh_pwauth: true\n");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(r#""is_valid":true"#));
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored:23.1"])
+        .args(["--kind", "networkconfig"])
+        .arg("-");
+    cmd.write_stdin("network:\n  version: 2\n  ethernets: {}\n");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "cloud-init 23.1 ships no networkconfigv2 schema",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn unknown_release() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored:9.1"])
+        .arg("-");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "no bundled schemas for cloud-init 9.1",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn vendored_fallback() -> Result<(), Box<dyn std::error::Error>> {
+    let cache_dir = assert_fs::TempDir::new()?;
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--format", "json"])
+        .args(["--schema", "http://127.0.0.1:9/schema.json"])
+        .arg("--cache-dir")
+        .arg(cache_dir.path())
+        .arg("-");
+    cmd.write_stdin("#cloud-config\nruncmd: [ls]\n");
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Warning: cannot fetch the JsonSchema, using the vendored copy",
+        ))
+        .stdout(predicate::str::contains(r#""is_valid":true"#));
+
+    Ok(())
+}
+
+#[test]
+fn schema_cache_empty() -> Result<(), Box<dyn std::error::Error>> {
+    let cache_dir = assert_fs::TempDir::new()?;
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.args(["schema", "cache"])
+        .env("CCV_CACHE_DIR", cache_dir.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Cache directory: {}\nTTL: 1h\nNo cached JSON Schemas\n",
+            cache_dir.path().display()
+        )));
+
+    Ok(())
+}
+
+#[test]
+fn schema_resolve() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("schema-network-config-v1.json").write_str(
+        r#"{"properties": {"network": {"$ref": "https://raw.githubusercontent.com/canonical/cloud-init/23.4/cloudinit/config/schemas/network.json"}}}"#,
+    )?;
+    dir.child("network.json")
+        .write_str(r#"{"type": "object"}"#)?;
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.args(["schema", "resolve", "--kind", "networkconfig", "--schema"])
+        .arg(dir.path());
+    cmd.assert().success().stdout(predicate::str::contains(
+        "\"network\": {\n      \"type\": \"object\"\n    }",
+    ));
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.args(["schema", "resolve", "--kind", "networkconfigv2", "--schema"])
+        .arg(dir.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("schema-network-config-v2.json"));
+
+    Ok(())
+}
+
+#[test]
+fn network_config_v2() -> Result<(), Box<dyn std::error::Error>> {
+    let content =
+        "version: 2\nethernets:\n  eth0:\n    match:\n      macaddress: aa:bb\n    dhcp4: true\n";
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--kind", "networkconfig"])
+        .args(["--schema", "vendored"])
+        .args(["--format", "json"])
+        .arg("-");
+    cmd.write_stdin(content);
+    cmd.assert().success().stdout(predicate::str::contains(
+        r#"{"annotations":[],"errors":[],"is_valid":true}"#,
+    ));
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--kind", "networkconfig"])
+        .args(["--network-version", "1"])
+        .args(["--schema", "vendored"])
+        .args(["--format", "json"])
+        .arg("-");
+    cmd.write_stdin(content);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(r#""is_valid":false"#));
+
+    Ok(())
+}
+
+#[test]
+fn shell_script_with_crlf() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored"])
+        .args(["--format", "json"])
+        .arg("-");
+    cmd.write_stdin("#!/bin/bash\r\necho hi\r\n");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            r#""content_type":"text/x-shellscript""#,
+        ))
+        .stdout(predicate::str::contains("carriage return"));
+
+    Ok(())
+}
+
+#[test]
+fn multipart_user_data() -> Result<(), Box<dyn std::error::Error>> {
+    let content = "Content-Type: multipart/mixed; boundary=\"b\"\n\
+MIME-Version: 1.0\n\
+\n\
+--b\n\
+Content-Type: text/cloud-config\n\
+Merge-Type: list(append)\n\
+\n\
+runcmd: [ls]\n\
+--b\n\
+Content-Type: text/x-shellscript\n\
+Content-Disposition: attachment; filename=\"setup.sh\"\n\
+\n\
+#!/bin/sh\n\
+echo hi\n\
+--b--\n";
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored"])
+        .args(["--format", "json"])
+        .arg("-");
+    cmd.write_stdin(content);
+    cmd.assert().success().stdout(predicate::str::contains(
+        r#""content_type":"multipart/mixed","errors":[],"is_valid":true,"parts":[{"content_type":"text/cloud-config","filename":"part-000","index":0},{"content_type":"text/x-shellscript","filename":"setup.sh","index":1}]"#,
+    ));
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored"])
+        .args(["--format", "human"])
+        .arg("-");
+    cmd.write_stdin(content.replace("list(append)", "list(append"));
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "error[CCV-E-MIME]: Invalid Merge-Type header: \"list(append\" is not a merger",
+        ))
+        .stdout(predicate::str::contains("<stdin>:6:1"))
+        .stdout(predicate::str::contains("= part: 0 (part-000)"));
+
+    Ok(())
+}
+
+#[test]
+fn merged_with_vendor_data() -> Result<(), Box<dyn std::error::Error>> {
+    let vendor_data = assert_fs::NamedTempFile::new("vendor-data.yaml")?;
+    vendor_data.write_str("#cloud-config\nruncmd: [pwd]\npackage_update: 1\n")?;
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored"])
+        .args(["--format", "json"])
+        .arg("--vendor-data")
+        .arg(vendor_data.path())
+        .arg("-");
+    cmd.write_stdin("#cloud-config\nruncmd: [ls]\n");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            r#""merged":{"config":{"package_update":1,"runcmd":["ls"]},"#,
+        ))
+        .stdout(predicate::str::contains(
+            r#""instance_path":"/package_update""#,
+        ));
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored"])
+        .args(["--format", "human"])
+        .arg("--merged")
+        .arg("-");
+    cmd.write_stdin("#cloud-config\nruncmd: [ls]\n");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("merged cloud-config:"))
+        .stdout(predicate::str::contains(
+            "<stdin> (merged): 0 errors, 0 deprecations",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn templated_user_data() -> Result<(), Box<dyn std::error::Error>> {
+    let instance_data = assert_fs::NamedTempFile::new("instance-data.json")?;
+    instance_data.write_str(r#"{"v1": {"region": 1}}"#)?;
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored"])
+        .args(["--format", "human"])
+        .arg("--instance-data")
+        .arg(instance_data.path())
+        .arg("-");
+    cmd.write_stdin(
+        "## template: jinja\n#cloud-config\npackage_update: {{ v1.region }}\nfqdn: {{ fqdn }}\n",
+    );
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "error[CCV-E-TYPE]: 1 is not of type \"boolean\"",
+        ))
+        .stdout(predicate::str::contains("<stdin>:3:1"))
+        .stdout(predicate::str::contains(
+            "warning[CCV-W-TEMPLATE-VARIABLE]: Could not render jinja template variable 'fqdn'",
+        ))
+        .stdout(predicate::str::contains("<stdin>:4:10"));
+
+    Ok(())
+}
+
+#[test]
+fn yaml_versions() -> Result<(), Box<dyn std::error::Error>> {
+    let payload = "#cloud-config\npackage_update: yes\n";
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored"])
+        .args(["--format", "human"])
+        .arg("-");
+    cmd.write_stdin(payload);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "warning[CCV-W-YAML-TYPE]: \"yes\" is the boolean true in YAML 1.1",
+        ))
+        .stdout(predicate::str::contains("<stdin>:2:17"));
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored"])
+        .args(["--format", "human"])
+        .args(["--yaml-version", "1.2"])
+        .arg("-");
+    cmd.write_stdin(payload);
+    cmd.assert().failure().stdout(predicate::str::contains(
+        "error[CCV-E-TYPE]: \"yes\" is not of type \"boolean\"",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn suggestions() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored"])
+        .args(["--format", "human"])
+        .arg("-");
+    cmd.write_stdin("#cloud-config\npower_state:\n  mode: reboto\n");
+    cmd.assert().failure().stdout(predicate::str::contains(
+        "= help: did you mean `reboot` instead of `reboto`?",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn collapsed_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let payload = "#cloud-config\nruncmd: [1]\n";
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored"])
+        .args(["--format", "human"])
+        .arg("-");
+    cmd.write_stdin(payload);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "error[CCV-E-TYPE]: 1 is not of type \"array\", \"string\" or \"null\"",
+        ))
+        .stdout(predicate::str::contains(
+            "= note: collapsed from 3 errors, see --verbose",
+        ));
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored"])
+        .args(["--format", "human"])
+        .arg("--verbose")
+        .arg("-");
+    cmd.write_stdin(payload);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "= detail: /runcmd/0: 1 is not of type \"array\"",
+        ))
+        .stdout(predicate::str::contains("see --verbose").not());
+
+    Ok(())
+}
+
+#[test]
+fn deprecation_policies() -> Result<(), Box<dyn std::error::Error>> {
+    let payload = "#cloud-config\napt_update: 1\n";
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored"])
+        .args(["--format", "human"])
+        .arg("-");
+    cmd.write_stdin(payload);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "warning[CCV-W-DEPRECATED]: Deprecated in version 22.2. Use ``package_update`` instead.",
+        ))
+        .stdout(predicate::str::contains(
+            "= note: deprecated in 22.2, scheduled to be removed in 27.2",
+        ))
+        .stdout(predicate::str::contains("<stdin>: 1 error, 1 deprecation"));
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored"])
+        .args(["--format", "human"])
+        .args(["--deprecations", "error"])
+        .arg("-");
+    cmd.write_stdin("#cloud-config\napt_update: true\n");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("error[CCV-W-DEPRECATED]"))
+        .stdout(predicate::str::contains("<stdin>: 1 error, 0 deprecations"));
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored"])
+        .args(["--format", "human"])
+        .args(["--deprecations", "ignore"])
+        .arg("-");
+    cmd.write_stdin(payload);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("<stdin>: 1 error, 0 deprecations"));
+
+    Ok(())
+}
+
+#[test]
+fn target_cloud_init() -> Result<(), Box<dyn std::error::Error>> {
+    let payload = "#cloud-config\napt_update: true\nusers:\n  - name: a\n    uid: '1'\n";
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored"])
+        .args(["--format", "human"])
+        .args(["--target-cloud-init", "22.2"])
+        .arg("-");
+    cmd.write_stdin(payload);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "warning[CCV-W-CHANGED]: Changed in cloud-init 22.3, after 22.2",
+        ))
+        .stdout(predicate::str::contains(
+            "<stdin> on cloud-init 22.2: 0 errors, 1 change",
+        ));
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored"])
+        .args(["--target-cloud-init", "28.1"])
+        .arg("-");
+    cmd.write_stdin(payload);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("\"is_valid\":true"))
+        .stdout(predicate::str::contains("\"is_compatible\":false"))
+        .stdout(predicate::str::contains("\"code\":\"CCV-E-REMOVED\""));
+
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--target-cloud-init", "latest"])
+        .arg("-");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "Not a valid cloud-init version: latest",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn template_without_instance_data() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ccv-cli")?;
+    cmd.arg("validate")
+        .args(["--schema", "vendored"])
+        .args(["--format", "human"])
+        .arg("-");
+    cmd.write_stdin("## template: jinja\n#cloud-config\nhostname: {{ v1.hostname }}\nruncmd: [\n");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "error[CCV-E-TEMPLATE]: Template cannot render to valid YAML whatever its variables",
+        ))
+        .stdout(predicate::str::contains(
+            "warning[CCV-W-TEMPLATE-VARIABLE]: 'v1.hostname' is not an instance-data variable",
+        ))
+        .stdout(predicate::str::contains("<stdin>:3:14"));
+
+    Ok(())
+}
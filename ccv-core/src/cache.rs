@@ -0,0 +1,523 @@
+//! Persistent cache of the resolved schemas fetched over HTTP.
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use reqwest::{header, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+use crate::resolver::Origins;
+use crate::schema::{ConfigKind, Schema, SchemaSource, VendoredSource};
+
+/// A schema document fetched over HTTP, with the validators used to revalidate it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Document {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// The resolved schema of a [`ConfigKind`] fetched from `url`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub kind: String,
+    pub url: String,
+    pub fetched_at: SystemTime,
+    /// Every document the schema was resolved from, starting with the root one.
+    pub documents: Vec<Document>,
+    schema: Value,
+    /// Entries cached before the origins were recorded have none.
+    #[serde(default)]
+    origins: Origins,
+}
+
+impl CacheEntry {
+    /// Time since the entry was last fetched or revalidated.
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.fetched_at)
+            .unwrap_or_default()
+    }
+}
+
+/// Where a schema loaded through a [`SchemaCache`] comes from.
+#[derive(Debug)]
+pub enum CacheStatus {
+    /// The source does not fetch the schema over HTTP, so it is not cached.
+    Bypassed,
+    /// The cached schema is younger than the TTL.
+    Fresh,
+    /// The cached schema is older than the TTL, but the server reported it unchanged.
+    Revalidated,
+    /// The schema has been downloaded and cached.
+    Downloaded,
+    /// The schema could not be fetched, the last cached copy is used.
+    Stale { age: Duration, error: Error },
+    /// The schema could not be fetched nor was it cached, the vendored copy is used.
+    Vendored { error: Error },
+}
+
+impl CacheStatus {
+    /// Warning to show to the user when the schema could not be fetched.
+    pub fn warning(&self) -> Option<String> {
+        match self {
+            Self::Stale { age, error } => Some(format!(
+                "cannot fetch the JsonSchema, using the copy cached {} ago: {}",
+                format_age(*age),
+                error
+            )),
+            Self::Vendored { error } => Some(format!(
+                "cannot fetch the JsonSchema, using the vendored copy: {}",
+                error
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Formats `age` in its largest whole unit, e.g. `3h`.
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+/// On-disk cache of resolved schemas, keyed by [`ConfigKind`] and source URL.
+///
+/// Entries older than the TTL are revalidated with `If-None-Match` / `If-Modified-Since`
+/// requests for every document they were resolved from. If the schema cannot be fetched, the
+/// last cached copy is used and, failing that, the vendored one.
+#[derive(Debug, Clone)]
+pub struct SchemaCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl Default for SchemaCache {
+    fn default() -> Self {
+        Self::new(Self::default_dir())
+    }
+}
+
+impl SchemaCache {
+    pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl: Self::DEFAULT_TTL,
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// `ccv` within the user's cache directory, e.g. `$XDG_CACHE_HOME/ccv` on Linux.
+    pub fn default_dir() -> PathBuf {
+        dirs::cache_dir().unwrap_or_else(env::temp_dir).join("ccv")
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Cached entries, skipping the ones that cannot be read.
+    pub fn entries(&self) -> Result<Vec<CacheEntry>> {
+        let read_dir = match fs::read_dir(&self.dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(source) => {
+                return Err(Error::ReadSchema {
+                    path: self.dir.clone(),
+                    source,
+                })
+            }
+        };
+        let mut entries: Vec<CacheEntry> = read_dir
+            .filter_map(|dir_entry| read_entry(&dir_entry.ok()?.path()))
+            .collect();
+        entries.sort_by(|a, b| (&a.kind, &a.url).cmp(&(&b.kind, &b.url)));
+        Ok(entries)
+    }
+
+    /// Loads the schema of `kind` from `source`, through the cache if it is fetched over HTTP.
+    pub async fn load(
+        &self,
+        source: &dyn SchemaSource,
+        kind: ConfigKind,
+    ) -> Result<(Schema, CacheStatus)> {
+        self.load_with(source, kind, false).await
+    }
+
+    /// Like [`SchemaCache::load`], but revalidates the cached schema even if it is fresh.
+    pub async fn refresh(
+        &self,
+        source: &dyn SchemaSource,
+        kind: ConfigKind,
+    ) -> Result<(Schema, CacheStatus)> {
+        self.load_with(source, kind, true).await
+    }
+
+    async fn load_with(
+        &self,
+        source: &dyn SchemaSource,
+        kind: ConfigKind,
+        force: bool,
+    ) -> Result<(Schema, CacheStatus)> {
+        let Some(url) = source.url(&kind) else {
+            let schema = Schema::from_source(source, kind).await?;
+            return Ok((schema, CacheStatus::Bypassed));
+        };
+        let path = self.entry_path(&kind, &url);
+        let cached = read_entry(&path);
+
+        let fetched = match &cached {
+            Some(entry) if !force && entry.age() < self.ttl => {
+                let schema =
+                    Schema::from_resolved(entry.schema.clone(), kind, entry.origins.clone());
+                return Ok((schema, CacheStatus::Fresh));
+            }
+            Some(entry) => match revalidate(entry).await {
+                Ok(true) => Ok(None),
+                Ok(false) => download(&url, &kind).await.map(Some),
+                Err(e) => Err(e),
+            },
+            None => download(&url, &kind).await.map(Some),
+        };
+
+        match (fetched, cached) {
+            (Ok(Some(entry)), _) => {
+                store(&path, &entry);
+                Ok((
+                    Schema::from_resolved(entry.schema, kind, entry.origins),
+                    CacheStatus::Downloaded,
+                ))
+            }
+            (Ok(None), Some(mut entry)) => {
+                entry.fetched_at = SystemTime::now();
+                store(&path, &entry);
+                Ok((
+                    Schema::from_resolved(entry.schema, kind, entry.origins),
+                    CacheStatus::Revalidated,
+                ))
+            }
+            (Err(error), Some(entry)) => Ok((
+                Schema::from_resolved(entry.schema.clone(), kind, entry.origins.clone()),
+                CacheStatus::Stale {
+                    age: entry.age(),
+                    error,
+                },
+            )),
+            (Err(error), None) => {
+                let schema = Schema::from_source(&VendoredSource, kind).await?;
+                Ok((schema, CacheStatus::Vendored { error }))
+            }
+            (Ok(None), None) => unreachable!("only cached entries are revalidated"),
+        }
+    }
+
+    fn entry_path(&self, kind: &ConfigKind, url: &Url) -> PathBuf {
+        let url: String = url
+            .as_str()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.dir.join(format!("{}-{}.json", kind, url))
+    }
+}
+
+fn read_entry(path: &Path) -> Option<CacheEntry> {
+    let content = fs::read(path).ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+/// Writes `entry` atomically. Failures are ignored: a read-only cache must not prevent
+/// loading the schema.
+fn store(path: &Path, entry: &CacheEntry) {
+    let tmp_path = path.with_extension("json.tmp");
+    let _ = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&tmp_path, serde_json::to_vec(entry)?))
+        .and_then(|_| fs::rename(&tmp_path, path));
+}
+
+/// Whether none of the documents of `entry` changed on the server.
+async fn revalidate(entry: &CacheEntry) -> Result<bool> {
+    let client = reqwest::Client::new();
+    for document in &entry.documents {
+        let mut request = client.get(&document.url);
+        if let Some(etag) = &document.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &document.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+        let response = request.send().await?;
+        if response.status() != StatusCode::NOT_MODIFIED {
+            response.error_for_status()?;
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+async fn download(url: &Url, kind: &ConfigKind) -> Result<CacheEntry> {
+    let source = RecordingSource {
+        url: url.clone(),
+        client: reqwest::Client::new(),
+        documents: Mutex::new(vec![]),
+    };
+    let schema = Schema::from_source(&source, kind.clone()).await?;
+    Ok(CacheEntry {
+        kind: kind.to_string(),
+        url: url.to_string(),
+        fetched_at: SystemTime::now(),
+        documents: source.documents.into_inner().expect("not poisoned"),
+        schema: schema.schema().clone(),
+        origins: schema.origins().clone(),
+    })
+}
+
+/// Fetches the documents of a schema, recording their validators.
+#[derive(Debug)]
+struct RecordingSource {
+    url: Url,
+    client: reqwest::Client,
+    documents: Mutex<Vec<Document>>,
+}
+
+impl RecordingSource {
+    async fn get(&self, url: &Url) -> Result<Value> {
+        let response = self
+            .client
+            .get(url.clone())
+            .send()
+            .await?
+            .error_for_status()?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned)
+        };
+        let document = Document {
+            url: url.to_string(),
+            etag: header(header::ETAG),
+            last_modified: header(header::LAST_MODIFIED),
+        };
+        self.documents.lock().expect("not poisoned").push(document);
+        Ok(response.json().await?)
+    }
+}
+
+#[async_trait]
+impl SchemaSource for RecordingSource {
+    async fn load(&self, _kind: &ConfigKind) -> Result<Value> {
+        self.get(&self.url).await
+    }
+
+    async fn load_ref(&self, url: &Url) -> Result<Value> {
+        self.get(url).await
+    }
+
+    fn url(&self, _kind: &ConfigKind) -> Option<Url> {
+        Some(self.url.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::schema::UrlSource;
+    use serde_json::json;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+
+    struct Server {
+        url: Url,
+        requests: Arc<AtomicUsize>,
+        handle: JoinHandle<()>,
+    }
+
+    /// Serves a root schema referencing another document, both with a constant ETag, and
+    /// answers `304 Not Modified` to requests carrying it.
+    async fn serve() -> Server {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url: Url = format!("http://{}/root.json", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let documents = [
+            (
+                "/root.json",
+                json!({"oneOf": [{"$ref": url.join("ref.json").unwrap().as_str()}]}),
+            ),
+            ("/ref.json", json!({"type": "object"})),
+        ];
+        let requests = Arc::new(AtomicUsize::new(0));
+        let handle = tokio::spawn({
+            let requests = requests.clone();
+            async move {
+                loop {
+                    let (mut stream, _) = listener.accept().await.unwrap();
+                    requests.fetch_add(1, Ordering::SeqCst);
+                    let mut request = vec![];
+                    let mut buffer = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        let n = stream.read(&mut buffer).await.unwrap();
+                        request.extend_from_slice(&buffer[..n]);
+                    }
+                    let request = String::from_utf8(request).unwrap().to_lowercase();
+                    let path = request.split(' ').nth(1).unwrap();
+                    let (status, body) = match documents.iter().find(|(p, _)| *p == path) {
+                        None => ("404 Not Found", String::new()),
+                        Some(_) if request.contains("if-none-match: \"v1\"") => {
+                            ("304 Not Modified", String::new())
+                        }
+                        Some((_, document)) => ("200 OK", document.to_string()),
+                    };
+                    let response = format!(
+                        "HTTP/1.1 {}\r\netag: \"v1\"\r\ncontent-length: {}\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    );
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                }
+            }
+        });
+        Server {
+            url,
+            requests,
+            handle,
+        }
+    }
+
+    fn requests(server: &Server) -> usize {
+        server.requests.load(Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn download_then_fresh() {
+        let server = serve().await;
+        let dir = tempfile::tempdir().unwrap();
+        let cache = SchemaCache::new(dir.path());
+        let source = UrlSource::new(server.url.clone());
+
+        let (schema, status) = cache.load(&source, ConfigKind::CloudConfig).await.unwrap();
+        assert!(matches!(status, CacheStatus::Downloaded));
+        assert_eq!(&json!({"oneOf": [{"type": "object"}]}), schema.schema());
+        assert_eq!(2, requests(&server));
+
+        let (schema, status) = cache.load(&source, ConfigKind::CloudConfig).await.unwrap();
+        assert!(matches!(status, CacheStatus::Fresh));
+        assert_eq!(&json!({"oneOf": [{"type": "object"}]}), schema.schema());
+        assert_eq!(2, requests(&server));
+
+        let entries = cache.entries().unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!("cloudconfig", entries[0].kind);
+        assert_eq!(server.url.as_str(), entries[0].url);
+        assert_eq!(2, entries[0].documents.len());
+        assert_eq!(Some("\"v1\""), entries[0].documents[0].etag.as_deref());
+    }
+
+    #[tokio::test]
+    async fn revalidate_expired_entries() {
+        let server = serve().await;
+        let dir = tempfile::tempdir().unwrap();
+        let cache = SchemaCache::new(dir.path()).with_ttl(Duration::ZERO);
+        let source = UrlSource::new(server.url.clone());
+
+        cache.load(&source, ConfigKind::CloudConfig).await.unwrap();
+        let (schema, status) = cache.load(&source, ConfigKind::CloudConfig).await.unwrap();
+        assert!(matches!(status, CacheStatus::Revalidated));
+        assert_eq!(&json!({"oneOf": [{"type": "object"}]}), schema.schema());
+        assert_eq!(4, requests(&server));
+
+        let cache = SchemaCache::new(dir.path());
+        let (_, status) = cache
+            .refresh(&source, ConfigKind::CloudConfig)
+            .await
+            .unwrap();
+        assert!(matches!(status, CacheStatus::Revalidated));
+        assert_eq!(6, requests(&server));
+    }
+
+    #[tokio::test]
+    async fn offline_fallback() {
+        let server = serve().await;
+        let dir = tempfile::tempdir().unwrap();
+        let cache = SchemaCache::new(dir.path()).with_ttl(Duration::ZERO);
+        let source = UrlSource::new(server.url.clone());
+        cache.load(&source, ConfigKind::CloudConfig).await.unwrap();
+
+        server.handle.abort();
+        let _ = server.handle.await;
+
+        let (schema, status) = cache.load(&source, ConfigKind::CloudConfig).await.unwrap();
+        assert!(matches!(status, CacheStatus::Stale { .. }));
+        assert!(status
+            .warning()
+            .unwrap()
+            .contains("using the copy cached 0s ago"));
+        assert_eq!(&json!({"oneOf": [{"type": "object"}]}), schema.schema());
+
+        let empty_dir = tempfile::tempdir().unwrap();
+        let (schema, status) = SchemaCache::new(empty_dir.path())
+            .load(&source, ConfigKind::NetworkConfig)
+            .await
+            .unwrap();
+        assert!(matches!(status, CacheStatus::Vendored { .. }));
+        assert!(matches!(schema.kind(), ConfigKind::NetworkConfig));
+    }
+
+    #[tokio::test]
+    async fn bypass_local_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, status) = SchemaCache::new(dir.path())
+            .load(&VendoredSource, ConfigKind::CloudConfig)
+            .await
+            .unwrap();
+        assert!(matches!(status, CacheStatus::Bypassed));
+        assert!(SchemaCache::new(dir.path()).entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn format_ages() {
+        assert_eq!("59s", format_age(Duration::from_secs(59)));
+        assert_eq!("2m", format_age(Duration::from_secs(150)));
+        assert_eq!("1h", format_age(Duration::from_secs(3600)));
+        assert_eq!("3d", format_age(Duration::from_secs(3 * 86400)));
+    }
+}
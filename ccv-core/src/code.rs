@@ -0,0 +1,366 @@
+//! Stable, machine-readable codes of the errors and annotations of a validation.
+//!
+//! Codes never change once released: tooling may filter and count problems by them, while their
+//! descriptions are free text which may be reworded at any time.
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// How serious a problem is.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Cloud-init fails on the configuration or ignores it, in part or in whole.
+    Error,
+    /// Cloud-init accepts the configuration, but it is deprecated or may not mean what it seems.
+    Warning,
+    /// Cloud-init accepts the configuration, as recent releases of it do.
+    Info,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+            Self::Info => write!(f, "info"),
+        }
+    }
+}
+
+/// The class of an error or annotation.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Code {
+    /// A value is not of the type the schema requires.
+    Type,
+    /// A mapping has a key the schema does not allow.
+    AdditionalProperty,
+    /// A mapping misses a key the schema requires.
+    Required,
+    /// A value is not one of the values the schema allows.
+    Enum,
+    /// A string does not have the format the schema requires.
+    Format,
+    /// A string does not match the pattern the schema requires.
+    Pattern,
+    /// A number is out of the range the schema, or the system it configures, allows.
+    Range,
+    /// A string, list or mapping is too short or too long.
+    Size,
+    /// A list has duplicate items.
+    UniqueItems,
+    /// A key requires others which are missing.
+    Dependency,
+    /// A value is valid under more than one of the `oneOf` alternatives.
+    OneOf,
+    /// A value is valid under a schema it must not be valid under.
+    Not,
+    /// Any other schema keyword failed.
+    Schema,
+    /// A payload cannot be parsed.
+    Syntax,
+    /// A cloud-config does not begin with `#cloud-config`.
+    Header,
+    /// A user-data starts with a byte order mark, which prevents detecting its type.
+    Bom,
+    /// A MIME part cannot be decoded or is of a type cloud-init ignores.
+    Mime,
+    /// A shell script cannot be executed.
+    Script,
+    /// An `#include` line is neither a URL nor a path.
+    Include,
+    /// A part handler misses the functions cloud-init calls.
+    PartHandler,
+    /// A `#cloud-config-archive` is malformed.
+    Archive,
+    /// A `#cloud-config-jsonp` patch is malformed.
+    JsonPatch,
+    /// A cloud-config part cannot be merged.
+    Merge,
+    /// A jinja template cannot be rendered, or renders to a payload cloud-init ignores.
+    Template,
+    /// A key or value is newer than the target cloud-init version.
+    Unsupported,
+    /// A key or value was removed by the target cloud-init version.
+    Removed,
+    /// A value is not in the syntax cloud-init, or the tool it passes the value to, requires.
+    Malformed,
+    /// A key or value is deprecated.
+    Deprecated,
+    /// The meaning of a key or value changed in a cloud-init version.
+    Changed,
+    /// A scalar is typed differently in YAML 1.1, which cloud-init uses, and YAML 1.2.
+    YamlType,
+    /// A mapping has a key more than once.
+    DuplicateKey,
+    /// A jinja template variable is not in the instance-data.
+    TemplateVariable,
+    /// An address is of another IP version than the subnet or network it belongs to.
+    AddressFamily,
+    /// A value undoes or overrides another one of the configuration.
+    Conflict,
+    /// Part of a value is discarded by cloud-init.
+    Discarded,
+    /// A credential or setting is insecure, or refused by recent versions of the tools using it.
+    Insecure,
+    /// Items run or apply in an order other than the one they seem to be in.
+    Order,
+    /// A value refers to something the configuration does not declare, and which is not standard.
+    Reference,
+    /// A path is relative, to a working directory which depends on how cloud-init runs.
+    RelativePath,
+    /// A gateway is outside of the subnet of its interface.
+    Unreachable,
+    /// A key or value was added in a cloud-init version.
+    New,
+}
+
+impl Code {
+    /// Every code, errors first, then warnings and informational codes.
+    pub const ALL: [Self; 41] = [
+        Self::Type,
+        Self::AdditionalProperty,
+        Self::Required,
+        Self::Enum,
+        Self::Format,
+        Self::Pattern,
+        Self::Range,
+        Self::Size,
+        Self::UniqueItems,
+        Self::Dependency,
+        Self::OneOf,
+        Self::Not,
+        Self::Schema,
+        Self::Syntax,
+        Self::Header,
+        Self::Bom,
+        Self::Mime,
+        Self::Script,
+        Self::Include,
+        Self::PartHandler,
+        Self::Archive,
+        Self::JsonPatch,
+        Self::Merge,
+        Self::Template,
+        Self::Unsupported,
+        Self::Removed,
+        Self::Malformed,
+        Self::Deprecated,
+        Self::Changed,
+        Self::YamlType,
+        Self::DuplicateKey,
+        Self::TemplateVariable,
+        Self::AddressFamily,
+        Self::Conflict,
+        Self::Discarded,
+        Self::Insecure,
+        Self::Order,
+        Self::Reference,
+        Self::RelativePath,
+        Self::Unreachable,
+        Self::New,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Type => "CCV-E-TYPE",
+            Self::AdditionalProperty => "CCV-E-ADDITIONAL-PROPERTY",
+            Self::Required => "CCV-E-REQUIRED",
+            Self::Enum => "CCV-E-ENUM",
+            Self::Format => "CCV-E-FORMAT",
+            Self::Pattern => "CCV-E-PATTERN",
+            Self::Range => "CCV-E-RANGE",
+            Self::Size => "CCV-E-SIZE",
+            Self::UniqueItems => "CCV-E-UNIQUE-ITEMS",
+            Self::Dependency => "CCV-E-DEPENDENCY",
+            Self::OneOf => "CCV-E-ONE-OF",
+            Self::Not => "CCV-E-NOT",
+            Self::Schema => "CCV-E-SCHEMA",
+            Self::Syntax => "CCV-E-SYNTAX",
+            Self::Header => "CCV-E-HEADER",
+            Self::Bom => "CCV-E-BOM",
+            Self::Mime => "CCV-E-MIME",
+            Self::Script => "CCV-E-SCRIPT",
+            Self::Include => "CCV-E-INCLUDE",
+            Self::PartHandler => "CCV-E-PART-HANDLER",
+            Self::Archive => "CCV-E-ARCHIVE",
+            Self::JsonPatch => "CCV-E-JSON-PATCH",
+            Self::Merge => "CCV-E-MERGE",
+            Self::Template => "CCV-E-TEMPLATE",
+            Self::Unsupported => "CCV-E-UNSUPPORTED",
+            Self::Removed => "CCV-E-REMOVED",
+            Self::Malformed => "CCV-E-MALFORMED",
+            Self::Deprecated => "CCV-W-DEPRECATED",
+            Self::Changed => "CCV-W-CHANGED",
+            Self::YamlType => "CCV-W-YAML-TYPE",
+            Self::DuplicateKey => "CCV-W-DUPLICATE-KEY",
+            Self::TemplateVariable => "CCV-W-TEMPLATE-VARIABLE",
+            Self::AddressFamily => "CCV-W-ADDRESS-FAMILY",
+            Self::Conflict => "CCV-W-CONFLICT",
+            Self::Discarded => "CCV-W-DISCARDED",
+            Self::Insecure => "CCV-W-INSECURE",
+            Self::Order => "CCV-W-ORDER",
+            Self::Reference => "CCV-W-REFERENCE",
+            Self::RelativePath => "CCV-W-RELATIVE-PATH",
+            Self::Unreachable => "CCV-W-UNREACHABLE",
+            Self::New => "CCV-I-NEW",
+        }
+    }
+
+    pub fn severity(self) -> Severity {
+        match self {
+            Self::Deprecated
+            | Self::Changed
+            | Self::YamlType
+            | Self::DuplicateKey
+            | Self::TemplateVariable
+            | Self::AddressFamily
+            | Self::Conflict
+            | Self::Discarded
+            | Self::Insecure
+            | Self::Order
+            | Self::Reference
+            | Self::RelativePath
+            | Self::Unreachable => Severity::Warning,
+            Self::New => Severity::Info,
+            _ => Severity::Error,
+        }
+    }
+
+    /// The code of an error of the JSON Schema `keyword`.
+    pub(crate) fn from_keyword(keyword: &str) -> Self {
+        match keyword {
+            "type" => Self::Type,
+            "additionalProperties" => Self::AdditionalProperty,
+            "required" => Self::Required,
+            "enum" | "const" => Self::Enum,
+            "format" => Self::Format,
+            "pattern" => Self::Pattern,
+            "minimum" | "maximum" | "exclusiveMinimum" | "exclusiveMaximum" | "multipleOf" => {
+                Self::Range
+            }
+            "minLength" | "maxLength" | "minItems" | "maxItems" | "minProperties"
+            | "maxProperties" => Self::Size,
+            "uniqueItems" => Self::UniqueItems,
+            "dependencies" => Self::Dependency,
+            "oneOf" => Self::OneOf,
+            "not" => Self::Not,
+            _ => Self::Schema,
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Code {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|code| code.as_str() == s)
+            .ok_or_else(|| format!("Not a valid code: {}", s))
+    }
+}
+
+impl Serialize for Code {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Code {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Codes are part of the public interface: changing one is a breaking change.
+    #[test]
+    fn stable_codes() {
+        let codes: Vec<_> = Code::ALL.iter().map(|code| code.as_str()).collect();
+        assert_eq!(
+            vec![
+                "CCV-E-TYPE",
+                "CCV-E-ADDITIONAL-PROPERTY",
+                "CCV-E-REQUIRED",
+                "CCV-E-ENUM",
+                "CCV-E-FORMAT",
+                "CCV-E-PATTERN",
+                "CCV-E-RANGE",
+                "CCV-E-SIZE",
+                "CCV-E-UNIQUE-ITEMS",
+                "CCV-E-DEPENDENCY",
+                "CCV-E-ONE-OF",
+                "CCV-E-NOT",
+                "CCV-E-SCHEMA",
+                "CCV-E-SYNTAX",
+                "CCV-E-HEADER",
+                "CCV-E-BOM",
+                "CCV-E-MIME",
+                "CCV-E-SCRIPT",
+                "CCV-E-INCLUDE",
+                "CCV-E-PART-HANDLER",
+                "CCV-E-ARCHIVE",
+                "CCV-E-JSON-PATCH",
+                "CCV-E-MERGE",
+                "CCV-E-TEMPLATE",
+                "CCV-E-UNSUPPORTED",
+                "CCV-E-REMOVED",
+                "CCV-E-MALFORMED",
+                "CCV-W-DEPRECATED",
+                "CCV-W-CHANGED",
+                "CCV-W-YAML-TYPE",
+                "CCV-W-DUPLICATE-KEY",
+                "CCV-W-TEMPLATE-VARIABLE",
+                "CCV-W-ADDRESS-FAMILY",
+                "CCV-W-CONFLICT",
+                "CCV-W-DISCARDED",
+                "CCV-W-INSECURE",
+                "CCV-W-ORDER",
+                "CCV-W-REFERENCE",
+                "CCV-W-RELATIVE-PATH",
+                "CCV-W-UNREACHABLE",
+                "CCV-I-NEW",
+            ],
+            codes
+        );
+    }
+
+    #[test]
+    fn severities() {
+        for code in Code::ALL {
+            let expected = match &code.as_str()[..6] {
+                "CCV-E-" => Severity::Error,
+                "CCV-W-" => Severity::Warning,
+                _ => Severity::Info,
+            };
+            assert_eq!(expected, code.severity(), "{}", code);
+            assert_eq!(Ok(code), code.as_str().parse());
+        }
+        assert!("CCV-E-BOGUS".parse::<Code>().is_err());
+    }
+
+    #[test]
+    fn keywords() {
+        assert_eq!(Code::Type, Code::from_keyword("type"));
+        assert_eq!(
+            Code::AdditionalProperty,
+            Code::from_keyword("additionalProperties")
+        );
+        assert_eq!(Code::Size, Code::from_keyword("minItems"));
+        assert_eq!(Code::Range, Code::from_keyword("exclusiveMinimum"));
+        assert_eq!(Code::Schema, Code::from_keyword("bogus"));
+    }
+}
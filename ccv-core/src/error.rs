@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 #[derive(thiserror::Error, Debug)]
@@ -5,18 +7,46 @@ pub enum Error {
     #[error("invalid yaml: {}", .0)]
     InvalidYaml(#[from] serde_yaml::Error),
 
+    #[error("invalid yaml: {description} at line {line} column {column}")]
+    YamlLoad {
+        description: String,
+        line: usize,
+        column: usize,
+    },
+
     #[error("invalid json: {}", .0)]
     InvalidJson(#[from] serde_json::Error),
 
+    #[error("invalid instance-data: {}", .0)]
+    InvalidInstanceData(String),
+
     #[error("invalid JsonSchema: {}", .0)]
     InvalidSchema(String),
 
+    #[error("cannot read {}: {}", .path.display(), .source)]
+    ReadSchema {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
     #[error("request error: {}", .0)]
     RequestError(#[from] reqwest::Error),
 
-    #[error("local JsonSchema ref not found: {}", .r#ref)]
-    LocalSchemaRefNotFound { r#ref: String },
+    #[error("JsonSchema ref not found: {}", .r#ref)]
+    SchemaRefNotFound { r#ref: String },
+
+    #[error("invalid JsonSchema ref: {}", .r#ref)]
+    InvalidSchemaRef { r#ref: String },
+
+    #[error("no bundled schemas for cloud-init {}", .0)]
+    UnknownRelease(String),
+
+    #[error("cloud-init {release} ships no {kind} schema")]
+    UnshippedSchema {
+        release: String,
+        kind: crate::schema::ConfigKind,
+    },
 
-    #[error("local JsonSchema with invalid name: {}", .r#ref)]
-    LocalSchemaRefInvalidName { r#ref: String },
+    #[error("no bundled schemas, enable a `release-*` feature of ccv-core")]
+    NoBundledRelease,
 }
@@ -0,0 +1,790 @@
+//! Rendering of `## template: jinja` user-data, as cloud-init's jinja template handler.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use base64::Engine as _;
+use minijinja::value::{Object, ObjectRepr};
+use minijinja::Environment;
+use saphyr_parser::{Event, Parser};
+use serde_json::{Map, Value};
+
+use crate::error::{Error, Result};
+use crate::span::{Position, Span};
+use crate::userdata::ContentType;
+
+/// What cloud-init renders undefined variables as.
+pub const MISSING_VARIABLE_PREFIX: &str = "CI_MISSING_JINJA_VAR/";
+
+/// Key listing the instance-data values which are base64-encoded.
+const BASE64_ENCODED_KEYS: &str = "base64-encoded-keys";
+
+/// Documented top-level keys of cloud-init's instance-data, besides the `v1` ones which are
+/// top-level variables too.
+const INSTANCE_DATA_KEYS: [&str; 11] = [
+    "_beta_keys",
+    "base64_encoded_keys",
+    "combined_cloud_config",
+    "ds",
+    "features",
+    "merged_cfg",
+    "merged_system_cfg",
+    "sensitive_keys",
+    "sys_info",
+    "system_info",
+    "v1",
+];
+
+/// Documented keys of the `v1` namespace, which is the same across datasources.
+const V1_KEYS: [&str; 19] = [
+    "_beta_keys",
+    "availability_zone",
+    "cloud_id",
+    "cloud_name",
+    "distro",
+    "distro_release",
+    "distro_version",
+    "instance_id",
+    "kernel_release",
+    "local_hostname",
+    "machine",
+    "platform",
+    "public_ssh_keys",
+    "python_version",
+    "region",
+    "subplatform",
+    "sys_platform",
+    "system_platform",
+    "variant",
+];
+
+/// What expressions are replaced with to check the literal text of templates.
+const PLACEHOLDER: &str = "x";
+
+/// The variables templates are rendered with, from an instance-data such as cloud-init's
+/// `/run/cloud-init/instance-data.json`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstanceData {
+    variables: Map<String, Value>,
+}
+
+impl InstanceData {
+    pub fn from_json(json: &str) -> Result<Self> {
+        Self::from_value(serde_json::from_str(json)?)
+    }
+
+    /// Converts an instance-data as cloud-init's `convert_jinja_instance_data`: base64-encoded
+    /// values are decoded, the keys of versioned namespaces such as `v1` are also top-level
+    /// variables and keys which are not identifiers have an alias which is.
+    pub fn from_value(instance_data: Value) -> Result<Self> {
+        let Value::Object(mut instance_data) = instance_data else {
+            return Err(Error::InvalidInstanceData(
+                "instance-data is not an object".to_string(),
+            ));
+        };
+        let decode_paths: Vec<String> = match instance_data.remove(BASE64_ENCODED_KEYS) {
+            Some(Value::Array(paths)) => paths
+                .iter()
+                .filter_map(Value::as_str)
+                .map(|path| path.replace('-', "_"))
+                .collect(),
+            _ => vec![],
+        };
+        Ok(Self {
+            variables: convert(instance_data, "", &decode_paths),
+        })
+    }
+
+    /// Value of the variable at the dotted `path`, e.g. `v1.region`.
+    fn get(&self, path: &[&str]) -> Option<&Value> {
+        let (first, rest) = path.split_first()?;
+        rest.iter()
+            .try_fold(self.variables.get(*first)?, |value, key| {
+                value.as_object()?.get(*key)
+            })
+    }
+}
+
+fn convert(data: Map<String, Value>, prefix: &str, decode_paths: &[String]) -> Map<String, Value> {
+    let mut variables = Map::new();
+    let data: BTreeMap<String, Value> = data.into_iter().collect();
+    for (key, value) in data {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}/{}", prefix, key)
+        };
+        let value = match value {
+            Value::String(encoded) if decode_paths.contains(&path) => {
+                let decoded = base64::engine::general_purpose::STANDARD
+                    .decode(encoded.as_bytes())
+                    .map(|decoded| String::from_utf8_lossy(&decoded).into_owned())
+                    .unwrap_or(encoded);
+                Value::String(decoded)
+            }
+            value => value,
+        };
+        let value = match value {
+            Value::Object(object) => {
+                let converted = convert(object, &path, decode_paths);
+                if is_versioned(&key) {
+                    for (subkey, subvalue) in &converted {
+                        variables.insert(subkey.clone(), subvalue.clone());
+                    }
+                }
+                Value::Object(converted)
+            }
+            value => value,
+        };
+        if let Some(alias) = alias(&key) {
+            variables.insert(alias, value.clone());
+        }
+        variables.insert(key, value);
+    }
+    variables
+}
+
+/// Versioned namespaces, `v` followed by digits.
+fn is_versioned(key: &str) -> bool {
+    key.strip_prefix('v')
+        .is_some_and(|version| !version.is_empty() && version.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// The key with the characters not allowed in identifiers replaced by underscores, if any.
+fn alias(key: &str) -> Option<String> {
+    let mut alias: String = key
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if alias.starts_with(|c: char| c.is_ascii_digit()) {
+        alias.insert(0, '_');
+    }
+    (alias != key).then_some(alias)
+}
+
+/// An undefined variable, rendered as cloud-init's `UndefinedJinjaVariable`.
+#[derive(Debug)]
+struct Missing(String);
+
+impl Object for Missing {
+    fn repr(self: &Arc<Self>) -> ObjectRepr {
+        ObjectRepr::Plain
+    }
+
+    fn is_true(self: &Arc<Self>) -> bool {
+        false
+    }
+
+    fn render(self: &Arc<Self>, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", MISSING_VARIABLE_PREFIX, self.0)
+    }
+}
+
+/// A variable the template uses which is not in the instance-data.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Undefined {
+    /// Dotted path of the variable.
+    pub(crate) variable: String,
+    /// First use of the variable in the template.
+    pub(crate) span: Option<Span>,
+}
+
+/// A mistake in a template.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Finding {
+    pub(crate) description: String,
+    pub(crate) span: Option<Span>,
+}
+
+/// A rendered template.
+#[derive(Debug)]
+pub(crate) struct Rendered<'a> {
+    template: &'a str,
+    pub(crate) content: String,
+    /// Line of the template every rendered line comes from, if it could be told.
+    lines: Vec<Option<usize>>,
+    pub(crate) undefined: Vec<Undefined>,
+}
+
+/// Renders a `## template: jinja` user-data with `instance_data`, as cloud-init's
+/// `render_jinja_payload`: the header line is dropped and undefined variables are rendered as
+/// `CI_MISSING_JINJA_VAR/<name>`.
+pub(crate) fn render<'a>(
+    template: &'a str,
+    instance_data: &InstanceData,
+) -> std::result::Result<Rendered<'a>, Finding> {
+    let (header, body) = template.split_once('\n').unwrap_or((template, ""));
+    let body_offset = header.len() + 1;
+    let mut env = Environment::new();
+    env.set_trim_blocks(true);
+    let compiled = env
+        .template_from_str(body)
+        .map_err(|e| render_error(template, e))?;
+
+    let mut undefined = vec![];
+    let mut missing = vec![];
+    let mut variables: Vec<String> = compiled.undeclared_variables(true).into_iter().collect();
+    variables.sort();
+    for variable in &variables {
+        let path: Vec<&str> = variable.split('.').collect();
+        if env.globals().any(|(name, _)| name == path[0]) || instance_data.get(&path).is_some() {
+            continue;
+        }
+        // Only the last name of a path can be missing, attributes of undefined values fail to
+        // render.
+        let parent = &path[..path.len() - 1];
+        if parent.is_empty() || instance_data.get(parent).is_some_and(Value::is_object) {
+            missing.push(path.iter().map(|name| name.to_string()).collect());
+        }
+        undefined.push(Undefined {
+            span: find_variable(body, variable).map(|span| span.relocate(template, body_offset)),
+            variable: variable.clone(),
+        });
+    }
+
+    let context = context(
+        &Value::Object(instance_data.variables.clone()),
+        &[],
+        &missing,
+    );
+    let content = compiled
+        .render(context)
+        .map_err(|e| render_error(template, e))?;
+    // From 0-based lines of the body to 1-based lines of the template, below its header.
+    let lines = align(body, &content)
+        .into_iter()
+        .map(|line| line.map(|line| line + 2))
+        .collect();
+    Ok(Rendered {
+        template,
+        content,
+        lines,
+        undefined,
+    })
+}
+
+fn render_error(template: &str, e: minijinja::Error) -> Finding {
+    let description = match e.detail() {
+        Some(detail) => format!("{}: {}", e.kind(), detail),
+        None => e.kind().to_string(),
+    };
+    Finding {
+        description: format!("Template cannot be rendered: {}", description),
+        // Below the header line.
+        span: Some(Span::line(template, e.line().unwrap_or(1) + 1)),
+    }
+}
+
+/// What can be told about a template without the instance-data it is rendered with.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Analysis {
+    /// Syntax errors, variables which fail to render and literal text which cannot form a
+    /// cloud-config.
+    pub(crate) errors: Vec<Finding>,
+    /// Variables which are not in cloud-init's instance-data.
+    pub(crate) warnings: Vec<Finding>,
+}
+
+/// Checks a `## template: jinja` user-data for the mistakes which do not depend on the
+/// instance-data: syntax errors, variables cloud-init does not document and literal text which
+/// cannot be part of a cloud-config whatever the variables render to.
+pub(crate) fn analyze(template: &str) -> Analysis {
+    let (header, body) = template.split_once('\n').unwrap_or((template, ""));
+    let body_offset = header.len() + 1;
+    let mut env = Environment::new();
+    env.set_trim_blocks(true);
+    let compiled = match env.template_from_str(body) {
+        Ok(compiled) => compiled,
+        Err(e) => {
+            return Analysis {
+                errors: vec![render_error(template, e)],
+                warnings: vec![],
+            }
+        }
+    };
+
+    let mut analysis = Analysis::default();
+    let mut variables: Vec<String> = compiled.undeclared_variables(true).into_iter().collect();
+    variables.sort();
+    for variable in &variables {
+        let path: Vec<&str> = variable.split('.').collect();
+        if env.globals().any(|(name, _)| name == path[0]) {
+            continue;
+        }
+        let Some(unknown) = unknown_name(&path) else {
+            continue;
+        };
+        let span = find_variable(body, variable).map(|span| span.relocate(template, body_offset));
+        // Attributes of undefined values fail to render.
+        if unknown + 1 < path.len() {
+            analysis.errors.push(Finding {
+                description: format!(
+                    "'{}' is not an instance-data variable, the template cannot be rendered",
+                    path[..=unknown].join(".")
+                ),
+                span,
+            });
+        } else {
+            analysis.warnings.push(Finding {
+                description: format!(
+                    "'{}' is not an instance-data variable, cloud-init renders it as \"{}{}\"",
+                    variable, MISSING_VARIABLE_PREFIX, path[unknown]
+                ),
+                span,
+            });
+        }
+    }
+
+    let skeleton = skeleton(body);
+    let line_span = |line: usize| Some(Span::line(template, line + 1));
+    match ContentType::from_starts_with(&skeleton) {
+        Some(ContentType::CloudConfig) => {
+            if let Some((description, line)) = check_skeleton(&skeleton) {
+                analysis.errors.push(Finding {
+                    description,
+                    span: line_span(line),
+                });
+            }
+        }
+        Some(_) => {}
+        None => {
+            // The type of a template is the one of its first rendered line.
+            let first_line = skeleton
+                .lines()
+                .position(|line| !line.trim().is_empty())
+                .filter(|index| {
+                    let line = body.lines().nth(*index).unwrap_or_default();
+                    tags(line).is_empty()
+                });
+            if let Some(index) = first_line {
+                analysis.errors.push(Finding {
+                    description: "Template does not render to a cloud-config, a boothook nor a \
+                                  shell script, cloud-init ignores it"
+                        .to_string(),
+                    span: line_span(index + 1),
+                });
+            }
+        }
+    }
+    analysis
+}
+
+/// Index of the first name of a variable path which is not in cloud-init's instance-data, if it
+/// can be told: datasource-specific and configuration namespaces can hold anything.
+fn unknown_name(path: &[&str]) -> Option<usize> {
+    match path {
+        [] => None,
+        ["v1", name, ..] => (!V1_KEYS.contains(name)).then_some(1),
+        [name, ..] => (!INSTANCE_DATA_KEYS.contains(name) && !V1_KEYS.contains(name)).then_some(0),
+    }
+}
+
+/// The template with its expressions replaced with a placeholder and its statements and comments
+/// removed, keeping its lines.
+fn skeleton(body: &str) -> String {
+    let mut skeleton = String::with_capacity(body.len());
+    let mut offset = 0;
+    for tag in tags(body) {
+        skeleton.push_str(&body[offset..tag.start]);
+        if tag.is_expression {
+            skeleton.push_str(PLACEHOLDER);
+        }
+        skeleton.extend(body[tag.start..tag.end].matches('\n'));
+        offset = tag.end;
+    }
+    skeleton.push_str(&body[offset..]);
+    skeleton
+}
+
+/// A YAML syntax error in the skeleton of a cloud-config template, or a document which is not a
+/// mapping, and the 1-based line of the skeleton where it is.
+fn check_skeleton(skeleton: &str) -> Option<(String, usize)> {
+    let mut has_root = false;
+    for event in Parser::new_from_str(skeleton) {
+        let (event, span) = match event {
+            Ok(event) => event,
+            Err(e) => {
+                return Some((
+                    format!(
+                        "Template cannot render to valid YAML whatever its variables: {}",
+                        e.info()
+                    ),
+                    e.marker().line(),
+                ))
+            }
+        };
+        match event {
+            Event::DocumentEnd => break,
+            _ if has_root => {}
+            Event::MappingStart(..) => has_root = true,
+            Event::Scalar(value, ..) if value.is_empty() => has_root = true,
+            Event::Scalar(..) | Event::SequenceStart(..) => {
+                return Some((
+                    "Template cannot render to a mapping, cloud-init ignores it".to_string(),
+                    span.start.line(),
+                ))
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The template context: the instance-data variables and the `missing` ones.
+fn context(value: &Value, path: &[&str], missing: &[Vec<String>]) -> minijinja::Value {
+    let Value::Object(object) = value else {
+        return minijinja::Value::from_serialize(value);
+    };
+    let mut context: BTreeMap<String, minijinja::Value> = object
+        .iter()
+        .map(|(key, value)| {
+            let path: Vec<&str> = path.iter().copied().chain([key.as_str()]).collect();
+            (key.clone(), context(value, &path, missing))
+        })
+        .collect();
+    for variable in missing {
+        if let Some((name, parent)) = variable.split_last() {
+            if parent.iter().eq(path) {
+                context.insert(
+                    name.to_string(),
+                    minijinja::Value::from_object(Missing(name.to_string())),
+                );
+            }
+        }
+    }
+    context.into_iter().collect()
+}
+
+/// Span of the first use of the dotted `variable` in the expressions and statements of `body`.
+fn find_variable(body: &str, variable: &str) -> Option<Span> {
+    let is_identifier = |c: char| c.is_alphanumeric() || c == '_';
+    tags(body).into_iter().find_map(|tag| {
+        let (start, _) = body[tag.start..tag.end]
+            .match_indices(variable)
+            .find(|(start, _)| {
+                let (start, end) = (tag.start + start, tag.start + start + variable.len());
+                !body[..start].ends_with(|c: char| is_identifier(c) || c == '.')
+                    && !body[end..].starts_with(is_identifier)
+            })?;
+        let start = tag.start + start;
+        Some(Span::from_offsets(body, start, start + variable.len()))
+    })
+}
+
+/// A `{{ expression }}`, `{% statement %}` or `{# comment #}` tag, as byte offsets.
+struct Tag {
+    start: usize,
+    end: usize,
+    is_expression: bool,
+}
+
+/// The tags of `text`, an unterminated one running to its end.
+fn tags(text: &str) -> Vec<Tag> {
+    let mut tags = vec![];
+    let mut offset = 0;
+    while let Some(start) = text[offset..].find('{').map(|start| offset + start) {
+        let close = match text.as_bytes().get(start + 1) {
+            Some(b'{') => "}}",
+            Some(b'%') => "%}",
+            Some(b'#') => "#}",
+            _ => {
+                offset = start + 1;
+                continue;
+            }
+        };
+        let end = text[start + 2..]
+            .find(close)
+            .map_or(text.len(), |end| start + 2 + end + close.len());
+        tags.push(Tag {
+            start,
+            end,
+            is_expression: close == "}}",
+        });
+        offset = end;
+    }
+    tags
+}
+
+impl Rendered<'_> {
+    /// The span in the template of a span of the rendered content: the same characters if their
+    /// line is rendered verbatim, otherwise the whole template line.
+    pub(crate) fn locate(&self, span: Span) -> Option<Span> {
+        let line = self.template_line(span.start.line)?;
+        let template_line = Span::line(self.template, line);
+        match (self.position(span.start), self.position(span.end)) {
+            (Some(start), Some(end)) if start.offset <= end.offset => Some(Span { start, end }),
+            (Some(start), _) => Some(Span {
+                start,
+                end: template_line.end,
+            }),
+            _ => Some(template_line),
+        }
+    }
+
+    fn template_line(&self, rendered_line: usize) -> Option<usize> {
+        self.lines
+            .get(rendered_line.checked_sub(1)?)
+            .copied()
+            .flatten()
+    }
+
+    /// The position in the template of a position in a rendered line identical to its template
+    /// line.
+    fn position(&self, position: Position) -> Option<Position> {
+        let line = self.template_line(position.line)?;
+        let rendered_line = Span::line(&self.content, position.line);
+        let template_line = Span::line(self.template, line);
+        let rendered_text = &self.content[rendered_line.start.offset..rendered_line.end.offset];
+        let template_text = &self.template[template_line.start.offset..template_line.end.offset];
+        (rendered_text == template_text).then(|| {
+            Position::at(
+                self.template,
+                template_line.start.offset + position.offset - rendered_line.start.offset,
+            )
+        })
+    }
+}
+
+/// The 0-based line of `template` every line of `rendered` most likely comes from.
+///
+/// Template lines are matched by their literal text around tags, in order: a rendered line is
+/// attributed to the next template line it matches, wrapping around for loops.
+fn align(template: &str, rendered: &str) -> Vec<Option<usize>> {
+    let candidates: Vec<(usize, Vec<&str>)> = template
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| literals(line).map(|literals| (index, literals)))
+        .collect();
+    let mut next = 0;
+    rendered
+        .lines()
+        .map(|line| {
+            let found = (next..candidates.len())
+                .chain(0..next)
+                .find(|candidate| matches(&candidates[*candidate].1, line))?;
+            next = found + 1;
+            Some(candidates[found].0)
+        })
+        .collect()
+}
+
+/// The literal text of a template line between its tags, `None` if it renders nothing: only
+/// statements and comments, whose line break is trimmed.
+fn literals(line: &str) -> Option<Vec<&str>> {
+    let tags = tags(line);
+    let mut literals = vec![];
+    let mut offset = 0;
+    for tag in &tags {
+        literals.push(&line[offset..tag.start]);
+        offset = tag.end;
+    }
+    literals.push(&line[offset..]);
+    let renders_nothing = !tags.is_empty()
+        && !tags.iter().any(|tag| tag.is_expression)
+        && literals.iter().all(|literal| literal.trim().is_empty());
+    (!renders_nothing).then_some(literals)
+}
+
+/// Whether `line` is a rendering of the template line of `literals`, ignoring the whitespace
+/// around it.
+fn matches(literals: &[&str], line: &str) -> bool {
+    let line = line.trim();
+    let (first, rest) = match literals {
+        [] => return line.is_empty(),
+        [literal] => return literal.trim() == line,
+        [first, rest @ ..] => (first.trim_start(), rest),
+    };
+    let (last, middle) = rest.split_last().expect("more than one literal");
+    let Some(mut line) = line
+        .strip_prefix(first)
+        .and_then(|line| line.strip_suffix(last.trim_end()))
+    else {
+        return false;
+    };
+    for literal in middle {
+        match line.find(literal) {
+            Some(index) => line = &line[index + literal.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn instance_data() -> InstanceData {
+        InstanceData::from_value(json!({
+            "base64-encoded-keys": ["ds/user_data"],
+            "ds": {"meta-data": {"instance-id": "i-1"}, "user_data": "I2Nsb3VkLWNvbmZpZw=="},
+            "v1": {"local-hostname": "host", "region": "us-east-1"},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn instance_data_variables() {
+        let instance_data = instance_data();
+        assert_eq!(Some(&json!("host")), instance_data.get(&["local_hostname"]));
+        assert_eq!(
+            Some(&json!("us-east-1")),
+            instance_data.get(&["v1", "region"])
+        );
+        assert_eq!(
+            Some(&json!("i-1")),
+            instance_data.get(&["ds", "meta_data", "instance_id"])
+        );
+        assert_eq!(
+            Some(&json!("#cloud-config")),
+            instance_data.get(&["ds", "user_data"])
+        );
+        assert!(InstanceData::from_json("[]").is_err());
+    }
+
+    #[test]
+    fn rendering() {
+        let template = "## template: jinja\n#cloud-config\n{% if v1.region %}\nhostname: {{ v1.local_hostname }}\n{% endif %}\nfqdn: {{ v1.fqdn }}\n";
+        let rendered = render(template, &instance_data()).unwrap();
+        assert_eq!(
+            "#cloud-config\nhostname: host\nfqdn: CI_MISSING_JINJA_VAR/fqdn",
+            rendered.content
+        );
+        assert_eq!(vec![Some(2), Some(4), Some(6)], rendered.lines);
+        assert_eq!(
+            vec![Undefined {
+                variable: "v1.fqdn".to_string(),
+                span: template.find("v1.fqdn").map(|start| Span::from_offsets(
+                    template,
+                    start,
+                    start + 7
+                )),
+            }],
+            rendered.undefined
+        );
+
+        // Rendered verbatim: the same characters; otherwise the whole template line.
+        assert_eq!(
+            Some(Span::from_offsets(template, 19, 27)),
+            rendered.locate(Span::from_offsets(&rendered.content, 0, 8))
+        );
+        assert_eq!(
+            Some(Span::line(template, 4)),
+            rendered.locate(Span::from_offsets(&rendered.content, 24, 28))
+        );
+    }
+
+    #[test]
+    fn loops() {
+        let template = "## template: jinja\n#cloud-config\nruncmd:\n{% for i in [1, 2] %}\n  - echo {{ i }}\n{% endfor %}\nlocale: C\n";
+        let rendered = render(template, &instance_data()).unwrap();
+        assert_eq!(
+            "#cloud-config\nruncmd:\n  - echo 1\n  - echo 2\nlocale: C",
+            rendered.content
+        );
+        assert_eq!(
+            vec![Some(2), Some(3), Some(5), Some(5), Some(7)],
+            rendered.lines
+        );
+    }
+
+    fn descriptions(findings: &[Finding]) -> Vec<(&str, Option<usize>)> {
+        findings
+            .iter()
+            .map(|finding| {
+                (
+                    finding.description.as_str(),
+                    finding.span.map(|span| span.start.line),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn analysis() {
+        let template = "## template: jinja\n#cloud-config\n{% if v1.region %}\nhostname: {{ v1.local_hostname }}\n{% endif %}\nfqdn: {{ v1.fqdn }}\nlocale: {{ ds.meta_data.locale }}\n{# a {{ comment }} #}\n";
+        let analysis = analyze(template);
+        assert_eq!(
+            Vec::<(&str, Option<usize>)>::new(),
+            descriptions(&analysis.errors)
+        );
+        assert_eq!(
+            vec![(
+                "'v1.fqdn' is not an instance-data variable, cloud-init renders it as \
+                 \"CI_MISSING_JINJA_VAR/fqdn\"",
+                Some(6)
+            )],
+            descriptions(&analysis.warnings)
+        );
+
+        let analysis = analyze("## template: jinja\n#cloud-config\nhostname: {{ host.name }}\n");
+        assert_eq!(
+            vec![(
+                "'host' is not an instance-data variable, the template cannot be rendered",
+                Some(3)
+            )],
+            descriptions(&analysis.errors)
+        );
+    }
+
+    #[test]
+    fn impossible_templates() {
+        let analysis =
+            analyze("## template: jinja\n#cloud-config\nruncmd:\n  - {{ v1.region }}\n bogus: [\n");
+        assert_eq!(1, analysis.errors.len());
+        assert!(analysis.errors[0]
+            .description
+            .starts_with("Template cannot render to valid YAML whatever its variables: "));
+
+        let analysis = analyze("## template: jinja\n#cloud-config\n- {{ v1.region }}\n");
+        assert_eq!(
+            vec![(
+                "Template cannot render to a mapping, cloud-init ignores it",
+                Some(3)
+            )],
+            descriptions(&analysis.errors)
+        );
+
+        let analysis =
+            analyze("## template: jinja\n{% if v1.region %}\nhostname: a\n{% endif %}\n");
+        assert_eq!(
+            vec![(
+                "Template does not render to a cloud-config, a boothook nor a shell script, \
+                 cloud-init ignores it",
+                Some(3)
+            )],
+            descriptions(&analysis.errors)
+        );
+
+        // The type depends on the variables.
+        assert_eq!(
+            Analysis::default(),
+            analyze("## template: jinja\n{{ v1.region }}\n")
+        );
+        assert_eq!(
+            Analysis::default(),
+            analyze("## template: jinja\n#!/bin/sh\necho {{ v1.region }}\n")
+        );
+    }
+
+    #[test]
+    fn render_errors() {
+        let template = "## template: jinja\n#cloud-config\nhostname: {{ v1.local_hostname\n";
+        let error = render(template, &instance_data()).unwrap_err();
+        assert!(error
+            .description
+            .starts_with("Template cannot be rendered: syntax error"));
+        assert_eq!(Some(3), error.span.map(|span| span.start.line));
+
+        let template = "## template: jinja\n#cloud-config\nhostname: {{ bogus.name }}\n";
+        let error = render(template, &instance_data()).unwrap_err();
+        assert_eq!(Some(3), error.span.map(|span| span.start.line));
+    }
+}
@@ -1,3 +1,15 @@
+pub mod cache;
+pub mod code;
 pub mod error;
+pub mod jinja;
+pub mod lifecycle;
+pub mod merge;
+mod mime;
+mod resolver;
 pub mod schema;
+mod semantic;
+pub mod span;
+mod suggest;
+pub mod userdata;
 pub mod validator;
+pub mod yaml;
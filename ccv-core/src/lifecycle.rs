@@ -0,0 +1,678 @@
+//! Lifecycle of the keys and values of a schema: the cloud-init versions they were added, changed
+//! and deprecated in, as declared by the `new`, `changed` and `deprecated` metadata of cloud-init's
+//! schemas.
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+use jsonschema::JSONSchema;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+
+use crate::code::Code;
+use crate::span;
+use crate::validator::{ConfigAnnotation, ConfigError};
+
+/// Releases a deprecated feature is kept for, as in cloud-init's `util.deprecate`.
+const DEPRECATION_YEARS: u32 = 5;
+
+/// A replacement named by a deprecation description.
+static REPLACEMENT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"Use ``([\w-]+)`` instead").expect("valid regex"));
+
+/// How deprecations, including changed keys and values, are reported.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeprecationPolicy {
+    /// As errors, which make the configuration invalid.
+    Error,
+    /// As annotations.
+    #[default]
+    Warn,
+    /// Not at all.
+    Ignore,
+}
+
+impl fmt::Display for DeprecationPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warn => write!(f, "warn"),
+            Self::Ignore => write!(f, "ignore"),
+        }
+    }
+}
+
+impl FromStr for DeprecationPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Self::Error),
+            "warn" => Ok(Self::Warn),
+            "ignore" => Ok(Self::Ignore),
+            _ => Err(format!("Not a valid deprecation policy: {}", s)),
+        }
+    }
+}
+
+/// The lifecycle metadata of an annotated key or value.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Lifecycle {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deprecated_version: Option<String>,
+    /// Version a deprecated key or value is scheduled to be removed in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub removal_version: Option<String>,
+    /// Key to use instead of a deprecated one, when known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replacement: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changed_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_version: Option<String>,
+}
+
+/// The version cloud-init removes what was deprecated in `deprecated_version`, five years later.
+pub(crate) fn removal_version(deprecated_version: &str) -> Option<String> {
+    let version: CloudInitVersion = deprecated_version.parse().ok()?;
+    Some(
+        CloudInitVersion {
+            year: version.year + DEPRECATION_YEARS,
+            ..version
+        }
+        .to_string(),
+    )
+}
+
+/// A cloud-init release, such as `23.4` or `23.4.1`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct CloudInitVersion {
+    year: u32,
+    release: u32,
+    patch: u32,
+}
+
+impl CloudInitVersion {
+    /// The release of a point release, e.g. `23.4` for `23.4.1`.
+    pub fn release(self) -> Self {
+        Self { patch: 0, ..self }
+    }
+}
+
+impl fmt::Display for CloudInitVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.year, self.release)?;
+        if self.patch != 0 {
+            write!(f, ".{}", self.patch)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for CloudInitVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let numbers: Option<Vec<u32>> = s.split('.').map(|n| n.parse().ok()).collect();
+        match numbers.as_deref() {
+            Some(&[year, release]) => Ok(Self {
+                year,
+                release,
+                patch: 0,
+            }),
+            Some(&[year, release, patch]) => Ok(Self {
+                year,
+                release,
+                patch,
+            }),
+            _ => Err(format!("Not a valid cloud-init version: {}", s)),
+        }
+    }
+}
+
+impl Serialize for CloudInitVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for CloudInitVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Whether a configuration works on a given cloud-init version, from the lifecycle of the keys and
+/// values it was annotated with.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Compatibility {
+    pub is_compatible: bool,
+    target: CloudInitVersion,
+    /// The oldest version which understands every key and value of the configuration, if any of
+    /// them is known to be new.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    minimum_version: Option<CloudInitVersion>,
+    /// Keys and values newer than the target, or removed by it.
+    errors: Vec<ConfigError>,
+    /// Keys and values which changed after the target.
+    annotations: Vec<ConfigAnnotation>,
+}
+
+impl Compatibility {
+    /// Analyzes the compatibility with `target` of the configuration `annotations` are of.
+    pub fn analyze<'a>(
+        annotations: impl IntoIterator<Item = &'a ConfigAnnotation>,
+        target: CloudInitVersion,
+    ) -> Self {
+        let mut compatibility = Self {
+            is_compatible: true,
+            target,
+            minimum_version: None,
+            errors: vec![],
+            annotations: vec![],
+        };
+        for annotation in annotations {
+            compatibility.check(annotation);
+        }
+        compatibility.is_compatible = compatibility.errors.is_empty();
+        compatibility
+    }
+
+    fn check(&mut self, annotation: &ConfigAnnotation) {
+        let version = |version: &Option<String>| {
+            version
+                .as_deref()
+                .and_then(|version| version.parse::<CloudInitVersion>().ok())
+        };
+        let lifecycle = annotation.lifecycle();
+        let target = self.target;
+
+        if let Some(new) = version(&lifecycle.new_version) {
+            self.minimum_version = self.minimum_version.max(Some(new));
+            if new > target {
+                let description = format!("Requires cloud-init {} or later, not {}", new, target);
+                self.errors
+                    .push(error(Code::Unsupported, description, annotation));
+            }
+        }
+        if let Some(removal) = version(&lifecycle.removal_version) {
+            if removal <= target {
+                let mut description = format!(
+                    "Removed as of cloud-init {}, so not supported by {}",
+                    removal, target
+                );
+                if let Some(replacement) = &lifecycle.replacement {
+                    description.push_str(&format!(". Use ``{}`` instead.", replacement));
+                }
+                self.errors
+                    .push(error(Code::Removed, description, annotation));
+            }
+        }
+        if let Some(changed) = version(&lifecycle.changed_version) {
+            if changed > target {
+                let description = format!(
+                    "Changed in cloud-init {}, after {}, which may not accept it or interpret it \
+                     differently",
+                    changed, target
+                );
+                let mut changed = ConfigAnnotation::new(
+                    Code::Changed,
+                    description,
+                    annotation.instance_path().to_string(),
+                    annotation.span(),
+                )
+                .with_keyword_location(annotation.keyword_location());
+                if let Some(part) = annotation.part() {
+                    changed = changed.with_part(part.clone());
+                }
+                changed.lifecycle = lifecycle.clone();
+                self.annotations.push(changed);
+            }
+        }
+    }
+
+    pub fn target(&self) -> CloudInitVersion {
+        self.target
+    }
+
+    /// The oldest cloud-init version which understands every key and value of the configuration,
+    /// if any of them is known to be new.
+    pub fn minimum_version(&self) -> Option<CloudInitVersion> {
+        self.minimum_version
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &ConfigError> {
+        self.errors.iter()
+    }
+
+    pub fn annotations(&self) -> impl Iterator<Item = &ConfigAnnotation> {
+        self.annotations.iter()
+    }
+}
+
+/// An error about the key or value `annotation` is about.
+fn error(code: Code, description: String, annotation: &ConfigAnnotation) -> ConfigError {
+    let mut error = ConfigError::new(
+        code,
+        description,
+        annotation.instance_path().to_string(),
+        annotation.span(),
+    )
+    .with_keyword_location(annotation.keyword_location());
+    if let Some(value) = annotation.value() {
+        error = error.with_value(value.clone());
+    }
+    if let Some(part) = annotation.part() {
+        error = error.with_part(part.clone());
+    }
+    error
+}
+
+/// The annotation of the instance at `instance_path` by `subschema`, at `keyword_location`, if it
+/// is deprecated, changed or new, in this order of precedence.
+pub(crate) fn annotation(
+    subschema: &Map<String, Value>,
+    keyword_location: &str,
+    instance_path: &str,
+) -> Option<ConfigAnnotation> {
+    let (keyword, code, mut description) = [
+        ("deprecated", Code::Deprecated, "Deprecated"),
+        ("changed", Code::Changed, "Changed"),
+        ("new", Code::New, "New"),
+    ]
+    .into_iter()
+    .find(|(keyword, ..)| subschema.get(*keyword) == Some(&Value::Bool(true)))
+    .map(|(keyword, code, description)| (keyword, code, description.to_string()))?;
+    let metadata = |suffix: &str| {
+        subschema
+            .get(&format!("{}_{}", keyword, suffix))
+            .and_then(Value::as_str)
+    };
+
+    if let Some(version) = metadata("version") {
+        description.push_str(" in version ");
+        description.push_str(version);
+    }
+    if let Some(dsc) = metadata("description") {
+        description.push_str(". ");
+        description.push_str(dsc);
+    }
+
+    let version = metadata("version").map(str::to_string);
+    let lifecycle = match code {
+        Code::Deprecated => Lifecycle {
+            removal_version: version.as_deref().and_then(removal_version),
+            replacement: metadata("description")
+                .and_then(|dsc| REPLACEMENT.captures(dsc))
+                .map(|captures| captures[1].to_string()),
+            deprecated_version: version,
+            ..Default::default()
+        },
+        Code::Changed => Lifecycle {
+            changed_version: version,
+            ..Default::default()
+        },
+        _ => Lifecycle {
+            new_version: version,
+            ..Default::default()
+        },
+    };
+    let mut annotation = ConfigAnnotation::new(code, description, instance_path.to_string(), None)
+        .with_keyword_location(&format!("{}/{}", keyword_location, keyword));
+    annotation.lifecycle = lifecycle;
+    Some(annotation)
+}
+
+/// Annotates `instance` with the lifecycle of every subschema of `schema` it is evaluated against,
+/// whether it is valid or not. As cloud-init, the branches of a `oneOf`/`anyOf` are only
+/// evaluated up to the first one the instance is valid against.
+pub(crate) fn annotate(schema: &Value, instance: &Value) -> Vec<ConfigAnnotation> {
+    let mut walker = Walker {
+        root: schema,
+        branches: HashMap::new(),
+        annotations: vec![],
+    };
+    walker.walk(schema, String::new(), instance, String::new());
+    walker.annotations
+}
+
+struct Walker<'a> {
+    root: &'a Value,
+    /// Validators of the `oneOf`/`anyOf` branches, by keyword location.
+    branches: HashMap<String, Option<JSONSchema>>,
+    annotations: Vec<ConfigAnnotation>,
+}
+
+impl Walker<'_> {
+    fn walk(&mut self, schema: &Value, location: String, instance: &Value, path: String) {
+        let Value::Object(schema) = schema else {
+            return;
+        };
+        // In draft 4, a reference overrides the keywords next to it.
+        if let Some(Value::String(reference)) = schema.get("$ref") {
+            let target = reference
+                .strip_prefix('#')
+                .and_then(|pointer| self.root.pointer(pointer));
+            if let Some(target) = target {
+                self.walk(target, format!("{}/$ref", location), instance, path);
+            }
+            return;
+        }
+        if let Some(annotation) = annotation(schema, &location, &path) {
+            self.annotations.push(annotation);
+        }
+
+        if let Some(Value::Array(subschemas)) = schema.get("allOf") {
+            for (index, subschema) in subschemas.iter().enumerate() {
+                let location = format!("{}/allOf/{}", location, index);
+                self.walk(subschema, location, instance, path.clone());
+            }
+        }
+        for combinator in ["anyOf", "oneOf"] {
+            let Some(Value::Array(subschemas)) = schema.get(combinator) else {
+                continue;
+            };
+            for (index, subschema) in subschemas.iter().enumerate() {
+                let location = format!("{}/{}/{}", location, combinator, index);
+                if self.is_valid(subschema, &location, instance) {
+                    self.walk(subschema, location, instance, path.clone());
+                    break;
+                }
+            }
+        }
+
+        match instance {
+            Value::Object(properties) => self.walk_properties(schema, &location, properties, &path),
+            Value::Array(items) => self.walk_items(schema, &location, items, &path),
+            _ => {}
+        }
+    }
+
+    fn walk_properties(
+        &mut self,
+        schema: &Map<String, Value>,
+        location: &str,
+        properties: &Map<String, Value>,
+        path: &str,
+    ) {
+        let declared = schema.get("properties").and_then(Value::as_object);
+        let patterns: Vec<(&String, Option<Regex>, &Value)> = schema
+            .get("patternProperties")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+            .map(|(pattern, subschema)| (pattern, Regex::new(pattern).ok(), subschema))
+            .collect();
+        let dependencies = schema.get("dependencies").and_then(Value::as_object);
+        for (name, value) in properties {
+            let value_path = format!("{}/{}", path, span::escape(name));
+            let mut matched = false;
+            if let Some(subschema) = declared.and_then(|declared| declared.get(name)) {
+                matched = true;
+                let location = format!("{}/properties/{}", location, span::escape(name));
+                self.walk(subschema, location, value, value_path.clone());
+            }
+            for (pattern, regex, subschema) in &patterns {
+                if regex.as_ref().is_some_and(|regex| regex.is_match(name)) {
+                    matched = true;
+                    let location =
+                        format!("{}/patternProperties/{}", location, span::escape(pattern));
+                    self.walk(subschema, location, value, value_path.clone());
+                }
+            }
+            if let Some(subschema @ Value::Object(_)) = schema.get("additionalProperties") {
+                if !matched {
+                    let location = format!("{}/additionalProperties", location);
+                    self.walk(subschema, location, value, value_path);
+                }
+            }
+            if let Some(subschema @ Value::Object(_)) =
+                dependencies.and_then(|dependencies| dependencies.get(name))
+            {
+                let location = format!("{}/dependencies/{}", location, span::escape(name));
+                let object = Value::Object(properties.clone());
+                self.walk(subschema, location, &object, path.to_string());
+            }
+        }
+    }
+
+    fn walk_items(
+        &mut self,
+        schema: &Map<String, Value>,
+        location: &str,
+        items: &[Value],
+        path: &str,
+    ) {
+        for (index, item) in items.iter().enumerate() {
+            let item_path = format!("{}/{}", path, index);
+            match schema.get("items") {
+                Some(subschema @ Value::Object(_)) => {
+                    self.walk(subschema, format!("{}/items", location), item, item_path);
+                }
+                Some(Value::Array(subschemas)) => match subschemas.get(index) {
+                    Some(subschema) => {
+                        let location = format!("{}/items/{}", location, index);
+                        self.walk(subschema, location, item, item_path);
+                    }
+                    None => {
+                        if let Some(subschema) = schema.get("additionalItems") {
+                            let location = format!("{}/additionalItems", location);
+                            self.walk(subschema, location, item, item_path);
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether `instance` is valid against the branch `subschema` at `location`. Branches are
+    /// compiled along with the root definitions, which their references may point to.
+    fn is_valid(&mut self, subschema: &Value, location: &str, instance: &Value) -> bool {
+        let root = self.root;
+        self.branches
+            .entry(location.to_string())
+            .or_insert_with(|| {
+                let schema = match root.get("definitions") {
+                    Some(definitions) => serde_json::json!({
+                        "definitions": definitions,
+                        "allOf": [subschema],
+                    }),
+                    None => subschema.clone(),
+                };
+                JSONSchema::options()
+                    .with_draft(jsonschema::Draft::Draft4)
+                    .compile(&schema)
+                    .ok()
+            })
+            .as_ref()
+            .is_some_and(|branch| branch.is_valid(instance))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn removal_versions() {
+        assert_eq!(Some("27.2".to_string()), removal_version("22.2"));
+        assert_eq!(Some("28.1".to_string()), removal_version("23.1"));
+        assert_eq!(None, removal_version("main"));
+    }
+
+    #[test]
+    fn versions() {
+        let version = |s: &str| s.parse::<CloudInitVersion>().unwrap();
+        assert!(version("22.4") < version("23.1"));
+        assert!(version("23.1") < version("23.1.1"));
+        assert!(version("9.1") < version("22.1"));
+        assert_eq!("23.1.1", version("23.1.1").to_string());
+        assert_eq!("23.1", version("23.1.0").to_string());
+        assert!("23".parse::<CloudInitVersion>().is_err());
+        assert!("main".parse::<CloudInitVersion>().is_err());
+        assert_eq!(
+            json!("22.4"),
+            serde_json::to_value(version("22.4")).unwrap()
+        );
+    }
+
+    #[test]
+    fn compatibility() {
+        let schema = json!({
+            "properties": {
+                "apt_update": {
+                    "deprecated": true,
+                    "deprecated_version": "22.2",
+                    "deprecated_description": "Use ``package_update`` instead.",
+                },
+                "doas": {"new": true, "new_version": "23.2"},
+                "delay": {"changed": true, "changed_version": "22.3"},
+            },
+        });
+        let instance = json!({"apt_update": true, "doas": [], "delay": "+5"});
+        let annotations = annotate(&schema, &instance);
+        let target = "22.2".parse().unwrap();
+
+        let compatibility = Compatibility::analyze(&annotations, target);
+        assert!(!compatibility.is_compatible);
+        assert_eq!(
+            Some("23.2".parse().unwrap()),
+            compatibility.minimum_version()
+        );
+        let errors: Vec<_> = compatibility
+            .errors()
+            .map(|error| (error.code(), error.description(), error.instance_path()))
+            .collect();
+        assert_eq!(
+            vec![(
+                Code::Unsupported,
+                "Requires cloud-init 23.2 or later, not 22.2",
+                "/doas"
+            )],
+            errors
+        );
+        let changes: Vec<_> = compatibility
+            .annotations()
+            .map(|annotation| (annotation.code(), annotation.instance_path()))
+            .collect();
+        assert_eq!(vec![(Code::Changed, "/delay")], changes);
+
+        let compatibility = Compatibility::analyze(&annotations, "27.2".parse().unwrap());
+        let errors: Vec<_> = compatibility
+            .errors()
+            .map(|error| (error.code(), error.description(), error.instance_path()))
+            .collect();
+        assert_eq!(
+            vec![(
+                Code::Removed,
+                "Removed as of cloud-init 27.2, so not supported by 27.2. Use ``package_update`` \
+                 instead.",
+                "/apt_update"
+            )],
+            errors
+        );
+        assert_eq!(0, compatibility.annotations().count());
+    }
+
+    #[test]
+    fn lifecycle_annotations() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "apt_update": {
+                    "type": "boolean",
+                    "deprecated": true,
+                    "deprecated_version": "22.2",
+                    "deprecated_description": "Use ``package_update`` instead.",
+                },
+                "delay": {
+                    "oneOf": [
+                        {"type": "integer"},
+                        {"type": "string", "changed": true, "changed_version": "22.3"},
+                    ],
+                },
+                "users": {
+                    "type": "array",
+                    "items": {
+                        "properties": {
+                            "doas": {"type": "array", "new": true, "new_version": "23.2"},
+                        },
+                    },
+                },
+            },
+        });
+        let annotations = annotate(
+            &schema,
+            &json!({"apt_update": 1, "delay": "+5", "users": [{}, {"doas": "bogus"}]}),
+        );
+        let annotations: Vec<_> = annotations
+            .iter()
+            .map(|annotation| {
+                (
+                    annotation.code(),
+                    annotation.description(),
+                    annotation.instance_path(),
+                    annotation.keyword_location(),
+                    annotation.lifecycle().clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    Code::Deprecated,
+                    "Deprecated in version 22.2. Use ``package_update`` instead.",
+                    "/apt_update",
+                    "/properties/apt_update/deprecated",
+                    Lifecycle {
+                        deprecated_version: Some("22.2".to_string()),
+                        removal_version: Some("27.2".to_string()),
+                        replacement: Some("package_update".to_string()),
+                        ..Default::default()
+                    }
+                ),
+                (
+                    Code::Changed,
+                    "Changed in version 22.3",
+                    "/delay",
+                    "/properties/delay/oneOf/1/changed",
+                    Lifecycle {
+                        changed_version: Some("22.3".to_string()),
+                        ..Default::default()
+                    }
+                ),
+                (
+                    Code::New,
+                    "New in version 23.2",
+                    "/users/1/doas",
+                    "/properties/users/items/properties/doas/new",
+                    Lifecycle {
+                        new_version: Some("23.2".to_string()),
+                        ..Default::default()
+                    }
+                ),
+            ],
+            annotations
+        );
+    }
+
+    #[test]
+    fn invalid_branches() {
+        let schema = json!({
+            "definitions": {"deprecated": {"deprecated": true}},
+            "oneOf": [
+                {"allOf": [{"type": "string"}, {"$ref": "#/definitions/deprecated"}]},
+                {"type": "integer"},
+            ],
+        });
+        assert!(annotate(&schema, &json!(1)).is_empty());
+        assert_eq!(1, annotate(&schema, &json!("a")).len());
+    }
+}
@@ -0,0 +1,559 @@
+//! cloud-init's merging of cloud-configs.
+//!
+//! How two cloud-configs are merged is declared with `Merge-Type` headers or `merge_how` keys,
+//! e.g. `list(append)+dict(no_replace,recurse_list)+str()`.
+use std::fmt;
+
+use serde_json::{Map, Value};
+
+/// Mergers of the cloud-config parts of a user-data declaring none.
+pub const DEFAULT_PART_MERGERS: &str = "dict(replace)+list()+str()";
+/// Mergers of whole cloud-configs declaring none, such as the user-data and vendor-data ones.
+pub const DEFAULT_MERGERS: &str = "dict()+list()+str()";
+
+/// Merger of one type of value and its options.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Merger {
+    pub name: String,
+    pub options: Vec<String>,
+}
+
+impl fmt::Display for Merger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({})", self.name, self.options.join(","))
+    }
+}
+
+/// Mergers cloud-init provides and their options.
+const MERGERS: [(&str, &[&str]); 3] = [
+    (
+        "dict",
+        &[
+            "allow_delete",
+            "no_replace",
+            "recurse_array",
+            "recurse_list",
+            "recurse_str",
+            "replace",
+        ],
+    ),
+    (
+        "list",
+        &[
+            "append",
+            "no_replace",
+            "prepend",
+            "recurse_array",
+            "recurse_dict",
+            "recurse_list",
+            "recurse_str",
+            "replace",
+        ],
+    ),
+    ("str", &["append"]),
+];
+
+impl Merger {
+    /// A merger cloud-init provides, with options it knows about.
+    fn new(name: &str, options: Vec<String>) -> Result<Self, String> {
+        let name = name.strip_prefix("m_").unwrap_or(name);
+        let known_options = MERGERS
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|(_, options)| *options)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown merger \"{}\", expected one of \"dict\", \"list\" or \"str\"",
+                    name
+                )
+            })?;
+        if let Some(option) = options
+            .iter()
+            .find(|option| !known_options.contains(&option.as_str()))
+        {
+            return Err(format!(
+                "Unknown option \"{}\" of merger \"{}\", expected one of {}",
+                option,
+                name,
+                known_options
+                    .iter()
+                    .map(|option| format!("\"{}\"", option))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            options,
+        })
+    }
+
+    fn has(&self, option: &str) -> bool {
+        self.options.iter().any(|o| o == option)
+    }
+
+    /// The first of `methods` among the options, otherwise `default`.
+    fn method<'a>(&self, methods: &[&'a str], default: &'a str) -> &'a str {
+        methods
+            .iter()
+            .find(|method| self.has(method))
+            .copied()
+            .unwrap_or(default)
+    }
+
+    fn recurse_array(&self) -> bool {
+        self.has("recurse_array") || self.has("recurse_list")
+    }
+}
+
+/// Parses a merge specification as cloud-init's `string_extract_mergers`, rejecting the mergers
+/// and options cloud-init does not know about.
+pub fn parse_mergers(spec: &str) -> Result<Vec<Merger>, String> {
+    let mut mergers = vec![];
+    for merger in spec.split('+') {
+        let merger = merger.trim().to_lowercase().replace('-', "_");
+        if merger.is_empty() {
+            continue;
+        }
+        let (name, options) = merger
+            .strip_suffix(')')
+            .and_then(|merger| merger.split_once('('))
+            .filter(|(name, _)| is_identifier(name))
+            .ok_or_else(|| {
+                format!(
+                    "\"{}\" is not a merger, expected a name followed by options in \
+                     parentheses, e.g. \"list(append)\"",
+                    merger
+                )
+            })?;
+        let options = options
+            .split(',')
+            .map(str::trim)
+            .filter(|option| !option.is_empty())
+            .map(str::to_string)
+            .collect();
+        mergers.push(Merger::new(name, options)?);
+    }
+    Ok(mergers)
+}
+
+/// Removes the `merge_how` key of `config`, or failing that the `merge_type` one, returning its
+/// mergers as cloud-init's `dict_extract_mergers`.
+///
+/// The mergers are a specification string or a list of mergers, each a mapping with a `name` and
+/// its `settings`, or a list of its name followed by its options.
+pub fn extract_mergers(config: &mut Value) -> Result<Vec<Merger>, String> {
+    let Value::Object(config) = config else {
+        return Ok(vec![]);
+    };
+    let mergers = config
+        .remove("merge_how")
+        .or_else(|| config.remove("merge_type"));
+    match mergers {
+        None => Ok(vec![]),
+        Some(Value::String(spec)) => parse_mergers(&spec),
+        Some(Value::Array(mergers)) => mergers
+            .iter()
+            .filter_map(|merger| {
+                let (name, options) = match merger {
+                    Value::Object(merger) => (
+                        merger.get("name"),
+                        merger
+                            .get("settings")
+                            .and_then(Value::as_array)
+                            .map(Vec::as_slice),
+                    ),
+                    Value::Array(merger) => (merger.first(), merger.get(1..)),
+                    _ => (None, None),
+                };
+                let Some(name) = name.and_then(Value::as_str) else {
+                    return Some(Err(format!("{} is not a merger", merger)));
+                };
+                let name = name.trim().replace('-', "_");
+                if name.is_empty() {
+                    return None;
+                }
+                let options = options
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|option| match option {
+                        Value::String(option) => option.clone(),
+                        option => option.to_string(),
+                    })
+                    .collect();
+                Some(Merger::new(&name, options))
+            })
+            .collect(),
+        Some(mergers) => Err(format!("{} is not a list of mergers", mergers)),
+    }
+}
+
+/// Merges `merge_with` into `value`, as cloud-init's `LookupMerger.merge`.
+///
+/// Values are merged by the merger of the type of `value`, if any, otherwise `value` is kept.
+pub fn merge(mergers: &[Merger], value: Value, merge_with: Value) -> Value {
+    let name = match value {
+        Value::Object(_) => "dict",
+        Value::Array(_) => "list",
+        Value::String(_) => "str",
+        _ => return value,
+    };
+    let Some(merger) = mergers.iter().find(|merger| merger.name == name) else {
+        return value;
+    };
+    match value {
+        Value::Object(value) => merge_dict(mergers, merger, value, merge_with),
+        Value::Array(value) => merge_list(mergers, merger, value, merge_with),
+        Value::String(value) => merge_str(merger, value, merge_with),
+        _ => unreachable!("only containers and strings are merged"),
+    }
+}
+
+fn merge_dict(
+    mergers: &[Merger],
+    merger: &Merger,
+    mut value: Map<String, Value>,
+    merge_with: Value,
+) -> Value {
+    let Value::Object(merge_with) = merge_with else {
+        return Value::Object(value);
+    };
+    let replace = merger.method(&["replace", "no_replace"], "no_replace") == "replace";
+    let merge_same_key = |old: Value, new: Value| {
+        let recurse = match new {
+            _ if replace => false,
+            Value::Array(_) => merger.recurse_array(),
+            Value::String(_) => merger.has("recurse_str"),
+            // Dicts are always merged recursively.
+            Value::Object(_) => true,
+            _ => false,
+        };
+        match (replace, recurse) {
+            (true, _) => new,
+            (false, true) => merge(mergers, old, new),
+            (false, false) => old,
+        }
+    };
+    for (key, new) in merge_with {
+        if new.is_null() && merger.has("allow_delete") && value.contains_key(&key) {
+            value.remove(&key);
+        } else if let Some(old) = value.remove(&key) {
+            value.insert(key, merge_same_key(old, new));
+        } else {
+            value.insert(key, new);
+        }
+    }
+    Value::Object(value)
+}
+
+fn merge_list(mergers: &[Merger], merger: &Merger, value: Vec<Value>, merge_with: Value) -> Value {
+    let method = merger.method(&["append", "prepend", "replace", "no_replace"], "replace");
+    let merge_with = match merge_with {
+        Value::Array(merge_with) => merge_with,
+        merge_with if method == "replace" => return merge_with,
+        // Python extends lists with the characters of strings and the keys of dicts.
+        Value::String(merge_with) => merge_with
+            .chars()
+            .map(|c| Value::String(c.to_string()))
+            .collect(),
+        Value::Object(merge_with) => merge_with
+            .into_iter()
+            .map(|(k, _)| Value::String(k))
+            .collect(),
+        // Which cloud-init fails to merge.
+        _ => return Value::Array(value),
+    };
+    match method {
+        "append" => Value::Array(value.into_iter().chain(merge_with).collect()),
+        "prepend" => Value::Array(merge_with.into_iter().chain(value).collect()),
+        "no_replace" => Value::Array(value),
+        _ => {
+            let mut merge_with = merge_with.into_iter();
+            let merged = value
+                .into_iter()
+                .map(|old| match merge_with.next() {
+                    None => old,
+                    Some(new) => {
+                        let recurse = match new {
+                            Value::Array(_) => merger.recurse_array(),
+                            Value::String(_) => merger.has("recurse_str"),
+                            Value::Object(_) => merger.has("recurse_dict"),
+                            _ => false,
+                        };
+                        if recurse {
+                            merge(mergers, old, new)
+                        } else {
+                            new
+                        }
+                    }
+                })
+                .collect();
+            Value::Array(merged)
+        }
+    }
+}
+
+fn merge_str(merger: &Merger, value: String, merge_with: Value) -> Value {
+    match merge_with {
+        Value::String(merge_with) if merger.has("append") => Value::String(value + &merge_with),
+        merge_with => merge_with,
+    }
+}
+
+/// Applies a JSON patch (RFC 6902) to `value`, as cloud-init's `#cloud-config-jsonp` parts.
+pub fn apply_patch(value: &Value, patch: &Value) -> Result<Value, String> {
+    let operations = patch
+        .as_array()
+        .ok_or_else(|| "JSON patch is not a list of operations".to_string())?;
+    let mut patched = value.clone();
+    for operation in operations {
+        let member = |name: &str| {
+            operation
+                .get(name)
+                .ok_or_else(|| format!("\"{}\" is a required property of {}", name, operation))
+        };
+        let path = |name: &str| {
+            member(name)?
+                .as_str()
+                .ok_or_else(|| format!("\"{}\" is not a JSON pointer in {}", name, operation))
+        };
+        match member("op")?.as_str() {
+            Some("add") => add(&mut patched, path("path")?, member("value")?.clone())?,
+            Some("remove") => {
+                remove(&mut patched, path("path")?)?;
+            }
+            Some("replace") => {
+                remove(&mut patched, path("path")?)?;
+                add(&mut patched, path("path")?, member("value")?.clone())?;
+            }
+            Some("move") => {
+                let moved = remove(&mut patched, path("from")?)?;
+                add(&mut patched, path("path")?, moved)?;
+            }
+            Some("copy") => {
+                let copied = patched
+                    .pointer(path("from")?)
+                    .cloned()
+                    .ok_or_else(|| format!("\"{}\" does not exist", path("from").unwrap()))?;
+                add(&mut patched, path("path")?, copied)?;
+            }
+            Some("test") => {
+                if patched.pointer(path("path")?) != Some(member("value")?) {
+                    return Err(format!("Test failed: {}", operation));
+                }
+            }
+            _ => return Err(format!("{} is not a JSON patch operation", operation)),
+        }
+    }
+    Ok(patched)
+}
+
+/// Splits a JSON pointer into its parent pointer and its unescaped last reference token.
+fn split_pointer(pointer: &str) -> Result<(&str, String), String> {
+    let (parent, token) = pointer
+        .rsplit_once('/')
+        .ok_or_else(|| format!("\"{}\" is not a JSON pointer", pointer))?;
+    Ok((parent, token.replace("~1", "/").replace("~0", "~")))
+}
+
+fn add(value: &mut Value, pointer: &str, new: Value) -> Result<(), String> {
+    if pointer.is_empty() {
+        *value = new;
+        return Ok(());
+    }
+    let (parent, token) = split_pointer(pointer)?;
+    match value.pointer_mut(parent) {
+        Some(Value::Object(object)) => {
+            object.insert(token, new);
+        }
+        Some(Value::Array(array)) => {
+            let index = match token.as_str() {
+                "-" => array.len(),
+                token => token
+                    .parse()
+                    .ok()
+                    .filter(|index| *index <= array.len())
+                    .ok_or_else(|| format!("\"{}\" is not a valid index", pointer))?,
+            };
+            array.insert(index, new);
+        }
+        _ => return Err(format!("\"{}\" does not exist", parent)),
+    }
+    Ok(())
+}
+
+fn remove(value: &mut Value, pointer: &str) -> Result<Value, String> {
+    let (parent, token) = split_pointer(pointer)?;
+    let removed = match value.pointer_mut(parent) {
+        Some(Value::Object(object)) => object.remove(&token),
+        Some(Value::Array(array)) => token
+            .parse()
+            .ok()
+            .filter(|index| *index < array.len())
+            .map(|index| array.remove(index)),
+        _ => None,
+    };
+    removed.ok_or_else(|| format!("\"{}\" does not exist", pointer))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn merge_all(spec: &str, configs: Vec<Value>) -> Value {
+        let mergers = parse_mergers(spec).unwrap();
+        configs
+            .into_iter()
+            .fold(json!({}), |merged, config| merge(&mergers, merged, config))
+    }
+
+    #[test]
+    fn mergers() {
+        let mergers =
+            parse_mergers("List( Append )+ dict(no-replace,recurse_list) +str()").unwrap();
+        let mergers: Vec<_> = mergers.iter().map(Merger::to_string).collect();
+        assert_eq!(
+            vec!["list(append)", "dict(no_replace,recurse_list)", "str()"],
+            mergers
+        );
+        assert_eq!(Ok(vec![]), parse_mergers(""));
+    }
+
+    #[test]
+    fn invalid_mergers() {
+        assert_eq!(
+            Err(
+                "\"list\" is not a merger, expected a name followed by options in parentheses, \
+                 e.g. \"list(append)\""
+                    .to_string()
+            ),
+            parse_mergers("list")
+        );
+        assert_eq!(
+            Err(
+                "Unknown merger \"lists\", expected one of \"dict\", \"list\" or \"str\""
+                    .to_string()
+            ),
+            parse_mergers("lists(append)")
+        );
+        assert_eq!(
+            Err(
+                "Unknown option \"prepend\" of merger \"str\", expected one of \"append\""
+                    .to_string()
+            ),
+            parse_mergers("str(prepend)")
+        );
+    }
+
+    #[test]
+    fn extracted_mergers() {
+        let mut config = json!({
+            "merge_how": [
+                {"name": "list", "settings": ["append"]},
+                ["dict", "no_replace", "recurse_list"],
+            ],
+            "merge_type": "str(append)",
+            "runcmd": ["ls"],
+        });
+        let mergers: Vec<_> = extract_mergers(&mut config)
+            .unwrap()
+            .iter()
+            .map(Merger::to_string)
+            .collect();
+        assert_eq!(
+            vec!["list(append)", "dict(no_replace,recurse_list)"],
+            mergers
+        );
+        assert_eq!(
+            json!({"merge_type": "str(append)", "runcmd": ["ls"]}),
+            config
+        );
+
+        let mut config = json!({"merge_how": 1});
+        assert_eq!(
+            Err("1 is not a list of mergers".to_string()),
+            extract_mergers(&mut config)
+        );
+    }
+
+    #[test]
+    fn default_part_mergers() {
+        let merged = merge_all(
+            DEFAULT_PART_MERGERS,
+            vec![
+                json!({"runcmd": ["a"], "apt": {"x": 1, "y": 1}, "locale": "C"}),
+                json!({"runcmd": ["b"], "apt": {"y": 2}}),
+            ],
+        );
+        assert_eq!(
+            json!({"runcmd": ["b"], "apt": {"y": 2}, "locale": "C"}),
+            merged
+        );
+    }
+
+    #[test]
+    fn recursive_mergers() {
+        let merged = merge_all(
+            "list(append)+dict(no_replace,recurse_list,recurse_str)+str(append)",
+            vec![
+                json!({"runcmd": ["a"], "apt": {"x": 1, "y": 1}, "locale": "C"}),
+                json!({"runcmd": ["b"], "apt": {"y": 2, "z": 2}, "locale": ".UTF-8"}),
+            ],
+        );
+        assert_eq!(
+            json!({
+                "runcmd": ["a", "b"],
+                "apt": {"x": 1, "y": 1, "z": 2},
+                "locale": "C.UTF-8",
+            }),
+            merged
+        );
+
+        let merged = merge_all(
+            "dict(allow_delete,no_replace,recurse_list)+list(prepend)",
+            vec![
+                json!({"packages": ["a"], "locale": "C"}),
+                json!({"packages": ["b"], "locale": null}),
+            ],
+        );
+        assert_eq!(json!({"packages": ["b", "a"]}), merged);
+
+        let merged = merge_all(
+            "dict(no_replace,recurse_list)+list(recurse_dict)",
+            vec![
+                json!({"users": [{"name": "a"}, "default"]}),
+                json!({"users": [{"name": "b", "uid": 1}, "c", "d"]}),
+            ],
+        );
+        assert_eq!(json!({"users": [{"name": "a", "uid": 1}, "c"]}), merged);
+    }
+
+    #[test]
+    fn patches() {
+        let config = json!({"runcmd": ["a"], "locale": "C"});
+        let patch = json!([
+            {"op": "add", "path": "/runcmd/-", "value": "b"},
+            {"op": "add", "path": "/runcmd/0", "value": "0"},
+            {"op": "move", "from": "/locale", "path": "/timezone"},
+            {"op": "copy", "from": "/timezone", "path": "/a~1b"},
+            {"op": "test", "path": "/runcmd/2", "value": "b"},
+        ]);
+        assert_eq!(
+            Ok(json!({"runcmd": ["0", "a", "b"], "timezone": "C", "a/b": "C"})),
+            apply_patch(&config, &patch)
+        );
+        assert_eq!(
+            Err("\"/packages\" does not exist".to_string()),
+            apply_patch(&config, &json!([{"op": "remove", "path": "/packages"}]))
+        );
+    }
+}
@@ -0,0 +1,358 @@
+//! Parsing of MIME messages, as lenient as Python's `email` package which cloud-init relies on.
+use base64::Engine as _;
+
+use crate::span::Span;
+use crate::userdata::Encoding;
+
+/// A header field, unfolded.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Header {
+    pub(crate) name: String,
+    pub(crate) value: String,
+    /// Byte offsets of the first line of the field in the message.
+    start: usize,
+    end: usize,
+}
+
+impl Header {
+    /// Span of the first line of the field in `message`.
+    pub(crate) fn span(&self, message: &str) -> Span {
+        Span::from_offsets(message, self.start, self.end)
+    }
+
+    /// The value without its parameters, lowercased.
+    pub(crate) fn main_value(&self) -> String {
+        split_params(&self.value)[0].trim().to_lowercase()
+    }
+
+    /// Value of the parameter `name`, unquoted.
+    pub(crate) fn param(&self, name: &str) -> Option<String> {
+        split_params(&self.value)
+            .into_iter()
+            .skip(1)
+            .find_map(|param| {
+                let (key, value) = param.split_once('=')?;
+                key.trim()
+                    .eq_ignore_ascii_case(name)
+                    .then(|| unquote(value.trim()))
+            })
+    }
+}
+
+/// A message, or a part of a multipart message.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Entity<'a> {
+    pub(crate) headers: Vec<Header>,
+    pub(crate) body: &'a str,
+    /// Byte offset of the body in the message.
+    pub(crate) body_offset: usize,
+}
+
+impl<'a> Entity<'a> {
+    pub(crate) fn parse(message: &'a str) -> Self {
+        Self::parse_range(message, 0, message.len())
+    }
+
+    /// Parses the entity in the bytes `start..end` of `message`.
+    fn parse_range(message: &'a str, start: usize, end: usize) -> Self {
+        let mut headers: Vec<Header> = vec![];
+        let mut offset = start;
+        while offset < end {
+            let line = message[offset..end]
+                .split_inclusive('\n')
+                .next()
+                .unwrap_or_default();
+            let content = line.trim_end_matches(['\r', '\n']);
+            let line_end = offset + content.len();
+            if content.is_empty() {
+                // The separator between the headers and the body.
+                offset += line.len();
+                break;
+            }
+            match headers.last_mut() {
+                Some(header) if content.starts_with([' ', '\t']) => {
+                    header.value.push_str(content);
+                }
+                _ => match content.split_once(':') {
+                    Some((name, value)) if is_header_name(name) => headers.push(Header {
+                        name: name.to_string(),
+                        value: value.trim_start().to_string(),
+                        start: offset,
+                        end: line_end,
+                    }),
+                    // Not a header: the body starts without a separator.
+                    _ => break,
+                },
+            }
+            offset += line.len();
+        }
+        Self {
+            headers,
+            body: &message[offset..end],
+            body_offset: offset,
+        }
+    }
+
+    /// First header called `name`, case-insensitively.
+    pub(crate) fn header(&self, name: &str) -> Option<&Header> {
+        self.headers
+            .iter()
+            .find(|header| header.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The lowercased MIME type, `text/plain` if it is missing or malformed.
+    pub(crate) fn mime_type(&self) -> String {
+        match self.header("Content-Type").map(Header::main_value) {
+            Some(mime_type) if mime_type.matches('/').count() == 1 => mime_type,
+            _ => "text/plain".to_string(),
+        }
+    }
+
+    /// File name from the `Content-Disposition` header or, failing that, the `Content-Type` one.
+    pub(crate) fn filename(&self) -> Option<String> {
+        self.header("Content-Disposition")
+            .and_then(|header| header.param("filename"))
+            .or_else(|| {
+                self.header("Content-Type")
+                    .and_then(|header| header.param("name"))
+            })
+    }
+
+    /// The leaf entities of the message, depth first, as Python's `Message.walk` skipping
+    /// multipart containers.
+    pub(crate) fn leaves(self, message: &'a str) -> Vec<Entity<'a>> {
+        let boundary = self
+            .header("Content-Type")
+            .filter(|_| self.mime_type().starts_with("multipart/"))
+            .and_then(|header| header.param("boundary"));
+        match boundary {
+            Some(boundary) => self
+                .parts(message, &boundary)
+                .into_iter()
+                .flat_map(|part| part.leaves(message))
+                .collect(),
+            None => vec![self],
+        }
+    }
+
+    /// Parts of a multipart body, ignoring its preamble and epilogue.
+    fn parts(&self, message: &'a str, boundary: &str) -> Vec<Entity<'a>> {
+        let delimiter = format!("--{}", boundary);
+        let mut parts = vec![];
+        // Start of the current part, `None` within the preamble.
+        let mut part_start = None;
+        let mut offset = self.body_offset;
+        for line in self.body.split_inclusive('\n') {
+            let content = line.trim_end_matches(['\r', '\n']);
+            if let Some(rest) = content.strip_prefix(&delimiter) {
+                let rest = rest.trim_end_matches([' ', '\t']);
+                if rest.is_empty() || rest == "--" {
+                    if let Some(start) = part_start {
+                        // The line break before the delimiter belongs to it.
+                        let before = &message[start..offset];
+                        let end = start
+                            + before
+                                .strip_suffix('\n')
+                                .map(|before| before.strip_suffix('\r').unwrap_or(before))
+                                .unwrap_or(before)
+                                .len();
+                        parts.push(Self::parse_range(message, start, end));
+                    }
+                    if rest == "--" {
+                        return parts;
+                    }
+                    part_start = Some(offset + line.len());
+                }
+            }
+            offset += line.len();
+        }
+        // A missing closing delimiter ends the last part at the end of the message.
+        if let Some(start) = part_start {
+            parts.push(Self::parse_range(message, start, offset));
+        }
+        parts
+    }
+
+    /// The body without its `Content-Transfer-Encoding`, `None` if it is not encoded.
+    pub(crate) fn decoded_body(&self) -> Result<Option<(Encoding, Vec<u8>)>, String> {
+        let encoding = self
+            .header("Content-Transfer-Encoding")
+            .map(Header::main_value);
+        match encoding.as_deref() {
+            Some("base64") => {
+                let encoded: Vec<u8> = self
+                    .body
+                    .bytes()
+                    .filter(|b| !b.is_ascii_whitespace())
+                    .collect();
+                base64::engine::general_purpose::STANDARD
+                    .decode(encoded)
+                    .map(|decoded| Some((Encoding::Base64, decoded)))
+                    .map_err(|e| format!("Part is not valid base64: {}", e))
+            }
+            Some("quoted-printable") => Ok(Some((
+                Encoding::QuotedPrintable,
+                decode_quoted_printable(self.body),
+            ))),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Python's `email` header names: printable ASCII characters except the colon.
+fn is_header_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| (b'!'..=b'~').contains(&b))
+}
+
+/// Splits a header value on the semicolons outside of quotes.
+fn split_params(value: &str) -> Vec<&str> {
+    let mut params = vec![];
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (index, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                params.push(&value[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    params.push(&value[start..]);
+    params
+}
+
+fn unquote(value: &str) -> String {
+    match value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        Some(quoted) => {
+            let mut unquoted = String::with_capacity(quoted.len());
+            let mut chars = quoted.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => unquoted.extend(chars.next()),
+                    c => unquoted.push(c),
+                }
+            }
+            unquoted
+        }
+        None => value.to_string(),
+    }
+}
+
+fn decode_quoted_printable(body: &str) -> Vec<u8> {
+    let bytes = body.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let rest = &bytes[index..];
+        if rest.starts_with(b"=\r\n") {
+            index += 3;
+        } else if rest.starts_with(b"=\n") {
+            index += 2;
+        } else if let Some(byte) = rest
+            .get(1..3)
+            .filter(|_| rest[0] == b'=')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            index += 3;
+        } else {
+            decoded.push(rest[0]);
+            index += 1;
+        }
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MESSAGE: &str = "Content-Type: multipart/mixed; boundary=\"==b;1==\"\r\n\
+MIME-Version: 1.0\r\n\
+\r\n\
+preamble\r\n\
+--==b;1==\r\n\
+Content-Type: text/cloud-config\r\n\
+Content-Disposition: attachment;\r\n filename=\"config.yaml\"\r\n\
+\r\n\
+runcmd: [ls]\r\n\
+--==b;1==\r\n\
+Content-Type: text/x-shellscript; charset=\"us-ascii\"\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+IyEvYmluL3NoCg==\r\n\
+--==b;1==--\r\n\
+epilogue\r\n";
+
+    #[test]
+    fn multipart() {
+        let message = Entity::parse(MESSAGE);
+        assert_eq!("multipart/mixed", message.mime_type());
+        assert_eq!("1.0", message.header("mime-version").unwrap().value);
+
+        let leaves = message.leaves(MESSAGE);
+        assert_eq!(2, leaves.len());
+
+        let config = &leaves[0];
+        assert_eq!("text/cloud-config", config.mime_type());
+        assert_eq!(Some("config.yaml".to_string()), config.filename());
+        assert_eq!("runcmd: [ls]", config.body);
+        assert_eq!(
+            config.body,
+            &MESSAGE[config.body_offset..config.body_offset + config.body.len()]
+        );
+        assert_eq!(Ok(None), config.decoded_body());
+        assert_eq!(
+            7,
+            config
+                .header("content-disposition")
+                .unwrap()
+                .span(MESSAGE)
+                .start
+                .line
+        );
+
+        let script = &leaves[1];
+        assert_eq!("text/x-shellscript", script.mime_type());
+        assert_eq!(None, script.filename());
+        assert_eq!(
+            Ok(Some((Encoding::Base64, b"#!/bin/sh\n".to_vec()))),
+            script.decoded_body()
+        );
+    }
+
+    #[test]
+    fn nested_and_unterminated() {
+        let message = "Content-Type: multipart/mixed; boundary=a\n\n--a\n\
+Content-Type: multipart/alternative; boundary=b\n\n--b\n\nfirst\n--b\n\
+Content-Type: text/plain\n\nsecond\n--b--\n--a\nContent-Type: bogus\n\nthird\n";
+        let leaves = Entity::parse(message).leaves(message);
+        let bodies: Vec<_> = leaves.iter().map(|leaf| leaf.body).collect();
+        assert_eq!(vec!["first", "second", "third\n"], bodies);
+        assert_eq!("text/plain", leaves[2].mime_type());
+    }
+
+    #[test]
+    fn headers_without_separator() {
+        let entity = Entity::parse("Content-Type: text/plain\n#cloud-config\n");
+        assert_eq!(1, entity.headers.len());
+        assert_eq!("#cloud-config\n", entity.body);
+    }
+
+    #[test]
+    fn quoted_printable() {
+        assert_eq!(
+            b"a=b caf\xc3\xa9\nlong line".to_vec(),
+            decode_quoted_printable("a=3Db caf=C3=A9\nlong =\nline")
+        );
+    }
+}
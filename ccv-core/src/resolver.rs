@@ -0,0 +1,592 @@
+//! Resolution of the `$ref`s of a JSON Schema into a single, self-contained schema.
+//!
+//! References are inlined, so that keyword locations reported by the validator point into the
+//! resolved schema. Recursive references cannot be inlined: the first expansion of a recursive
+//! subschema is inlined and the references back to it are rewritten to point to a copy stored in
+//! the root `definitions`. The document and pointer each inlined subschema comes from are recorded
+//! as its [`Origins`].
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use futures::{future::BoxFuture, FutureExt};
+use percent_encoding::percent_decode_str;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::error::{Error, Result};
+use crate::schema::SchemaSource;
+use crate::span::escape;
+
+const REF: &str = "$ref";
+const ID: &str = "$id";
+const DEFS: &str = "$defs";
+const DEFINITIONS: &str = "definitions";
+/// Keywords whose values map names, rather than keywords, to subschemas.
+const NAMED_SUBSCHEMAS: [&str; 2] = ["properties", "patternProperties"];
+
+/// A schema document and its base URI.
+#[derive(Debug, Clone)]
+struct Document {
+    url: Option<Url>,
+    value: Arc<Value>,
+}
+
+impl Document {
+    /// The base URI is declared by `$id`, relative to the retrieval `url`, or is `url` itself.
+    fn new(url: Option<Url>, value: Value) -> Self {
+        let id = value
+            .get(ID)
+            .and_then(Value::as_str)
+            .and_then(|id| match &url {
+                Some(url) => url.join(id).ok(),
+                None => Url::parse(id).ok(),
+            });
+        Self {
+            url: id.or(url).map(without_fragment),
+            value: Arc::new(value),
+        }
+    }
+}
+
+fn without_fragment(mut url: Url) -> Url {
+    url.set_fragment(None);
+    url
+}
+
+/// Absolute URIs, within the documents they were resolved from, of the subschemas inlined into a
+/// resolved schema, by their JSON pointer in the resolved schema. Subschemas of a document without
+/// a base URI have none.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct Origins(BTreeMap<String, String>);
+
+impl Origins {
+    /// Absolute URI of the keyword at `keyword_location`, which follows the `$ref`s to the
+    /// recursive subschemas of `schema`.
+    pub(crate) fn absolute_location(
+        &self,
+        schema: &Value,
+        keyword_location: &str,
+    ) -> Option<String> {
+        let mut pointer = String::new();
+        for token in keyword_location.split('/').skip(1) {
+            let reference = (token == REF)
+                .then(|| schema.pointer(&pointer)?.get(REF)?.as_str())
+                .flatten();
+            match reference.and_then(|reference| reference.strip_prefix('#')) {
+                Some(target) => pointer = target.to_string(),
+                None => {
+                    pointer.push('/');
+                    pointer.push_str(token);
+                }
+            }
+        }
+        // The closest enclosing inlined subschema.
+        let mut location = pointer.as_str();
+        loop {
+            if let Some(origin) = self.0.get(location) {
+                return Some(format!("{}{}", origin, &pointer[location.len()..]));
+            }
+            location = location.rsplit_once('/')?.0;
+        }
+    }
+}
+
+/// Resolves every `$ref` of `schema`, which was retrieved from `url`, loading the referenced
+/// documents from `source`.
+pub(crate) async fn resolve(
+    source: &dyn SchemaSource,
+    schema: Value,
+    url: Option<Url>,
+) -> Result<(Value, Origins)> {
+    let mut resolver = Resolver {
+        source,
+        documents: HashMap::new(),
+        expanding: vec![],
+        recursive: HashMap::new(),
+        definitions: Map::new(),
+        origins: Origins::default(),
+    };
+    let root = Document::new(url, schema);
+    if let Some(url) = &root.url {
+        resolver.documents.insert(url.clone(), root.clone());
+    }
+
+    let mut resolved = resolver
+        .resolve_target(root, String::new(), String::new())
+        .await?;
+    if !resolver.definitions.is_empty() {
+        if let Value::Object(obj) = &mut resolved {
+            obj.insert(DEFINITIONS.to_string(), Value::Object(resolver.definitions));
+        }
+    }
+    Ok((resolved, resolver.origins))
+}
+
+struct Resolver<'a> {
+    source: &'a dyn SchemaSource,
+    /// Documents loaded during this resolution, by base URI.
+    documents: HashMap<Url, Document>,
+    /// Subschemas being expanded, identified by their absolute URI.
+    expanding: Vec<String>,
+    /// Names within the root `definitions` of the recursive subschemas.
+    recursive: HashMap<String, String>,
+    definitions: Map<String, Value>,
+    origins: Origins,
+}
+
+impl<'a> Resolver<'a> {
+    async fn document(&mut self, url: Url) -> Result<Document> {
+        let url = without_fragment(url);
+        if let Some(document) = self.documents.get(&url) {
+            return Ok(document.clone());
+        }
+        let document = Document::new(Some(url.clone()), self.source.load_ref(&url).await?);
+        if let Some(base) = &document.url {
+            self.documents.insert(base.clone(), document.clone());
+        }
+        self.documents.insert(url, document.clone());
+        Ok(document)
+    }
+
+    /// The document and JSON pointer `reference` points to, relative to `document`.
+    async fn locate(&mut self, document: &Document, reference: &str) -> Result<(Document, String)> {
+        let invalid = || Error::InvalidSchemaRef {
+            r#ref: reference.to_owned(),
+        };
+        let (uri, fragment) = reference.split_once('#').unwrap_or((reference, ""));
+        let document = if uri.is_empty() {
+            document.clone()
+        } else {
+            let url = match &document.url {
+                Some(base) => base.join(uri),
+                None => Url::parse(uri),
+            }
+            .map_err(|_| invalid())?;
+            self.document(url).await?
+        };
+
+        let pointer = percent_decode_str(fragment)
+            .decode_utf8()
+            .map_err(|_| invalid())?;
+        // Plain-name fragments are not supported.
+        if !pointer.is_empty() && !pointer.starts_with('/') {
+            return Err(invalid());
+        }
+        Ok((document, pointer.into_owned()))
+    }
+
+    /// Resolves the subschema at `pointer` within `document`, inlined at `location` of the
+    /// resolved schema.
+    fn resolve_target(
+        &mut self,
+        document: Document,
+        pointer: String,
+        location: String,
+    ) -> BoxFuture<'_, Result<Value>> {
+        async move {
+            let uri = format!(
+                "{}#{}",
+                document.url.as_ref().map_or("", Url::as_str),
+                pointer
+            );
+            if self.expanding.contains(&uri) {
+                let name = self.definition_name(&uri, &pointer);
+                let mut reference = Map::new();
+                reference.insert(
+                    REF.to_string(),
+                    Value::String(format!("#/{}/{}", DEFINITIONS, name)),
+                );
+                return Ok(Value::Object(reference));
+            }
+
+            let target = document
+                .value
+                .pointer(&pointer)
+                .ok_or_else(|| Error::SchemaRefNotFound { r#ref: uri.clone() })?
+                .clone();
+            let target = match (pointer.is_empty(), target) {
+                // The base URI has been applied already.
+                (true, Value::Object(mut obj)) => {
+                    obj.remove(ID);
+                    Value::Object(obj)
+                }
+                (_, target) => target,
+            };
+
+            if document.url.is_some() {
+                self.origins.0.insert(location.clone(), uri.clone());
+            }
+            self.expanding.push(uri.clone());
+            let resolved = self.resolve_schema(&document, target, &location).await;
+            self.expanding.pop();
+            let resolved = resolved?;
+
+            if let Some(name) = self.recursive.get(&uri) {
+                self.definitions.insert(name.clone(), resolved.clone());
+                // The copy has the origins of the first expansion.
+                let definition = format!("/{}/{}", DEFINITIONS, name);
+                let copied: Vec<_> = self
+                    .origins
+                    .0
+                    .range(location.clone()..)
+                    .take_while(|(at, _)| at.starts_with(&location))
+                    .filter_map(|(at, origin)| {
+                        let rest = &at[location.len()..];
+                        (rest.is_empty() || rest.starts_with('/'))
+                            .then(|| (format!("{}{}", definition, rest), origin.clone()))
+                    })
+                    .collect();
+                self.origins.0.extend(copied);
+            }
+            Ok(resolved)
+        }
+        .boxed()
+    }
+
+    /// Resolves `schema`, a subschema of `document`, inlined at `location` of the resolved schema.
+    fn resolve_schema<'b>(
+        &'b mut self,
+        document: &'b Document,
+        schema: Value,
+        location: &'b str,
+    ) -> BoxFuture<'b, Result<Value>> {
+        async move {
+            match schema {
+                Value::Object(obj) => {
+                    // Keywords next to `$ref` are ignored, as in draft 4.
+                    if let Some(Value::String(reference)) = obj.get(REF) {
+                        let (target_document, pointer) = self.locate(document, reference).await?;
+                        return self
+                            .resolve_target(target_document, pointer, location.to_owned())
+                            .await;
+                    }
+
+                    let mut resolved = Map::with_capacity(obj.len());
+                    for (key, value) in obj {
+                        match (key.as_str(), value) {
+                            // Referenced definitions are inlined.
+                            (DEFS | DEFINITIONS, _) => {}
+                            (keyword, Value::Object(subschemas))
+                                if NAMED_SUBSCHEMAS.contains(&keyword) =>
+                            {
+                                let mut named = Map::with_capacity(subschemas.len());
+                                for (name, subschema) in subschemas {
+                                    let location =
+                                        format!("{}/{}/{}", location, keyword, escape(&name));
+                                    let subschema =
+                                        self.resolve_schema(document, subschema, &location).await?;
+                                    named.insert(name, subschema);
+                                }
+                                resolved.insert(key, Value::Object(named));
+                            }
+                            (_, value) => {
+                                let location = format!("{}/{}", location, escape(&key));
+                                let value = self.resolve_schema(document, value, &location).await?;
+                                resolved.insert(key, value);
+                            }
+                        }
+                    }
+                    Ok(Value::Object(resolved))
+                }
+                Value::Array(arr) => {
+                    let mut resolved = Vec::with_capacity(arr.len());
+                    for (i, item) in arr.into_iter().enumerate() {
+                        let location = format!("{}/{}", location, i);
+                        resolved.push(self.resolve_schema(document, item, &location).await?);
+                    }
+                    Ok(Value::Array(resolved))
+                }
+                _ => Ok(schema),
+            }
+        }
+        .boxed()
+    }
+
+    /// Name of the recursive subschema `uri` within the root `definitions`, after the last
+    /// token of its `pointer`.
+    fn definition_name(&mut self, uri: &str, pointer: &str) -> String {
+        if let Some(name) = self.recursive.get(uri) {
+            return name.clone();
+        }
+        let token = pointer.rsplit('/').next().unwrap_or_default();
+        let base: String = match token {
+            "" => "root".to_string(),
+            token => token
+                .replace("~1", "/")
+                .replace("~0", "~")
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect(),
+        };
+        let mut name = base.clone();
+        let mut suffix = 1;
+        while self.recursive.values().any(|taken| *taken == name) {
+            suffix += 1;
+            name = format!("{}_{}", base, suffix);
+        }
+        self.recursive.insert(uri.to_owned(), name.clone());
+        name
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::schema::{ConfigKind, FileSource, Schema};
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    /// Serves hand-written documents, counting how many times each is loaded.
+    #[derive(Debug, Default)]
+    struct Documents {
+        documents: HashMap<String, Value>,
+        loads: Mutex<HashMap<String, usize>>,
+    }
+
+    impl Documents {
+        fn new(documents: &[(&str, Value)]) -> Self {
+            Self {
+                documents: documents
+                    .iter()
+                    .map(|(url, value)| (url.to_string(), value.clone()))
+                    .collect(),
+                ..Self::default()
+            }
+        }
+
+        fn loads(&self, url: &str) -> usize {
+            self.loads.lock().unwrap().get(url).copied().unwrap_or(0)
+        }
+    }
+
+    #[async_trait]
+    impl SchemaSource for Documents {
+        async fn load(&self, _kind: &ConfigKind) -> Result<Value> {
+            unreachable!("resolve is called with the root schema")
+        }
+
+        async fn load_ref(&self, url: &Url) -> Result<Value> {
+            *self
+                .loads
+                .lock()
+                .unwrap()
+                .entry(url.to_string())
+                .or_default() += 1;
+            self.documents
+                .get(url.as_str())
+                .cloned()
+                .ok_or_else(|| Error::SchemaRefNotFound {
+                    r#ref: url.to_string(),
+                })
+        }
+    }
+
+    async fn resolve_at(source: &Documents, url: &str, schema: Value) -> Result<(Value, Origins)> {
+        resolve(source, schema, Some(url.parse().unwrap())).await
+    }
+
+    #[tokio::test]
+    async fn local_refs() {
+        let schema = json!({
+            "$defs": {"a/b": {"type": "string"}, "c d": {"type": "integer"}},
+            "definitions": {"e~f": {"$ref": "#/$defs/c%20d"}},
+            "properties": {
+                "x": {"$ref": "#/$defs/a~1b"},
+                "y": {"$ref": "#/definitions/e~0f", "description": "ignored"},
+                "$ref": {"type": "null"}
+            }
+        });
+        let (resolved, _) = resolve(&Documents::default(), schema, None).await.unwrap();
+        assert_eq!(
+            json!({"properties": {
+                "x": {"type": "string"},
+                "y": {"type": "integer"},
+                "$ref": {"type": "null"}
+            }}),
+            resolved
+        );
+    }
+
+    #[tokio::test]
+    async fn remote_refs_with_their_own_defs() {
+        let source = Documents::new(&[
+            (
+                "https://example.com/schemas/common.json",
+                json!({
+                    "$defs": {
+                        "name": {"$ref": "#/$defs/string"},
+                        "string": {"type": "string", "minLength": 1},
+                        "port": {"$ref": "other/port.json"}
+                    }
+                }),
+            ),
+            (
+                "https://example.com/schemas/other/port.json",
+                json!({"type": "integer"}),
+            ),
+        ]);
+        let schema = json!({
+            "$defs": {"string": {"type": "null"}},
+            "properties": {
+                "name": {"$ref": "common.json#/$defs/name"},
+                "port": {"$ref": "https://example.com/schemas/common.json#/$defs/port"},
+                "local": {"$ref": "#/$defs/string"}
+            }
+        });
+
+        let (resolved, origins) =
+            resolve_at(&source, "https://example.com/schemas/root.json", schema)
+                .await
+                .unwrap();
+        assert_eq!(
+            json!({"properties": {
+                "name": {"type": "string", "minLength": 1},
+                "port": {"type": "integer"},
+                "local": {"type": "null"}
+            }}),
+            resolved
+        );
+        assert_eq!(1, source.loads("https://example.com/schemas/common.json"));
+        let absolute = |keyword_location| origins.absolute_location(&resolved, keyword_location);
+        assert_eq!(
+            Some("https://example.com/schemas/common.json#/$defs/string/minLength"),
+            absolute("/properties/name/minLength").as_deref()
+        );
+        assert_eq!(
+            Some("https://example.com/schemas/other/port.json#/type"),
+            absolute("/properties/port/type").as_deref()
+        );
+        assert_eq!(
+            Some("https://example.com/schemas/root.json#/$defs/string/type"),
+            absolute("/properties/local/type").as_deref()
+        );
+        assert_eq!(
+            Some("https://example.com/schemas/root.json#/properties"),
+            absolute("/properties").as_deref()
+        );
+        assert_eq!(
+            1,
+            source.loads("https://example.com/schemas/other/port.json")
+        );
+    }
+
+    #[tokio::test]
+    async fn base_uri_from_id() {
+        let source = Documents::new(&[(
+            "https://example.com/v2/common.json",
+            json!({"type": "boolean"}),
+        )]);
+        let schema = json!({
+            "$id": "https://example.com/v2/root.json",
+            "items": {"$ref": "common.json"}
+        });
+
+        let (resolved, _) = resolve_at(&source, "https://mirror.example.org/root.json", schema)
+            .await
+            .unwrap();
+        assert_eq!(json!({"items": {"type": "boolean"}}), resolved);
+    }
+
+    #[tokio::test]
+    async fn recursive_refs() {
+        let schema = json!({
+            "$defs": {
+                "node": {
+                    "type": "object",
+                    "properties": {"children": {"type": "array", "items": {"$ref": "#/$defs/node"}}}
+                }
+            },
+            "properties": {"tree": {"$ref": "#/$defs/node"}}
+        });
+
+        let (resolved, origins) = resolve_at(
+            &Documents::default(),
+            "https://example.com/tree.json",
+            schema,
+        )
+        .await
+        .unwrap();
+        let node = json!({
+            "type": "object",
+            "properties": {"children": {"type": "array", "items": {"$ref": "#/definitions/node"}}}
+        });
+        assert_eq!(
+            json!({"properties": {"tree": node}, "definitions": {"node": node}}),
+            resolved
+        );
+
+        // Through the `$ref`s to the copy of the recursive subschema.
+        assert_eq!(
+            Some("https://example.com/tree.json#/$defs/node/properties/children/items"),
+            origins
+                .absolute_location(
+                    &resolved,
+                    "/properties/tree/properties/children/items/$ref/properties/children/items"
+                )
+                .as_deref()
+        );
+        assert_eq!(
+            None,
+            resolve(&Documents::default(), json!({"type": "null"}), None)
+                .await
+                .unwrap()
+                .1
+                .absolute_location(&json!({"type": "null"}), "/type")
+        );
+
+        let compiled = jsonschema::JSONSchema::compile(&resolved).unwrap();
+        assert!(compiled.is_valid(&json!({"tree": {"children": [{"children": []}]}})));
+        assert!(!compiled.is_valid(&json!({"tree": {"children": [{"children": [1]}]}})));
+    }
+
+    #[tokio::test]
+    async fn invalid_refs() {
+        let error = resolve(
+            &Documents::default(),
+            json!({"$ref": "#/$defs/missing"}),
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(error, Error::SchemaRefNotFound { r#ref } if r#ref == "#/$defs/missing"));
+
+        let error = resolve(&Documents::default(), json!({"$ref": "#anchor"}), None)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::InvalidSchemaRef { r#ref } if r#ref == "#anchor"));
+
+        let error = resolve(
+            &Documents::default(),
+            json!({"$ref": "relative.json"}),
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(error, Error::InvalidSchemaRef { r#ref } if r#ref == "relative.json"));
+    }
+
+    /// The vendored cloud-config schema is `versions.schema.cloud-config.resolved.json` with its
+    /// `$defs` inlined.
+    #[tokio::test]
+    async fn vendored_cloud_config() {
+        let schemas_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../schemas");
+        let source =
+            FileSource::new(schemas_dir.join("versions.schema.cloud-config.resolved.json"));
+
+        let schema = Schema::from_source(&source, ConfigKind::CloudConfig)
+            .await
+            .unwrap();
+        assert_eq!(Schema::from_vendored().unwrap().schema(), schema.schema());
+    }
+}
@@ -1,25 +1,46 @@
-use std::{fmt::Display, str::FromStr, sync::Arc};
+use std::{
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
 
 use crate::error::{self, Result};
-use async_recursion::async_recursion;
-use futures::future::join_all;
-use futures::lock::Mutex;
+use crate::lifecycle::CloudInitVersion;
+use crate::resolver::{self, Origins};
+use async_trait::async_trait;
 use reqwest::Url;
-use serde_json::{Map, Value};
+use serde::Deserialize;
+use serde_json::Value;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ConfigKind {
     CloudConfig,
+    /// Network-config version 1.
     NetworkConfig,
+    /// Network-config version 2, netplan-style.
+    NetworkConfigV2,
 }
 
+/// Location of the JSON Schemas within a cloud-init source checkout.
+const CLOUD_INIT_SCHEMAS_DIR: &str = "cloudinit/config/schemas";
+const CLOUD_INIT_SCHEMAS_URL: &str =
+    "https://raw.githubusercontent.com/canonical/cloud-init/main/cloudinit/config/schemas/";
+
 impl ConfigKind {
-    fn url(&self) -> &str {
+    /// Name of the root schema document within cloud-init's schemas directory.
+    fn file_name(&self) -> &str {
         match self {
-            Self::CloudConfig => "https://raw.githubusercontent.com/canonical/cloud-init/main/cloudinit/config/schemas/versions.schema.cloud-config.json",
-            Self::NetworkConfig => "https://raw.githubusercontent.com/canonical/cloud-init/main/cloudinit/config/schemas/schema-network-config-v1.json",
+            Self::CloudConfig => "versions.schema.cloud-config.json",
+            Self::NetworkConfig => "schema-network-config-v1.json",
+            Self::NetworkConfigV2 => "schema-network-config-v2.json",
         }
     }
+
+    fn url(&self) -> String {
+        format!("{}{}", CLOUD_INIT_SCHEMAS_URL, self.file_name())
+    }
 }
 
 impl FromStr for ConfigKind {
@@ -29,6 +50,7 @@ impl FromStr for ConfigKind {
         match s.to_ascii_lowercase().as_str() {
             "cloudconfig" => Ok(Self::CloudConfig),
             "networkconfig" => Ok(Self::NetworkConfig),
+            "networkconfigv2" => Ok(Self::NetworkConfigV2),
             _ => Err(format!("Not a valid str variant: {}", s)),
         }
     }
@@ -39,142 +61,578 @@ impl Display for ConfigKind {
         match self {
             Self::CloudConfig => write!(f, "cloudconfig"),
             Self::NetworkConfig => write!(f, "networkconfig"),
+            Self::NetworkConfigV2 => write!(f, "networkconfigv2"),
+        }
+    }
+}
+
+/// Version of a network-config, which selects its [`ConfigKind`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "u64")]
+pub enum NetworkVersion {
+    #[default]
+    V1,
+    V2,
+}
+
+impl NetworkVersion {
+    /// Reads `network.version`, or a top-level `version`, as cloud-init accepts both.
+    pub fn detect(payload: &str) -> Option<Self> {
+        let config: Value = serde_yaml::from_str(payload).ok()?;
+        let config = config.get("network").unwrap_or(&config);
+        config.get("version")?.as_u64()?.try_into().ok()
+    }
+
+    pub fn kind(self) -> ConfigKind {
+        match self {
+            Self::V1 => ConfigKind::NetworkConfig,
+            Self::V2 => ConfigKind::NetworkConfigV2,
+        }
+    }
+}
+
+impl TryFrom<u64> for NetworkVersion {
+    type Error = String;
+
+    fn try_from(version: u64) -> std::result::Result<Self, Self::Error> {
+        match version {
+            1 => Ok(Self::V1),
+            2 => Ok(Self::V2),
+            _ => Err(format!("Not a valid network-config version: {}", version)),
+        }
+    }
+}
+
+impl FromStr for NetworkVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        s.parse::<u64>()
+            .map_err(|_| format!("Not a valid network-config version: {}", s))?
+            .try_into()
+    }
+}
+
+/// Where the JSON Schema documents of a [`ConfigKind`] are loaded from.
+#[async_trait]
+pub trait SchemaSource: fmt::Debug + Send + Sync {
+    /// Loads the root schema document of `kind`.
+    async fn load(&self, kind: &ConfigKind) -> Result<Value>;
+
+    /// Loads a schema document referenced by `url` from another document.
+    async fn load_ref(&self, url: &Url) -> Result<Value> {
+        fetch(url).await
+    }
+
+    /// URL the root schema of `kind` is fetched from, if the source loads it over HTTP.
+    fn url(&self, _kind: &ConfigKind) -> Option<Url> {
+        None
+    }
+
+    /// Base URI of the root schema of `kind`, against which relative references are resolved.
+    fn base_url(&self, kind: &ConfigKind) -> Option<Url> {
+        self.url(kind)
+    }
+
+    /// Whether the source loads the schema of each kind, unlike a single schema file or URL,
+    /// loaded whatever the kind.
+    fn is_per_kind(&self) -> bool {
+        true
+    }
+
+    /// Whether the source has a schema of `kind`, which bundled releases may not ship.
+    fn ships(&self, _kind: &ConfigKind) -> bool {
+        true
+    }
+}
+
+async fn fetch(url: &Url) -> Result<Value> {
+    if url.scheme() == "file" {
+        if let Ok(path) = url.to_file_path() {
+            return read_json(&path);
+        }
+    }
+    Ok(reqwest::get(url.clone())
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+fn file_url(path: &Path) -> Option<Url> {
+    Url::from_file_path(std::path::absolute(path).ok()?).ok()
+}
+
+fn read_json(path: &Path) -> Result<Value> {
+    let content = fs::read_to_string(path).map_err(|source| error::Error::ReadSchema {
+        path: path.to_owned(),
+        source,
+    })?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Fetches the schemas over HTTP, from cloud-init's `main` branch unless a URL is given.
+#[derive(Debug, Default, Clone)]
+pub struct UrlSource {
+    url: Option<Url>,
+}
+
+impl UrlSource {
+    pub fn new(url: Url) -> Self {
+        Self { url: Some(url) }
+    }
+}
+
+#[async_trait]
+impl SchemaSource for UrlSource {
+    async fn load(&self, kind: &ConfigKind) -> Result<Value> {
+        fetch(&self.url(kind).expect("always fetched over HTTP")).await
+    }
+
+    fn url(&self, kind: &ConfigKind) -> Option<Url> {
+        match &self.url {
+            Some(url) => Some(url.clone()),
+            None => Some(kind.url().parse().expect("valid cloud-init schema URL")),
         }
     }
+
+    fn is_per_kind(&self) -> bool {
+        self.url.is_none()
+    }
+}
+
+/// Reads the schema from a local JSON file, whatever the [`ConfigKind`].
+#[derive(Debug, Clone)]
+pub struct FileSource {
+    path: PathBuf,
+}
+
+impl FileSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl SchemaSource for FileSource {
+    async fn load(&self, _kind: &ConfigKind) -> Result<Value> {
+        read_json(&self.path)
+    }
+
+    fn base_url(&self, _kind: &ConfigKind) -> Option<Url> {
+        file_url(&self.path)
+    }
+
+    fn is_per_kind(&self) -> bool {
+        false
+    }
+}
+
+/// Reads the schemas from a cloud-init source checkout, or from its schemas directory.
+///
+/// References to cloud-init's schemas on GitHub are read from the same directory.
+#[derive(Debug, Clone)]
+pub struct DirectorySource {
+    dir: PathBuf,
+}
+
+impl DirectorySource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let checkout_dir = path.join(CLOUD_INIT_SCHEMAS_DIR);
+        Self {
+            dir: if checkout_dir.is_dir() {
+                checkout_dir
+            } else {
+                path
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl SchemaSource for DirectorySource {
+    async fn load(&self, kind: &ConfigKind) -> Result<Value> {
+        read_json(&self.dir.join(kind.file_name()))
+    }
+
+    async fn load_ref(&self, url: &Url) -> Result<Value> {
+        let is_cloud_init_schema = url.host_str() == Some("raw.githubusercontent.com")
+            && url
+                .path()
+                .contains(&format!("/{}/", CLOUD_INIT_SCHEMAS_DIR));
+        match url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+        {
+            Some(file_name) if is_cloud_init_schema => read_json(&self.dir.join(file_name)),
+            _ => fetch(url).await,
+        }
+    }
+
+    fn base_url(&self, kind: &ConfigKind) -> Option<Url> {
+        file_url(&self.dir.join(kind.file_name()))
+    }
+}
+
+/// Schemas bundled within this crate, which do not require network access: the ones of the latest
+/// bundled [`Release`] which ships a schema of the kind. Network-configs, which older releases
+/// have no schemas for, fall back to a snapshot of cloud-init's `main` branch.
+#[derive(Debug, Default, Clone)]
+pub struct VendoredSource;
+
+static MAIN_NETWORK_CONFIG: &str = include_str!("../../schemas/schema-network-config-v1.json");
+static MAIN_NETWORK_CONFIG_V2: &str = include_str!("../../schemas/schema-network-config-v2.json");
+
+#[async_trait]
+impl SchemaSource for VendoredSource {
+    async fn load(&self, kind: &ConfigKind) -> Result<Value> {
+        let latest = RELEASES
+            .iter()
+            .rev()
+            .find_map(|release| release.schema(kind).ok());
+        let schema = match (latest, kind) {
+            (Some(schema), _) => schema,
+            (None, ConfigKind::CloudConfig) => return Err(error::Error::NoBundledRelease),
+            (None, ConfigKind::NetworkConfig) => MAIN_NETWORK_CONFIG,
+            (None, ConfigKind::NetworkConfigV2) => MAIN_NETWORK_CONFIG_V2,
+        };
+        Ok(serde_json::from_str(schema)?)
+    }
+}
+
+/// The resolved schemas of a cloud-init release, bundled within this crate. Each release is behind
+/// a `release-<year>-<release>` cargo feature, e.g. `release-23-1`.
+#[derive(Debug, Clone, Copy)]
+pub struct Release {
+    version: &'static str,
+    cloud_config: &'static str,
+    /// Network-config schemas, which older releases do not ship.
+    network_config: Option<&'static str>,
+    network_config_v2: Option<&'static str>,
+}
+
+/// Every bundled release, oldest first. To bundle another one, run `make vendor-release
+/// RELEASE=<release>`, which resolves the schemas of its tag into `schemas/releases/<release>/`,
+/// add it below with the kinds it ships, and its feature to `Cargo.toml`, `all-releases` included.
+static RELEASES: &[Release] = &[
+    #[cfg(feature = "release-23-1")]
+    Release {
+        version: "23.1",
+        cloud_config: include_str!("../../schemas/releases/23.1/cloud-config.json"),
+        network_config: None,
+        network_config_v2: None,
+    },
+];
+
+impl Release {
+    /// Every bundled release, oldest first.
+    pub fn all() -> &'static [Release] {
+        RELEASES
+    }
+
+    /// The bundled release `version`, such as `23.4`. Point releases, such as `23.4.1`, share the
+    /// schemas of their release.
+    pub fn get(version: &str) -> Result<&'static Release> {
+        let unknown = || error::Error::UnknownRelease(version.to_string());
+        let release = version
+            .parse::<CloudInitVersion>()
+            .map_err(|_| unknown())?
+            .release();
+        RELEASES
+            .iter()
+            .find(|candidate| candidate.version() == release)
+            .ok_or_else(unknown)
+    }
+
+    pub fn latest() -> Result<&'static Release> {
+        RELEASES.last().ok_or(error::Error::NoBundledRelease)
+    }
+
+    pub fn version(&self) -> CloudInitVersion {
+        self.version.parse().expect("valid release version")
+    }
+
+    /// Whether the release ships a schema of `kind`.
+    pub fn ships(&self, kind: &ConfigKind) -> bool {
+        self.schema(kind).is_ok()
+    }
+
+    fn schema(&self, kind: &ConfigKind) -> Result<&'static str> {
+        let schema = match kind {
+            ConfigKind::CloudConfig => Some(self.cloud_config),
+            ConfigKind::NetworkConfig => self.network_config,
+            ConfigKind::NetworkConfigV2 => self.network_config_v2,
+        };
+        schema.ok_or_else(|| error::Error::UnshippedSchema {
+            release: self.version.to_string(),
+            kind: kind.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl SchemaSource for Release {
+    async fn load(&self, kind: &ConfigKind) -> Result<Value> {
+        Ok(serde_json::from_str(self.schema(kind)?)?)
+    }
+
+    fn ships(&self, kind: &ConfigKind) -> bool {
+        Release::ships(self, kind)
+    }
+}
+
+/// Builds the [`SchemaSource`] described by `spec`: `vendored`, `vendored:<release>`, an
+/// `http(s)://` URL, a cloud-init source checkout or schemas directory, or a schema file.
+pub fn source_from_spec(spec: &str) -> std::result::Result<Arc<dyn SchemaSource>, String> {
+    if spec == "vendored" {
+        return Ok(Arc::new(VendoredSource));
+    }
+    if let Some(release) = spec.strip_prefix("vendored:") {
+        let release = Release::get(release).map_err(|e| e.to_string())?;
+        return Ok(Arc::new(*release));
+    }
+    if spec.starts_with("http://") || spec.starts_with("https://") {
+        let url = spec
+            .parse()
+            .map_err(|e| format!("Invalid schema URL {}: {}", spec, e))?;
+        return Ok(Arc::new(UrlSource::new(url)));
+    }
+    let path = Path::new(spec);
+    if path.is_dir() {
+        Ok(Arc::new(DirectorySource::new(path)))
+    } else {
+        Ok(Arc::new(FileSource::new(path)))
+    }
 }
 
 #[derive(Debug)]
-pub struct Schema(serde_json::Value, ConfigKind);
+pub struct Schema(serde_json::Value, ConfigKind, Origins);
 
 impl Schema {
     pub async fn get(kind: ConfigKind) -> Result<Self> {
-        let client = reqwest::Client::new();
-        let resp = client.get(kind.url()).send().await?;
-        let schema = resp.json::<serde_json::Value>().await?;
+        Self::from_source(&UrlSource::default(), kind).await
+    }
+
+    pub async fn from_source(source: &dyn SchemaSource, kind: ConfigKind) -> Result<Self> {
+        let schema = source.load(&kind).await?;
+        let (schema, origins) = resolver::resolve(source, schema, source.base_url(&kind)).await?;
+        Ok(Self(schema, kind, origins))
+    }
 
-        let resolver = Arc::new(Mutex::new(Resolver::new()));
-        Ok(Self(resolve(resolver, schema).await?, kind))
+    /// Wraps an already resolved schema, whose subschemas come from `origins`.
+    pub(crate) fn from_resolved(schema: Value, kind: ConfigKind, origins: Origins) -> Self {
+        Self(schema, kind, origins)
     }
 
+    /// The cloud-config schema of the latest bundled release.
     pub fn from_vendored() -> Result<Self> {
-        static SCHEMA: &str =
-            include_str!("../../schemas/versions.schema.cloud-config.resolved.1.json");
-        let schema = serde_json::from_str(SCHEMA)?;
-        Ok(Self(schema, ConfigKind::CloudConfig))
+        Self::from_release(Release::latest()?.version, ConfigKind::CloudConfig)
+    }
+
+    /// The schema of `kind` of the bundled `release`, such as `23.4`.
+    pub fn from_release(release: &str, kind: ConfigKind) -> Result<Self> {
+        let schema = serde_json::from_str(Release::get(release)?.schema(&kind)?)?;
+        // Bundled schemas are resolved already, from documents unknown.
+        Ok(Self(schema, kind, Origins::default()))
     }
 
     pub fn schema(&self) -> &Value {
         &self.0
     }
-}
 
-const REF: &str = "$ref";
-const DEFS: &str = "$defs";
+    pub fn kind(&self) -> &ConfigKind {
+        &self.1
+    }
 
-struct Resolver {
-    client: reqwest::Client,
-    defs: Arc<Mutex<Option<serde_json::Value>>>,
-}
+    pub(crate) fn origins(&self) -> &Origins {
+        &self.2
+    }
 
-impl Resolver {
-    fn new() -> Self {
-        Resolver {
-            client: reqwest::Client::new(),
-            defs: Arc::new(Mutex::new(None)),
-        }
+    /// Absolute URI of the keyword at `keyword_location` within the document it was resolved
+    /// from, if known.
+    pub fn absolute_location(&self, keyword_location: &str) -> Option<String> {
+        self.2.absolute_location(&self.0, keyword_location)
     }
 
-    async fn get(&self, url: &str) -> Result<Value> {
-        // XXX: value type encoding both cases
-        match url.parse::<Url>() {
-            Ok(url) => Ok(self.client.get(url).send().await?.json().await?),
-            Err(_) => {
-                let pointer = if url.starts_with("#/$defs") {
-                    let (_, pointer) = url.split_once("#/$defs").expect("Cannot panic");
-                    pointer
-                } else {
-                    url
-                };
-
-                match self.get_def(pointer).await {
-                    None => Err(crate::error::Error::LocalSchemaRefNotFound {
-                        r#ref: pointer.to_owned(),
-                    }),
-                    Some(val) => Ok(val),
-                }
+    /// Description of the closest subschema enclosing the keyword at `keyword_location`.
+    pub fn description(&self, keyword_location: &str) -> Option<&str> {
+        let mut location = keyword_location;
+        while let Some((parent, _)) = location.rsplit_once('/') {
+            location = parent;
+            let description = self
+                .0
+                .pointer(location)
+                .and_then(|subschema| subschema.get("description"))
+                .and_then(Value::as_str);
+            if description.is_some() {
+                return description;
             }
         }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn write_json(path: &Path, value: &Value) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, value.to_string()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn vendored_network_config() {
+        let schema = Schema::from_source(&VendoredSource, ConfigKind::NetworkConfig)
+            .await
+            .expect("valid schema");
+        assert!(matches!(schema.kind(), ConfigKind::NetworkConfig));
+        assert!(!schema.schema().to_string().contains("$ref"));
     }
 
-    async fn set_defs(&self, defs: Value) {
-        *self.defs.lock().await = Some(defs);
+    #[test]
+    fn detect_network_version() {
+        let detect = NetworkVersion::detect;
+        assert_eq!(Some(NetworkVersion::V1), detect("network:\n  version: 1\n"));
+        assert_eq!(Some(NetworkVersion::V2), detect("network:\n  version: 2\n"));
+        assert_eq!(
+            Some(NetworkVersion::V2),
+            detect("version: 2\nethernets: {}\n")
+        );
+        assert_eq!(None, detect("network:\n  version: 3\n"));
+        assert_eq!(None, detect("network: {}\n"));
+        assert_eq!(None, detect("\"a"));
+        assert_eq!(Ok(NetworkVersion::V2), "2".parse());
+        assert!("v2".parse::<NetworkVersion>().is_err());
     }
 
-    async fn get_def(&self, key: &str) -> Option<Value> {
-        match &*self.defs.lock().await {
-            Some(defs) => defs.pointer(key).cloned(),
-            None => None,
+    #[tokio::test]
+    async fn directory_source() {
+        let checkout = tempfile::tempdir().unwrap();
+        let schemas_dir = checkout.path().join(CLOUD_INIT_SCHEMAS_DIR);
+        write_json(
+            &schemas_dir.join("versions.schema.cloud-config.json"),
+            &json!({"oneOf": [{"$ref": format!("{}schema-cloud-config-v1.json", CLOUD_INIT_SCHEMAS_URL)}]}),
+        );
+        write_json(
+            &schemas_dir.join("schema-cloud-config-v1.json"),
+            &json!({"properties": {"runcmd": {"type": "array"}}}),
+        );
+
+        for path in [checkout.path(), &schemas_dir] {
+            let schema = Schema::from_source(&DirectorySource::new(path), ConfigKind::CloudConfig)
+                .await
+                .expect("valid schema");
+            assert_eq!(
+                &json!({"oneOf": [{"properties": {"runcmd": {"type": "array"}}}]}),
+                schema.schema()
+            );
         }
     }
-}
 
-#[async_recursion]
-async fn resolve(
-    resolver: Arc<Mutex<Resolver>>,
-    schema: serde_json::Value,
-) -> Result<serde_json::Value> {
-    // TODO: clean up
-    match schema {
-        serde_json::Value::Object(mut obj) => {
-            let mut new_obj = Map::with_capacity(obj.len());
+    #[tokio::test]
+    async fn file_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("schema.json");
+        write_json(&path, &json!({"type": "object"}));
+
+        let schema = Schema::from_source(&FileSource::new(&path), ConfigKind::NetworkConfig)
+            .await
+            .expect("valid schema");
+        assert_eq!(&json!({"type": "object"}), schema.schema());
 
-            // set definitions
-            if let Some(defs) = obj.remove(DEFS) {
-                resolver.lock().await.set_defs(defs).await;
-            }
+        let missing = dir.path().join("missing.json");
+        let error = Schema::from_source(&FileSource::new(&missing), ConfigKind::CloudConfig)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, error::Error::ReadSchema { path, .. } if path == missing));
+    }
+
+    #[test]
+    fn sources_from_spec() {
+        let dir = tempfile::tempdir().unwrap();
+        let spec = |spec: &str| format!("{:?}", source_from_spec(spec).unwrap());
+
+        assert_eq!("VendoredSource", spec("vendored"));
+        let per_kind = |spec: &str| source_from_spec(spec).unwrap().is_per_kind();
+        assert!(per_kind("vendored"));
+        assert!(per_kind(dir.path().to_str().unwrap()));
+        assert!(!per_kind("https://example.com/schema.json"));
+        assert!(!per_kind("schema.json"));
+        assert!(spec("https://example.com/schema.json").starts_with("UrlSource"));
+        assert!(spec(dir.path().to_str().unwrap()).starts_with("DirectorySource"));
+        assert!(spec("schema.json").starts_with("FileSource"));
+        #[cfg(feature = "release-23-1")]
+        assert!(spec("vendored:23.1").starts_with("Release"));
+        assert!(source_from_spec("https://[::1").is_err());
+        assert!(source_from_spec("vendored:9.1").is_err());
+    }
+
+    #[cfg(feature = "release-23-1")]
+    #[tokio::test]
+    async fn releases() {
+        let release = Release::get("23.1").unwrap();
+        assert_eq!(
+            "23.1".parse::<CloudInitVersion>().unwrap(),
+            release.version()
+        );
+        assert_eq!(release.version(), Release::get("23.1.2").unwrap().version());
+        assert!(matches!(
+            Release::get("9.1"),
+            Err(error::Error::UnknownRelease(version)) if version == "9.1"
+        ));
+        assert!(Release::get("main").is_err());
+        assert!(Release::all()
+            .windows(2)
+            .all(|releases| releases[0].version() < releases[1].version()));
+
+        assert!(release.ships(&ConfigKind::CloudConfig));
+        assert!(!release.ships(&ConfigKind::NetworkConfig));
+        assert!(matches!(
+            Schema::from_release("23.1", ConfigKind::NetworkConfigV2),
+            Err(error::Error::UnshippedSchema {
+                release,
+                kind: ConfigKind::NetworkConfigV2,
+            }) if release == "23.1"
+        ));
 
-            for (mut key, mut val) in obj.into_iter() {
-                if key == REF {
-                    if let Value::String(val_str) = val.clone() {
-                        if key.starts_with("#/$defs") {
-                            let (_, pointer) = val_str.split_once("#/$defs").expect("Cannot panic");
-                            let (_, new_key) = match pointer.rsplit_once('/') {
-                                Some(x) => x,
-                                None => {
-                                    return Err(error::Error::LocalSchemaRefInvalidName {
-                                        r#ref: val_str.to_owned(),
-                                    });
-                                }
-                            };
-                            key = new_key.to_string();
-                            val = resolver.lock().await.get(&val_str).await?;
-                        } else {
-                            val = resolver.lock().await.get(&val_str).await?;
-                            return resolve(resolver.clone(), val).await;
-                        }
-                    }
-                    new_obj.insert(key, resolve(resolver.clone(), val).await?);
-                } else {
-                    new_obj.insert(key, resolve(resolver.clone(), val).await?);
+        // Bundled schemas are resolved already.
+        for release in Release::all() {
+            for kind in [
+                ConfigKind::CloudConfig,
+                ConfigKind::NetworkConfig,
+                ConfigKind::NetworkConfigV2,
+            ] {
+                if !release.ships(&kind) {
+                    continue;
                 }
+                let bundled = Schema::from_release(release.version, kind.clone()).unwrap();
+                let resolved = Schema::from_source(release, kind).await.unwrap();
+                assert_eq!(resolved.schema(), bundled.schema());
             }
-            Ok(serde_json::Value::Object(new_obj))
         }
-        serde_json::Value::Array(arr) => {
-            let mut new_arr = Vec::with_capacity(arr.len());
-            for item in join_all(arr.into_iter().map(|v| resolve(resolver.clone(), v))).await {
-                new_arr.push(item?);
-            }
+        assert_eq!(
+            Schema::from_vendored().unwrap().schema(),
+            Schema::from_release(Release::latest().unwrap().version, ConfigKind::CloudConfig)
+                .unwrap()
+                .schema()
+        );
+    }
 
-            Ok(serde_json::Value::Array(new_arr))
+    #[tokio::test]
+    async fn vendored() {
+        // Network-configs fall back to the snapshot of cloud-init's `main` branch.
+        for kind in [ConfigKind::NetworkConfig, ConfigKind::NetworkConfigV2] {
+            let schema = Schema::from_source(&VendoredSource, kind).await.unwrap();
+            assert!(schema.schema().is_object());
         }
-        _ => Ok(schema),
+        assert_eq!(
+            Release::all().is_empty(),
+            VendoredSource.load(&ConfigKind::CloudConfig).await.is_err()
+        );
     }
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
 
     #[tokio::test]
     async fn fetch() {
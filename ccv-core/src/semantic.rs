@@ -0,0 +1,81 @@
+//! Checks of what the schemas cannot express: values cloud-init fails to decode or parse, and
+//! configurations which are well-formed but do not do what they seem to.
+//!
+//! Checks run on the instance as loaded, valid or not, and skip the values which are not of the
+//! type the schema requires, already reported by the schema validation.
+use serde_json::Value;
+
+use crate::code::{Code, Severity};
+use crate::schema::ConfigKind;
+use crate::validator::{ConfigAnnotation, ConfigError};
+
+mod network;
+mod packages;
+mod storage;
+mod users;
+mod write_files;
+
+/// The errors and warnings of the checks of an instance.
+#[derive(Debug, Default)]
+pub(crate) struct Findings {
+    pub(crate) errors: Vec<ConfigError>,
+    pub(crate) annotations: Vec<ConfigAnnotation>,
+}
+
+impl Findings {
+    /// Reports a problem of the value at `instance_path`, as an error or an annotation after the
+    /// severity of `code`.
+    fn push(&mut self, code: Code, description: String, instance_path: String) {
+        match code.severity() {
+            Severity::Error => {
+                self.errors
+                    .push(ConfigError::new(code, description, instance_path, None))
+            }
+            _ => self.annotations.push(ConfigAnnotation::new(
+                code,
+                description,
+                instance_path,
+                None,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Findings {
+    /// The code, instance path and description of every error, then of every annotation.
+    fn summary(&self) -> Vec<(Code, String, String)> {
+        let errors = self.errors.iter().map(|error| {
+            (
+                error.code(),
+                error.instance_path().to_string(),
+                error.description().to_string(),
+            )
+        });
+        let annotations = self.annotations.iter().map(|annotation| {
+            (
+                annotation.code(),
+                annotation.instance_path().to_string(),
+                annotation.description().to_string(),
+            )
+        });
+        errors.chain(annotations).collect()
+    }
+}
+
+/// Checks an instance of `kind`.
+pub(crate) fn check(kind: &ConfigKind, instance: &Value) -> Findings {
+    let mut findings = Findings::default();
+    if let ConfigKind::CloudConfig = kind {
+        if let Some(Value::Array(files)) = instance.get("write_files") {
+            write_files::check(files, &mut findings);
+        }
+        users::check(instance, &mut findings);
+        packages::check(instance, &mut findings);
+        storage::check(instance, &mut findings);
+    }
+    if let ConfigKind::NetworkConfig = kind {
+        network::check(instance, &mut findings);
+    }
+    findings
+}
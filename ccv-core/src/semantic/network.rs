@@ -0,0 +1,839 @@
+//! Network configurations of version 1, whose addresses, links and MTUs cloud-init passes on to
+//! netplan, networkd or ifupdown.
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use serde_json::{Map, Value};
+
+use super::Findings;
+use crate::code::Code;
+
+/// Types of the items of `config` which declare an interface.
+const INTERFACE_TYPES: &[&str] = &["bond", "bridge", "physical", "vlan"];
+
+/// The smallest MTU of IPv4 links.
+const MIN_MTU: i64 = 68;
+
+/// The smallest MTU of IPv6 links, under which the kernel disables IPv6 on them.
+const MIN_IPV6_MTU: i64 = 1280;
+
+const MAX_MTU: i64 = 65535;
+
+/// An IP version.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Family {
+    V4,
+    V6,
+}
+
+impl Family {
+    fn of(address: IpAddr) -> Self {
+        match address {
+            IpAddr::V4(_) => Self::V4,
+            IpAddr::V6(_) => Self::V6,
+        }
+    }
+
+    /// The family a subnet of type `kind` configures, if a single one.
+    fn of_subnet(kind: &str) -> Option<Self> {
+        match kind {
+            "dhcp" | "dhcp4" => Some(Self::V4),
+            "dhcp6"
+            | "static6"
+            | "ipv6_dhcpv6-stateful"
+            | "ipv6_dhcpv6-stateless"
+            | "ipv6_slaac" => Some(Self::V6),
+            _ => None,
+        }
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            Self::V4 => 32,
+            Self::V6 => 128,
+        }
+    }
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::V4 => write!(f, "IPv4"),
+            Self::V6 => write!(f, "IPv6"),
+        }
+    }
+}
+
+/// An address and the length of the prefix of its network.
+#[derive(Debug, Clone, Copy)]
+struct Network {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl Network {
+    fn family(&self) -> Family {
+        Family::of(self.address)
+    }
+
+    fn mask(&self) -> u128 {
+        let width = self.family().bits();
+        let all = u128::MAX >> (128 - width);
+        let host = if self.prefix >= width {
+            0
+        } else {
+            all >> self.prefix
+        };
+        all & !host
+    }
+
+    fn contains(&self, address: IpAddr) -> bool {
+        Family::of(address) == self.family()
+            && bits(address) & self.mask() == bits(self.address) & self.mask()
+    }
+
+    fn has_host_bits(&self) -> bool {
+        bits(self.address) & !self.mask() & (u128::MAX >> (128 - self.family().bits())) != 0
+    }
+}
+
+impl fmt::Display for Network {
+    /// The network, without the host bits of its address.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let network = bits(self.address) & self.mask();
+        let network = match self.family() {
+            Family::V4 => IpAddr::V4(Ipv4Addr::from(network as u32)),
+            Family::V6 => IpAddr::V6(Ipv6Addr::from(network)),
+        };
+        write!(f, "{}/{}", network, self.prefix)
+    }
+}
+
+fn bits(address: IpAddr) -> u128 {
+    match address {
+        IpAddr::V4(address) => u32::from(address) as u128,
+        IpAddr::V6(address) => u128::from(address),
+    }
+}
+
+pub(super) fn check(config: &Value, findings: &mut Findings) {
+    // Network-configs are read with or without the `network` key.
+    let (config, root) = match config.get("network") {
+        Some(network) => (network, "/network"),
+        None => (config, ""),
+    };
+    let Some(Value::Array(items)) = config.get("config") else {
+        return;
+    };
+    let items: Vec<(String, &Map<String, Value>)> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(index, item)| Some((format!("{}/config/{}", root, index), item.as_object()?)))
+        .collect();
+
+    let mut interfaces: HashMap<&str, &str> = HashMap::new();
+    for (path, item) in &items {
+        let kind = item.get("type").and_then(Value::as_str);
+        if !kind.is_some_and(|kind| INTERFACE_TYPES.contains(&kind)) {
+            continue;
+        }
+        let Some(Value::String(name)) = item.get("name") else {
+            continue;
+        };
+        match interfaces.get(name.as_str()) {
+            Some(first) => findings.push(
+                Code::Conflict,
+                format!(
+                    "Interface \"{}\" is declared before by {}, whose configuration this one \
+                     replaces",
+                    name, first
+                ),
+                format!("{}/name", path),
+            ),
+            None => {
+                interfaces.insert(name, path);
+            }
+        }
+    }
+
+    for (path, item) in &items {
+        match item.get("type").and_then(Value::as_str) {
+            Some("route") => check_route(item, path, None, findings),
+            Some("nameserver") => check_nameserver(item, path, &interfaces, findings),
+            Some(kind) if INTERFACE_TYPES.contains(&kind) => {
+                check_interface(item, path, &interfaces, findings)
+            }
+            _ => {}
+        }
+    }
+}
+
+fn check_interface(
+    interface: &Map<String, Value>,
+    path: &str,
+    interfaces: &HashMap<&str, &str>,
+    findings: &mut Findings,
+) {
+    if let Some(Value::String(mac)) = interface.get("mac_address") {
+        if !is_mac_address(mac) {
+            findings.push(
+                Code::Malformed,
+                format!(
+                    "MAC address \"{}\" is not 6 bytes, or 20 for InfiniBand, of hexadecimal \
+                     digits separated by colons",
+                    mac
+                ),
+                format!("{}/mac_address", path),
+            );
+        }
+    }
+
+    let mut check_reference = |name: &Value, description: &str, path: String| {
+        if let Value::String(name) = name {
+            if !interfaces.contains_key(name.as_str()) {
+                findings.push(
+                    Code::Reference,
+                    format!(
+                        "{} \"{}\" is not an interface of the network configuration",
+                        description, name
+                    ),
+                    path,
+                );
+            }
+        }
+    };
+    for (key, description) in [
+        ("bond_interfaces", "Bond member"),
+        ("bridge_interfaces", "Bridge port"),
+    ] {
+        if let Some(Value::Array(members)) = interface.get(key) {
+            for (index, member) in members.iter().enumerate() {
+                check_reference(member, description, format!("{}/{}/{}", path, key, index));
+            }
+        }
+    }
+    if let Some(link) = interface.get("vlan_link") {
+        check_reference(link, "VLAN link", format!("{}/vlan_link", path));
+    }
+
+    if let Some(id) = interface.get("vlan_id").and_then(Value::as_i64) {
+        if !(1..=4094).contains(&id) {
+            findings.push(
+                Code::Range,
+                format!("VLAN ID {} is not between 1 and 4094", id),
+                format!("{}/vlan_id", path),
+            );
+        }
+    }
+
+    let mut has_ipv6 = false;
+    if let Some(Value::Array(subnets)) = interface.get("subnets") {
+        for (index, subnet) in subnets.iter().enumerate() {
+            if let Value::Object(subnet) = subnet {
+                let family = check_subnet(subnet, &format!("{}/subnets/{}", path, index), findings);
+                has_ipv6 |= family == Some(Family::V6);
+            }
+        }
+    }
+
+    if let Some(mtu) = interface.get("mtu").and_then(Value::as_i64) {
+        let description = if !(MIN_MTU..=MAX_MTU).contains(&mtu) {
+            format!(
+                "MTU {} is not between {}, the minimum of IPv4, and {}",
+                mtu, MIN_MTU, MAX_MTU
+            )
+        } else if has_ipv6 && mtu < MIN_IPV6_MTU {
+            format!(
+                "MTU {} is under {}, the minimum of IPv6, which the kernel disables on the \
+                 interface",
+                mtu, MIN_IPV6_MTU
+            )
+        } else {
+            return;
+        };
+        findings.push(Code::Range, description, format!("{}/mtu", path));
+    }
+}
+
+/// Checks a subnet, returning the IP version it configures, if known.
+fn check_subnet(
+    subnet: &Map<String, Value>,
+    path: &str,
+    findings: &mut Findings,
+) -> Option<Family> {
+    let kind = subnet.get("type").and_then(Value::as_str);
+    let mut family = kind.and_then(Family::of_subnet);
+
+    let mut network = None;
+    if let Some(Value::String(address)) = subnet.get("address") {
+        match parse_network(address) {
+            Ok((address, prefix)) => {
+                let prefix = match (prefix, subnet.get("netmask").and_then(Value::as_str)) {
+                    (Some(prefix), _) => Some(prefix),
+                    (None, Some(netmask)) => match parse_prefix(netmask, Family::of(address)) {
+                        Ok(prefix) => Some(prefix),
+                        Err(description) => {
+                            findings.push(
+                                Code::Malformed,
+                                description,
+                                format!("{}/netmask", path),
+                            );
+                            None
+                        }
+                    },
+                    (None, None) => None,
+                };
+                check_family(family, kind, "Address", address, path, "address", findings);
+                family = family.or(Some(Family::of(address)));
+                network = prefix.map(|prefix| Network { address, prefix });
+            }
+            Err(description) => {
+                findings.push(Code::Malformed, description, format!("{}/address", path))
+            }
+        }
+    } else if let Some(Value::String(netmask)) = subnet.get("netmask") {
+        if let Ok(netmask) = netmask.parse::<Ipv4Addr>() {
+            check_family(
+                family,
+                kind,
+                "Netmask",
+                IpAddr::V4(netmask),
+                path,
+                "netmask",
+                findings,
+            );
+        }
+    }
+
+    for key in ["gateway", "broadcast"] {
+        let Some(Value::String(value)) = subnet.get(key) else {
+            continue;
+        };
+        let noun = if key == "gateway" {
+            "Gateway"
+        } else {
+            "Broadcast address"
+        };
+        match parse_address(value, noun) {
+            Ok(address) => {
+                check_family(family, kind, noun, address, path, key, findings);
+                if key == "gateway" {
+                    check_reachable(address, value, network.as_ref(), path, findings);
+                }
+            }
+            Err(description) => {
+                findings.push(Code::Malformed, description, format!("{}/{}", path, key))
+            }
+        }
+    }
+
+    if let Some(Value::Array(nameservers)) = subnet.get("dns_nameservers") {
+        for (index, nameserver) in nameservers.iter().enumerate() {
+            if let Value::String(nameserver) = nameserver {
+                if let Err(description) = parse_address(nameserver, "Nameserver") {
+                    findings.push(
+                        Code::Malformed,
+                        description,
+                        format!("{}/dns_nameservers/{}", path, index),
+                    );
+                }
+            }
+        }
+    }
+
+    if let Some(Value::Array(routes)) = subnet.get("routes") {
+        for (index, route) in routes.iter().enumerate() {
+            if let Value::Object(route) = route {
+                let path = format!("{}/routes/{}", path, index);
+                check_route(route, &path, network.as_ref(), findings);
+            }
+        }
+    }
+
+    family
+}
+
+/// Checks a route, of the interface with the `subnet` if any.
+fn check_route(
+    route: &Map<String, Value>,
+    path: &str,
+    subnet: Option<&Network>,
+    findings: &mut Findings,
+) {
+    // `network` is only read without `destination`.
+    let key = if route.contains_key("destination") {
+        "destination"
+    } else {
+        "network"
+    };
+    let mut destination = None;
+    if let Some(Value::String(value)) = route.get(key) {
+        match parse_network(value) {
+            Ok((address, prefix)) => {
+                let prefix = match (prefix, route.get("netmask").and_then(Value::as_str)) {
+                    (Some(prefix), _) => Some(prefix),
+                    (None, Some(netmask)) => match parse_prefix(netmask, Family::of(address)) {
+                        Ok(prefix) => Some(prefix),
+                        Err(description) => {
+                            findings.push(
+                                Code::Malformed,
+                                description,
+                                format!("{}/netmask", path),
+                            );
+                            None
+                        }
+                    },
+                    (None, None) => None,
+                };
+                if let Some(prefix) = prefix {
+                    let network = Network { address, prefix };
+                    if network.has_host_bits() {
+                        findings.push(
+                            Code::Malformed,
+                            format!(
+                                "Destination \"{}\" has host bits set, which \"ip route\" \
+                                 refuses: use \"{}\"",
+                                value, network
+                            ),
+                            format!("{}/{}", path, key),
+                        );
+                    }
+                }
+                destination = Some((value, Family::of(address)));
+            }
+            Err(description) => {
+                findings.push(Code::Malformed, description, format!("{}/{}", path, key))
+            }
+        }
+    }
+
+    let Some(Value::String(value)) = route.get("gateway") else {
+        return;
+    };
+    match parse_address(value, "Gateway") {
+        Ok(gateway) => match destination {
+            Some((destination, family)) if family != Family::of(gateway) => findings.push(
+                Code::AddressFamily,
+                format!(
+                    "Gateway \"{}\" is an {} address, but destination \"{}\" is an {} \
+                         network",
+                    value,
+                    Family::of(gateway),
+                    destination,
+                    family
+                ),
+                format!("{}/gateway", path),
+            ),
+            _ => check_reachable(gateway, value, subnet, path, findings),
+        },
+        Err(description) => {
+            findings.push(Code::Malformed, description, format!("{}/gateway", path))
+        }
+    }
+}
+
+fn check_nameserver(
+    nameserver: &Map<String, Value>,
+    path: &str,
+    interfaces: &HashMap<&str, &str>,
+    findings: &mut Findings,
+) {
+    let addresses: Vec<(&str, String)> = match nameserver.get("address") {
+        Some(Value::String(address)) => vec![(address, format!("{}/address", path))],
+        Some(Value::Array(addresses)) => addresses
+            .iter()
+            .enumerate()
+            .filter_map(|(index, address)| {
+                Some((address.as_str()?, format!("{}/address/{}", path, index)))
+            })
+            .collect(),
+        _ => vec![],
+    };
+    for (address, path) in addresses {
+        if let Err(description) = parse_address(address, "Nameserver") {
+            findings.push(Code::Malformed, description, path);
+        }
+    }
+
+    if let Some(Value::String(interface)) = nameserver.get("interface") {
+        if !interfaces.contains_key(interface.as_str()) {
+            findings.push(
+                Code::Reference,
+                format!(
+                    "Interface \"{}\" is not an interface of the network configuration",
+                    interface
+                ),
+                format!("{}/interface", path),
+            );
+        }
+    }
+}
+
+/// Reports the `address` of a subnet of type `kind`, at `key`, which is not of the IP version
+/// the subnet configures.
+fn check_family(
+    family: Option<Family>,
+    kind: Option<&str>,
+    noun: &str,
+    address: IpAddr,
+    path: &str,
+    key: &str,
+    findings: &mut Findings,
+) {
+    let Some(family) = family else {
+        return;
+    };
+    if Family::of(address) == family {
+        return;
+    }
+    let subnet = match kind {
+        Some(kind) if Family::of_subnet(kind).is_some() => {
+            format!("subnets of type \"{}\" configure {}", kind, family)
+        }
+        _ => format!("the address of the subnet is an {} one", family),
+    };
+    findings.push(
+        Code::AddressFamily,
+        format!(
+            "{} \"{}\" is an {} address, but {}",
+            noun,
+            address,
+            Family::of(address),
+            subnet
+        ),
+        format!("{}/{}", path, key),
+    );
+}
+
+/// Reports a `gateway` outside of the `subnet` of its interface.
+fn check_reachable(
+    gateway: IpAddr,
+    value: &str,
+    subnet: Option<&Network>,
+    path: &str,
+    findings: &mut Findings,
+) {
+    let Some(subnet) = subnet else {
+        return;
+    };
+    // Unspecified gateways make on-link routes, and link-local ones are reachable from any subnet.
+    let is_link_local = match gateway {
+        IpAddr::V4(gateway) => gateway.is_link_local(),
+        IpAddr::V6(gateway) => gateway.segments()[0] & 0xffc0 == 0xfe80,
+    };
+    if gateway.is_unspecified()
+        || is_link_local
+        || Family::of(gateway) != subnet.family()
+        || subnet.contains(gateway)
+    {
+        return;
+    }
+    findings.push(
+        Code::Unreachable,
+        format!(
+            "Gateway \"{}\" is outside of the subnet {}, so it is unreachable without a route \
+             to it",
+            value, subnet
+        ),
+        format!("{}/gateway", path),
+    );
+}
+
+fn is_mac_address(mac: &str) -> bool {
+    let bytes: Vec<&str> = mac.split(':').collect();
+    matches!(bytes.len(), 6 | 20)
+        && bytes
+            .iter()
+            .all(|byte| byte.len() == 2 && byte.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn parse_address(address: &str, noun: &str) -> Result<IpAddr, String> {
+    address.parse().map_err(|_| {
+        format!(
+            "{} \"{}\" is not an IPv4 nor an IPv6 address",
+            noun, address
+        )
+    })
+}
+
+/// Parses an address, with the length of the prefix of its network, or its netmask, after a
+/// `/`, as cloud-init.
+fn parse_network(network: &str) -> Result<(IpAddr, Option<u8>), String> {
+    let (address, prefix) = match network.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix)),
+        None => (network, None),
+    };
+    let address = parse_address(address, "Address")
+        .map_err(|_| format!("Address \"{}\" is not an IPv4 nor an IPv6 address", network))?;
+    let prefix = prefix
+        .map(|prefix| parse_prefix(prefix, Family::of(address)))
+        .transpose()?;
+    Ok((address, prefix))
+}
+
+/// Parses the length of a prefix, or a netmask, of a network of `family`.
+fn parse_prefix(prefix: &str, family: Family) -> Result<u8, String> {
+    if prefix.chars().all(|c| c.is_ascii_digit()) && !prefix.is_empty() {
+        return match prefix.parse::<u8>() {
+            Ok(length) if length <= family.bits() => Ok(length),
+            _ => Err(format!(
+                "Prefix length {} is longer than the {} bits of {} addresses",
+                prefix,
+                family.bits(),
+                family
+            )),
+        };
+    }
+    let mask = match (family, prefix.parse::<IpAddr>()) {
+        (Family::V4, Ok(IpAddr::V4(mask))) => u32::from(mask) as u128,
+        (Family::V6, Ok(IpAddr::V6(mask))) => u128::from(mask),
+        _ => {
+            return Err(format!(
+                "Netmask \"{}\" is neither a prefix length nor an {} netmask",
+                prefix, family
+            ))
+        }
+    };
+    let width = family.bits() as u32;
+    let ones = (mask << (128 - width)).leading_ones();
+    if ones + mask.trailing_zeros().min(width) != width {
+        return Err(format!(
+            "Netmask \"{}\" is not a run of ones followed by zeros",
+            prefix
+        ));
+    }
+    Ok(ones as u8)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn findings(config: Value) -> Vec<(Code, String, String)> {
+        let mut findings = Findings::default();
+        check(&config, &mut findings);
+        findings.summary()
+    }
+
+    #[test]
+    fn addresses() {
+        assert!(is_mac_address("52:54:00:12:34:56"));
+        assert!(is_mac_address(
+            "a0:00:02:20:fe:80:00:00:00:00:00:00:ec:0d:9a:03:00:15:e2:c1"
+        ));
+        assert!(!is_mac_address("aa:bb"));
+        assert!(!is_mac_address("52-54-00-12-34-56"));
+        assert!(!is_mac_address("52:54:00:12:34:5g"));
+
+        assert_eq!(Ok(24), parse_prefix("255.255.255.0", Family::V4));
+        assert_eq!(Ok(0), parse_prefix("0.0.0.0", Family::V4));
+        assert_eq!(Ok(32), parse_prefix("32", Family::V4));
+        assert_eq!(Ok(64), parse_prefix("ffff:ffff:ffff:ffff::", Family::V6));
+        assert_eq!(
+            Err("Netmask \"255.0.255.0\" is not a run of ones followed by zeros".to_string()),
+            parse_prefix("255.0.255.0", Family::V4)
+        );
+        assert_eq!(
+            Err("Prefix length 33 is longer than the 32 bits of IPv4 addresses".to_string()),
+            parse_prefix("33", Family::V4)
+        );
+        assert_eq!(
+            Err(
+                "Netmask \"255.255.255.0\" is neither a prefix length nor an IPv6 netmask"
+                    .to_string()
+            ),
+            parse_prefix("255.255.255.0", Family::V6)
+        );
+
+        let (address, prefix) = parse_network("10.20.0.0/8").unwrap();
+        let network = Network {
+            address,
+            prefix: prefix.unwrap(),
+        };
+        assert!(network.has_host_bits());
+        assert_eq!("10.0.0.0/8", network.to_string());
+        assert!(network.contains("10.255.0.1".parse().unwrap()));
+        assert!(!network.contains("11.0.0.1".parse().unwrap()));
+        let (address, prefix) = parse_network("2001:db8::1/64").unwrap();
+        let network = Network {
+            address,
+            prefix: prefix.unwrap(),
+        };
+        assert!(network.has_host_bits());
+        assert_eq!("2001:db8::/64", network.to_string());
+        assert_eq!(
+            Err("Address \"a.b.c.d/8\" is not an IPv4 nor an IPv6 address".to_string()),
+            parse_network("a.b.c.d/8")
+        );
+    }
+
+    #[test]
+    fn interfaces() {
+        let config = json!({"network": {"version": 1, "config": [
+            {"type": "physical", "name": "eth0", "mac_address": "52:54:00:12:34:56", "mtu": 9000},
+            {"type": "physical", "name": "eth1", "mac_address": "aa:bb"},
+            {"type": "physical", "name": "eth0"},
+            {"type": "bond", "name": "bond0", "bond_interfaces": ["eth0", "eth2"], "mtu": 1},
+            {"type": "bridge", "name": "br0", "bridge_interfaces": ["bond0"], "params": {}},
+            {"type": "vlan", "name": "vlan0", "vlan_link": "eth3", "vlan_id": 4095},
+            {"type": "nameserver", "address": ["10.0.0.1", "dns"], "interface": "eth4"},
+        ]}});
+        assert_eq!(
+            vec![
+                (
+                    Code::Malformed,
+                    "/network/config/1/mac_address".to_string(),
+                    "MAC address \"aa:bb\" is not 6 bytes, or 20 for InfiniBand, of hexadecimal \
+                     digits separated by colons"
+                        .to_string()
+                ),
+                (
+                    Code::Range,
+                    "/network/config/3/mtu".to_string(),
+                    "MTU 1 is not between 68, the minimum of IPv4, and 65535".to_string()
+                ),
+                (
+                    Code::Range,
+                    "/network/config/5/vlan_id".to_string(),
+                    "VLAN ID 4095 is not between 1 and 4094".to_string()
+                ),
+                (
+                    Code::Malformed,
+                    "/network/config/6/address/1".to_string(),
+                    "Nameserver \"dns\" is not an IPv4 nor an IPv6 address".to_string()
+                ),
+                (
+                    Code::Conflict,
+                    "/network/config/2/name".to_string(),
+                    "Interface \"eth0\" is declared before by /network/config/0, whose \
+                     configuration this one replaces"
+                        .to_string()
+                ),
+                (
+                    Code::Reference,
+                    "/network/config/3/bond_interfaces/1".to_string(),
+                    "Bond member \"eth2\" is not an interface of the network configuration"
+                        .to_string()
+                ),
+                (
+                    Code::Reference,
+                    "/network/config/5/vlan_link".to_string(),
+                    "VLAN link \"eth3\" is not an interface of the network configuration"
+                        .to_string()
+                ),
+                (
+                    Code::Reference,
+                    "/network/config/6/interface".to_string(),
+                    "Interface \"eth4\" is not an interface of the network configuration"
+                        .to_string()
+                ),
+            ],
+            findings(config)
+        );
+    }
+
+    #[test]
+    fn subnets() {
+        let config = json!({"version": 1, "config": [
+            {"type": "physical", "name": "eth0", "mtu": 1200, "subnets": [
+                {
+                    "type": "static",
+                    "address": "192.168.1.10/24",
+                    "gateway": "192.168.1.1",
+                    "dns_nameservers": ["192.168.1.1", "2001:db8::1"],
+                    "routes": [
+                        {"network": "10.0.0.0", "netmask": "255.0.0.0", "gateway": "192.168.1.1"},
+                        {"destination": "0.0.0.0/0", "gateway": "0.0.0.0"},
+                    ],
+                },
+                {"type": "static", "address": "10.0.0.5", "netmask": "255.255.0.255"},
+                {"type": "static6", "address": "2001:db8::10/64", "gateway": "fe80::1"},
+            ]},
+            {"type": "physical", "name": "eth1", "subnets": [
+                {
+                    "type": "dhcp6",
+                    "netmask": "255.255.255.0",
+                    "gateway": "10.0.0.1",
+                    "routes": [{"destination": "10.20.0.0/8", "gateway": "a.b.c.d"}],
+                },
+                {"type": "static", "address": "10.0.0.5/24", "gateway": "10.0.1.1"},
+                {"type": "static6", "address": "10.0.0.6/24"},
+                {"type": "static", "address": "10.0.0.7/24", "gateway": "2001:db8::1"},
+            ]},
+            {"type": "route", "destination": "2001:db8::/32", "gateway": "10.0.0.1"},
+        ]});
+        assert_eq!(
+            vec![
+                (
+                    Code::Malformed,
+                    "/config/0/subnets/1/netmask".to_string(),
+                    "Netmask \"255.255.0.255\" is not a run of ones followed by zeros".to_string()
+                ),
+                (
+                    Code::Range,
+                    "/config/0/mtu".to_string(),
+                    "MTU 1200 is under 1280, the minimum of IPv6, which the kernel disables on \
+                     the interface"
+                        .to_string()
+                ),
+                (
+                    Code::Malformed,
+                    "/config/1/subnets/0/routes/0/destination".to_string(),
+                    "Destination \"10.20.0.0/8\" has host bits set, which \"ip route\" refuses: \
+                     use \"10.0.0.0/8\""
+                        .to_string()
+                ),
+                (
+                    Code::Malformed,
+                    "/config/1/subnets/0/routes/0/gateway".to_string(),
+                    "Gateway \"a.b.c.d\" is not an IPv4 nor an IPv6 address".to_string()
+                ),
+                (
+                    Code::AddressFamily,
+                    "/config/1/subnets/0/netmask".to_string(),
+                    "Netmask \"255.255.255.0\" is an IPv4 address, but subnets of type \"dhcp6\" \
+                     configure IPv6"
+                        .to_string()
+                ),
+                (
+                    Code::AddressFamily,
+                    "/config/1/subnets/0/gateway".to_string(),
+                    "Gateway \"10.0.0.1\" is an IPv4 address, but subnets of type \"dhcp6\" \
+                     configure IPv6"
+                        .to_string()
+                ),
+                (
+                    Code::Unreachable,
+                    "/config/1/subnets/1/gateway".to_string(),
+                    "Gateway \"10.0.1.1\" is outside of the subnet 10.0.0.0/24, so it is \
+                     unreachable without a route to it"
+                        .to_string()
+                ),
+                (
+                    Code::AddressFamily,
+                    "/config/1/subnets/2/address".to_string(),
+                    "Address \"10.0.0.6\" is an IPv4 address, but subnets of type \"static6\" \
+                     configure IPv6"
+                        .to_string()
+                ),
+                (
+                    Code::AddressFamily,
+                    "/config/1/subnets/3/gateway".to_string(),
+                    "Gateway \"2001:db8::1\" is an IPv6 address, but the address of the subnet \
+                     is an IPv4 one"
+                        .to_string()
+                ),
+                (
+                    Code::AddressFamily,
+                    "/config/2/gateway".to_string(),
+                    "Gateway \"10.0.0.1\" is an IPv4 address, but destination \"2001:db8::/32\" \
+                     is an IPv6 network"
+                        .to_string()
+                ),
+            ],
+            findings(config)
+        );
+    }
+}
@@ -0,0 +1,633 @@
+//! Disks, partitions, filesystems and mounts, as `disk_setup`, `fs_setup`, `mounts`, `growpart`
+//! and `swap` declare them, and the references between them.
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use super::Findings;
+use crate::code::Code;
+use crate::span::escape;
+
+/// Labels of the filesystems of cloud images.
+const STANDARD_LABELS: &[&str] = &["BOOT", "UEFI", "cloudimg-rootfs"];
+
+/// Swap files are sized in MiB, rounding down.
+const MIB: f64 = 1024.0 * 1024.0;
+
+/// A disk `disk_setup` declares.
+struct Disk<'a> {
+    name: &'a str,
+    path: String,
+    /// The number of partitions it is partitioned into, if known.
+    partitions: Option<usize>,
+}
+
+/// A filesystem `fs_setup` declares.
+struct Filesystem<'a> {
+    path: String,
+    /// The device, as given in `disk_setup`.
+    device: Option<&'a str>,
+    partition: Partition<'a>,
+}
+
+/// The partition of a device a filesystem is made on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Partition<'a> {
+    /// The first partition without a filesystem, or the whole device if it has none.
+    Auto,
+    /// The whole device.
+    None,
+    Number(u64),
+    /// Any other value, such as `any`, which cloud-init resolves on the instance.
+    Other(&'a str),
+}
+
+/// The declared storage of a cloud-config.
+struct Storage<'a> {
+    aliases: HashMap<&'a str, &'a str>,
+    disks: Vec<Disk<'a>>,
+    filesystems: Vec<Filesystem<'a>>,
+    /// Filesystem labels, with the path of the first filesystem given each.
+    labels: HashMap<&'a str, String>,
+}
+
+impl<'a> Storage<'a> {
+    fn new(config: &'a Value, findings: &mut Findings) -> Self {
+        let aliases = match config.get("device_aliases") {
+            Some(Value::Object(aliases)) => aliases
+                .iter()
+                .filter_map(|(alias, device)| Some((alias.as_str(), device.as_str()?)))
+                .collect(),
+            _ => HashMap::new(),
+        };
+
+        let disks = match config.get("disk_setup") {
+            Some(Value::Object(disks)) => disks
+                .iter()
+                .map(|(name, disk)| Disk {
+                    name,
+                    path: format!("/disk_setup/{}", escape(name)),
+                    partitions: match disk.get("layout") {
+                        Some(Value::Bool(true)) => Some(1),
+                        Some(Value::Array(partitions)) => Some(partitions.len()),
+                        Some(Value::String(layout)) if layout == "remove" => Some(0),
+                        // Devices are left as they are.
+                        _ => None,
+                    },
+                })
+                .collect(),
+            _ => vec![],
+        };
+
+        let mut filesystems = vec![];
+        let mut labels: HashMap<&str, String> = HashMap::new();
+        if let Some(Value::Array(entries)) = config.get("fs_setup") {
+            for (index, entry) in entries.iter().enumerate() {
+                let Value::Object(entry) = entry else {
+                    continue;
+                };
+                let path = format!("/fs_setup/{}", index);
+                if let Some(Value::String(label)) = entry.get("label") {
+                    match labels.get(label.as_str()) {
+                        Some(first) => findings.push(
+                            Code::Conflict,
+                            format!(
+                                "Label \"{}\" is given before to the filesystem of {}: mounting \
+                                 LABEL={} mounts either of them",
+                                label, first, label
+                            ),
+                            format!("{}/label", path),
+                        ),
+                        None => {
+                            labels.insert(label, path.clone());
+                        }
+                    }
+                }
+                let (device, partition) = filesystem_device(entry);
+                filesystems.push(Filesystem {
+                    path,
+                    device,
+                    partition,
+                });
+            }
+        }
+
+        Self {
+            aliases,
+            disks,
+            filesystems,
+            labels,
+        }
+    }
+
+    /// The disk `disk_setup` declares as `device`, directly or through its alias.
+    fn disk(&self, device: &str) -> Option<&Disk<'a>> {
+        let resolve = |name: &'a str| self.aliases.get(name).copied().unwrap_or(name);
+        let device = self.aliases.get(device).copied().unwrap_or(device);
+        self.disks.iter().find(|disk| resolve(disk.name) == device)
+    }
+
+    /// The disk `disk_setup` declares which `device` is a partition of, as `<disk>.<partition>`
+    /// or `/dev/<disk><partition>`, with the disk as named in `device` and the partition number.
+    fn partition<'b>(&self, device: &'b str) -> Option<(&Disk<'a>, &'b str, u64)> {
+        let (disk, number) = match device.rsplit_once('.') {
+            Some((disk, number)) => (disk, number.parse().ok()?),
+            None => split_partition(device)?,
+        };
+        Some((self.disk(disk)?, disk, number))
+    }
+}
+
+/// The device and partition of an entry of `fs_setup`, whose device may be `<device>.<partition>`.
+fn filesystem_device(entry: &Map<String, Value>) -> (Option<&str>, Partition<'_>) {
+    let Some(Value::String(device)) = entry.get("device") else {
+        return (None, Partition::Auto);
+    };
+    if let Some((device, partition)) = device.rsplit_once('.') {
+        if let Ok(partition) = partition.parse() {
+            return (Some(device), Partition::Number(partition));
+        }
+    }
+    let partition = match entry.get("partition") {
+        None => Partition::Auto,
+        Some(Value::Number(partition)) => match partition.as_u64() {
+            Some(partition) => Partition::Number(partition),
+            None => Partition::Auto,
+        },
+        Some(Value::String(partition)) => match partition.as_str() {
+            "auto" => Partition::Auto,
+            "none" => Partition::None,
+            partition => match partition.parse() {
+                Ok(partition) => Partition::Number(partition),
+                Err(_) => Partition::Other(partition),
+            },
+        },
+        Some(_) => Partition::Auto,
+    };
+    (Some(device), partition)
+}
+
+/// The disk and number of a partition device, such as `/dev/sdb1` or `/dev/nvme0n1p1`.
+fn split_partition(device: &str) -> Option<(&str, u64)> {
+    let disk = device.trim_end_matches(|c: char| c.is_ascii_digit());
+    if disk.len() == device.len() || !device.starts_with("/dev/") {
+        return None;
+    }
+    let number = device[disk.len()..].parse().ok()?;
+    // Disks whose names end in digits separate their partitions with `p`.
+    let disk = match disk.strip_suffix('p') {
+        Some(disk) if disk.ends_with(|c: char| c.is_ascii_digit()) => disk,
+        _ => disk,
+    };
+    Some((disk, number))
+}
+
+pub(super) fn check(config: &Value, findings: &mut Findings) {
+    let storage = Storage::new(config, findings);
+
+    let mut made: HashMap<(&str, &Partition), &str> = HashMap::new();
+    for filesystem in &storage.filesystems {
+        let Some(device) = filesystem.device else {
+            continue;
+        };
+        if let Partition::Number(_) | Partition::None = filesystem.partition {
+            let first = *made
+                .entry((device, &filesystem.partition))
+                .or_insert(&filesystem.path);
+            if first != filesystem.path {
+                findings.push(
+                    Code::Conflict,
+                    format!(
+                        "Filesystem is made on the device of the filesystem of {} too: only one of \
+                         them is kept",
+                        first
+                    ),
+                    format!("{}/device", filesystem.path),
+                );
+            }
+        }
+        let (disk, device, partition) = match storage.disk(device) {
+            Some(disk) => (disk, device, filesystem.partition),
+            None => match storage.partition(device) {
+                Some((disk, device, number)) => (disk, device, Partition::Number(number)),
+                None => continue,
+            },
+        };
+        match partition {
+            Partition::Number(number) => check_partition(
+                disk,
+                device,
+                number,
+                format!("{}/device", filesystem.path),
+                findings,
+            ),
+            Partition::None if disk.partitions.is_some_and(|partitions| partitions > 0) => findings
+                .push(
+                    Code::Conflict,
+                    format!(
+                        "Filesystem is made on the whole of \"{}\", erasing the partitions {} \
+                         makes on it: set a \"partition\"",
+                        device, disk.path
+                    ),
+                    format!("{}/partition", filesystem.path),
+                ),
+            _ => {}
+        }
+    }
+
+    let mut mount_points: HashMap<&str, String> = HashMap::new();
+    if let Some(Value::Array(mounts)) = config.get("mounts") {
+        for (index, mount) in mounts.iter().enumerate() {
+            let Value::Array(mount) = mount else {
+                continue;
+            };
+            let path = format!("/mounts/{}", index);
+            if let Some(Value::String(device)) = mount.first() {
+                check_mounted_device(device, &storage, &format!("{}/0", path), findings);
+            }
+            let Some(Value::String(mount_point)) = mount.get(1) else {
+                continue;
+            };
+            if !mount_point.starts_with('/') {
+                continue;
+            }
+            match mount_points.get(mount_point.as_str()) {
+                Some(first) => findings.push(
+                    Code::Conflict,
+                    format!(
+                        "\"{}\" is mounted on before by {}, this mount hides it",
+                        mount_point, first
+                    ),
+                    format!("{}/1", path),
+                ),
+                None => {
+                    mount_points.insert(mount_point, path);
+                }
+            }
+        }
+    }
+
+    if let Some(Value::Object(growpart)) = config.get("growpart") {
+        let off = match growpart.get("mode") {
+            Some(Value::Bool(false)) => true,
+            Some(Value::String(mode)) => mode == "off",
+            _ => false,
+        };
+        if let (false, Some(Value::Array(devices))) = (off, growpart.get("devices")) {
+            for (index, device) in devices.iter().enumerate() {
+                let Value::String(device) = device else {
+                    continue;
+                };
+                if device == "/" || device.starts_with("/dev/") {
+                    continue;
+                }
+                if !mount_points.contains_key(device.as_str()) {
+                    findings.push(
+                        Code::Reference,
+                        format!(
+                            "\"{}\" is neither the root filesystem, a device nor a mount point \
+                             of \"mounts\"",
+                            device
+                        ),
+                        format!("/growpart/devices/{}", index),
+                    );
+                }
+            }
+        }
+    }
+
+    if let Some(Value::Object(swap)) = config.get("swap") {
+        check_swap(swap, findings);
+    }
+}
+
+fn check_mounted_device(device: &str, storage: &Storage, path: &str, findings: &mut Findings) {
+    if let Some(label) = device.strip_prefix("LABEL=") {
+        if !storage.labels.contains_key(label) && !STANDARD_LABELS.contains(&label) {
+            findings.push(
+                Code::Reference,
+                format!(
+                    "Label \"{}\" is not given to any filesystem of \"fs_setup\"",
+                    label
+                ),
+                path.to_string(),
+            );
+        }
+        return;
+    }
+    if let Some((disk, disk_device, number)) = storage.partition(device) {
+        check_partition(disk, disk_device, number, path.to_string(), findings);
+    }
+}
+
+/// Reports partition `number` of `device`, the `disk`, if it is not one `disk_setup` makes.
+fn check_partition(disk: &Disk, device: &str, number: u64, path: String, findings: &mut Findings) {
+    let Some(partitions) = disk.partitions else {
+        return;
+    };
+    if number == 0 || number as usize > partitions {
+        findings.push(
+            Code::Reference,
+            format!(
+                "Partition {} of \"{}\" is not one of the {} partitions {} makes",
+                number, device, partitions, disk.path
+            ),
+            path,
+        );
+    }
+}
+
+fn check_swap(swap: &Map<String, Value>, findings: &mut Findings) {
+    let size = swap.get("size");
+    if let Some(bytes) = size.and_then(swap_size) {
+        if bytes > 0.0 && bytes < MIB {
+            let unit = if size.is_some_and(Value::is_number) {
+                ", as a number of bytes,"
+            } else {
+                ""
+            };
+            findings.push(
+                Code::Discarded,
+                format!(
+                    "Size {}{} is under 1M, so cloud-init creates no swap file: give a size \
+                     such as \"2G\"",
+                    size.map(Value::to_string).unwrap_or_default(),
+                    unit
+                ),
+                "/swap/size".to_string(),
+            );
+        }
+    }
+    if swap.contains_key("maxsize") && size.and_then(Value::as_str) != Some("auto") {
+        findings.push(
+            Code::Discarded,
+            "Maximum size is only used when \"size\" is \"auto\", and is ignored".to_string(),
+            "/swap/maxsize".to_string(),
+        );
+    }
+}
+
+/// The size in bytes of a swap file, as cloud-init's `human2bytes`.
+fn swap_size(size: &Value) -> Option<f64> {
+    match size {
+        Value::Number(size) => size.as_f64(),
+        Value::String(size) => {
+            let (index, unit) = size.char_indices().next_back()?;
+            let exponent = "BKMGT".find(unit)?;
+            Some(size[..index].parse::<f64>().ok()? * 1024f64.powi(exponent as i32))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn findings(config: Value) -> Vec<(Code, String, String)> {
+        let mut findings = Findings::default();
+        check(&config, &mut findings);
+        findings.summary()
+    }
+
+    #[test]
+    fn devices() {
+        assert_eq!(Some(("/dev/sdb", 1)), split_partition("/dev/sdb1"));
+        assert_eq!(Some(("/dev/nvme0n1", 2)), split_partition("/dev/nvme0n1p2"));
+        assert_eq!(Some(("/dev/xvda", 10)), split_partition("/dev/xvda10"));
+        assert_eq!(None, split_partition("/dev/sdb"));
+        assert_eq!(None, split_partition("ephemeral0"));
+
+        let entry = json!({"device": "ephemeral0.2", "partition": "auto"});
+        assert_eq!(
+            (Some("ephemeral0"), Partition::Number(2)),
+            filesystem_device(entry.as_object().unwrap())
+        );
+        let entry = json!({"device": "/dev/sdb", "partition": "none"});
+        assert_eq!(
+            (Some("/dev/sdb"), Partition::None),
+            filesystem_device(entry.as_object().unwrap())
+        );
+        let entry = json!({"device": "/dev/sdb", "partition": "any"});
+        assert_eq!(
+            (Some("/dev/sdb"), Partition::Other("any")),
+            filesystem_device(entry.as_object().unwrap())
+        );
+
+        assert_eq!(Some(1.5 * 1024.0 * MIB), swap_size(&json!("1.5G")));
+        assert_eq!(Some(512.0), swap_size(&json!(512)));
+        assert_eq!(None, swap_size(&json!("auto")));
+        assert_eq!(None, swap_size(&json!("2é")));
+        assert_eq!(None, swap_size(&json!("")));
+    }
+
+    #[test]
+    fn storage() {
+        let config = json!({
+            "device_aliases": {"my_alias": "/dev/sdb", "swap_disk": "/dev/sdc"},
+            "disk_setup": {
+                "my_alias": {"table_type": "gpt", "layout": [50, 50], "overwrite": true},
+                "swap_disk": {"table_type": "gpt", "layout": [[100, 82]]},
+                "ephemeral0": {"layout": false},
+            },
+            "fs_setup": [
+                {"label": "fs1", "filesystem": "ext4", "device": "my_alias.1"},
+                {"label": "fs2", "filesystem": "ext4", "device": "/dev/sdb", "partition": 3},
+                {"label": "fs1", "filesystem": "ext4", "device": "my_alias.2"},
+                {"label": "swap", "filesystem": "swap", "device": "swap_disk", "partition": "none"},
+                {"filesystem": "ext4", "device": "ephemeral0.1"},
+                {"filesystem": "xfs", "device": "my_alias.1"},
+            ],
+            "mounts": [
+                ["LABEL=fs1", "/data"],
+                ["LABEL=logs", "/var/log/app"],
+                ["LABEL=cloudimg-rootfs", "/"],
+                ["/dev/sdb3", "/mnt"],
+                ["/dev/sdb2", "/data"],
+                ["my_alias.4", "/srv/data"],
+                ["swap", "none", "swap", "sw", "0", "0"],
+            ],
+            "growpart": {"devices": ["/", "/data", "/srv", "/dev/sdb2"]},
+            "swap": {"filename": "/swap.img", "size": 2048, "maxsize": "4G"},
+        });
+        assert_eq!(
+            vec![
+                (
+                    Code::Conflict,
+                    "/fs_setup/2/label".to_string(),
+                    "Label \"fs1\" is given before to the filesystem of /fs_setup/0: mounting \
+                     LABEL=fs1 mounts either of them"
+                        .to_string()
+                ),
+                (
+                    Code::Reference,
+                    "/fs_setup/1/device".to_string(),
+                    "Partition 3 of \"/dev/sdb\" is not one of the 2 partitions \
+                     /disk_setup/my_alias makes"
+                        .to_string()
+                ),
+                (
+                    Code::Conflict,
+                    "/fs_setup/3/partition".to_string(),
+                    "Filesystem is made on the whole of \"swap_disk\", erasing the partitions \
+                     /disk_setup/swap_disk makes on it: set a \"partition\""
+                        .to_string()
+                ),
+                (
+                    Code::Conflict,
+                    "/fs_setup/5/device".to_string(),
+                    "Filesystem is made on the device of the filesystem of /fs_setup/0 too: only \
+                     one of them is kept"
+                        .to_string()
+                ),
+                (
+                    Code::Reference,
+                    "/mounts/1/0".to_string(),
+                    "Label \"logs\" is not given to any filesystem of \"fs_setup\"".to_string()
+                ),
+                (
+                    Code::Reference,
+                    "/mounts/3/0".to_string(),
+                    "Partition 3 of \"/dev/sdb\" is not one of the 2 partitions \
+                     /disk_setup/my_alias makes"
+                        .to_string()
+                ),
+                (
+                    Code::Conflict,
+                    "/mounts/4/1".to_string(),
+                    "\"/data\" is mounted on before by /mounts/0, this mount hides it".to_string()
+                ),
+                (
+                    Code::Reference,
+                    "/mounts/5/0".to_string(),
+                    "Partition 4 of \"my_alias\" is not one of the 2 partitions \
+                     /disk_setup/my_alias makes"
+                        .to_string()
+                ),
+                (
+                    Code::Reference,
+                    "/growpart/devices/2".to_string(),
+                    "\"/srv\" is neither the root filesystem, a device nor a mount point of \
+                     \"mounts\""
+                        .to_string()
+                ),
+                (
+                    Code::Discarded,
+                    "/swap/size".to_string(),
+                    "Size 2048, as a number of bytes, is under 1M, so cloud-init creates no swap \
+                     file: give a size such as \"2G\""
+                        .to_string()
+                ),
+                (
+                    Code::Discarded,
+                    "/swap/maxsize".to_string(),
+                    "Maximum size is only used when \"size\" is \"auto\", and is ignored"
+                        .to_string()
+                ),
+            ],
+            findings(config)
+        );
+
+        let config = json!({
+            "growpart": {"mode": "off", "devices": ["/srv"]},
+            "swap": {"size": "auto", "maxsize": "4G"},
+        });
+        assert!(findings(config).is_empty());
+    }
+
+    #[test]
+    fn filesystem_partitions() {
+        let config = json!({
+            "device_aliases": {"data": "/dev/sdb"},
+            "disk_setup": {
+                "data": {"table_type": "gpt", "layout": [60, 40]},
+                "/dev/sdc": {"table_type": "mbr", "layout": true},
+                "/dev/sdd": {"layout": false},
+            },
+            "fs_setup": [
+                {"filesystem": "ext4", "device": "data.2"},
+                {"filesystem": "ext4", "device": "/dev/sdb", "partition": 3},
+                {"filesystem": "ext4", "device": "/dev/sdc", "partition": "none"},
+                {"filesystem": "ext4", "device": "/dev/sdd", "partition": 5},
+                {"filesystem": "ext4", "device": "/dev/sde", "partition": 1},
+            ],
+        });
+        assert_eq!(
+            vec![
+                (
+                    Code::Reference,
+                    "/fs_setup/1/device".to_string(),
+                    "Partition 3 of \"/dev/sdb\" is not one of the 2 partitions /disk_setup/data \
+                     makes"
+                        .to_string()
+                ),
+                (
+                    Code::Conflict,
+                    "/fs_setup/2/partition".to_string(),
+                    "Filesystem is made on the whole of \"/dev/sdc\", erasing the partitions \
+                     /disk_setup/~1dev~1sdc makes on it: set a \"partition\""
+                        .to_string()
+                ),
+            ],
+            findings(config)
+        );
+    }
+
+    #[test]
+    fn mounted_labels() {
+        let config = json!({
+            "fs_setup": [
+                {"label": "data", "filesystem": "ext4", "device": "/dev/sdb"},
+            ],
+            "mounts": [
+                ["LABEL=data", "/data"],
+                ["LABEL=BOOT", "/boot"],
+                ["LABEL=Data", "/srv"],
+                ["LABEL=", "/mnt"],
+            ],
+        });
+        assert_eq!(
+            vec![
+                (
+                    Code::Reference,
+                    "/mounts/2/0".to_string(),
+                    "Label \"Data\" is not given to any filesystem of \"fs_setup\"".to_string()
+                ),
+                (
+                    Code::Reference,
+                    "/mounts/3/0".to_string(),
+                    "Label \"\" is not given to any filesystem of \"fs_setup\"".to_string()
+                ),
+            ],
+            findings(config)
+        );
+    }
+
+    #[test]
+    fn grown_mount_points() {
+        let config = json!({
+            "mounts": [["/dev/sdb1", "/data"], ["/dev/sdc1", "none", "swap"]],
+            "growpart": {"mode": "auto", "devices": ["/", "/data", "/dev/sdc1", "/srv", "none"]},
+        });
+        assert_eq!(
+            vec![
+                (
+                    Code::Reference,
+                    "/growpart/devices/3".to_string(),
+                    "\"/srv\" is neither the root filesystem, a device nor a mount point of \
+                     \"mounts\""
+                        .to_string()
+                ),
+                (
+                    Code::Reference,
+                    "/growpart/devices/4".to_string(),
+                    "\"none\" is neither the root filesystem, a device nor a mount point of \
+                     \"mounts\""
+                        .to_string()
+                ),
+            ],
+            findings(config)
+        );
+    }
+}
@@ -0,0 +1,512 @@
+//! `write_files` entries, as cloud-init's `cc_write_files` decodes and writes them.
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::OnceLock;
+
+use flate2::read::GzDecoder;
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use super::Findings;
+use crate::code::Code;
+
+/// The largest mode `chmod` sets, with the setuid, setgid and sticky bits.
+const MAX_MODE: u32 = 0o7777;
+
+pub(super) fn check(files: &[Value], findings: &mut Findings) {
+    // Deferred files are written in the final stage, after the others.
+    let (deferred, immediate): (Vec<_>, Vec<_>) = files
+        .iter()
+        .enumerate()
+        .filter_map(|(index, file)| Some((index, file.as_object()?)))
+        .partition(|(_, file)| file.get("defer") == Some(&Value::Bool(true)));
+    let mut written = HashMap::new();
+    for (index, file) in immediate.into_iter().chain(deferred) {
+        let path = format!("/write_files/{}", index);
+        check_file(file, &path, findings);
+
+        let Some(file_path) = file.get("path").and_then(Value::as_str) else {
+            continue;
+        };
+        if file_path.is_empty() {
+            continue;
+        }
+        let append = file.get("append") == Some(&Value::Bool(true));
+        if let Some(previous) = written.insert(normalize(file_path), index) {
+            if !append {
+                findings.push(
+                    Code::Conflict,
+                    format!(
+                        "Overwrites \"{}\", written before by /write_files/{}: set \"append: \
+                         true\" to append to it",
+                        file_path, previous
+                    ),
+                    format!("{}/path", path),
+                );
+            }
+        }
+    }
+}
+
+fn check_file(file: &Map<String, Value>, path: &str, findings: &mut Findings) {
+    match file.get("path").and_then(Value::as_str) {
+        Some("") => findings.push(
+            Code::Malformed,
+            "Path is empty, cloud-init skips the file".to_string(),
+            format!("{}/path", path),
+        ),
+        Some(file_path) if !file_path.starts_with('/') => findings.push(
+            Code::RelativePath,
+            format!(
+                "Path \"{}\" is relative, cloud-init writes it relative to its working directory",
+                file_path
+            ),
+            format!("{}/path", path),
+        ),
+        _ => {}
+    }
+
+    if let Some(Value::String(permissions)) = file.get("permissions") {
+        if let Err(description) = parse_mode(permissions) {
+            findings.push(
+                Code::Malformed,
+                description,
+                format!("{}/permissions", path),
+            );
+        }
+    }
+
+    if let Some(Value::String(owner)) = file.get("owner") {
+        if let Err(description) = check_owner(owner) {
+            findings.push(Code::Malformed, description, format!("{}/owner", path));
+        }
+    }
+
+    let encoding = file
+        .get("encoding")
+        .and_then(Value::as_str)
+        .map(|encoding| encoding.trim().to_lowercase());
+    if let (Some(encoding), Some(Value::String(content))) = (encoding, file.get("content")) {
+        match decode(&encoding, content) {
+            Ok(Decoded {
+                discarded: Some(description),
+                ..
+            }) => findings.push(Code::Discarded, description, format!("{}/content", path)),
+            Ok(_) => {}
+            Err(description) => {
+                findings.push(Code::Malformed, description, format!("{}/content", path))
+            }
+        }
+    }
+}
+
+/// Parses `permissions` as cloud-init's `decode_perms`, with Python's `int(permissions, 8)`,
+/// which falls back to the default mode if it cannot.
+fn parse_mode(permissions: &str) -> Result<u32, String> {
+    let digits = permissions.trim();
+    let digits = digits
+        .strip_prefix("0o")
+        .or_else(|| digits.strip_prefix("0O"))
+        .map_or(digits, |digits| digits.strip_prefix('_').unwrap_or(digits));
+    let is_octal = !digits.is_empty()
+        && digits
+            .split('_')
+            .all(|group| !group.is_empty() && group.bytes().all(|b| matches!(b, b'0'..=b'7')));
+    let mode = is_octal
+        .then(|| u32::from_str_radix(&digits.replace('_', ""), 8).ok())
+        .flatten()
+        .ok_or_else(|| {
+            format!(
+                "Permissions \"{}\" are not an octal mode, such as \"0644\": cloud-init sets the \
+                 default, 0644, instead",
+                permissions
+            )
+        })?;
+    if mode > MAX_MODE {
+        return Err(format!(
+            "Permissions \"{}\" are not a file mode, which is at most 07777",
+            permissions
+        ));
+    }
+    Ok(mode)
+}
+
+/// Checks `owner` is a `user`, `user:group` or `:group` pair of names, as cloud-init's
+/// `extract_usergroup` splits it before looking the names up.
+fn check_owner(owner: &str) -> Result<(), String> {
+    static NAME: OnceLock<Regex> = OnceLock::new();
+    let name = NAME.get_or_init(|| {
+        Regex::new(r"^[A-Za-z_][A-Za-z0-9_.-]{0,31}\$?$").expect("The name regex must be valid")
+    });
+    let (user, group) = match owner.split_once(':') {
+        Some((user, group)) => (user.trim(), Some(group.trim())),
+        None => (owner.trim(), None),
+    };
+    for (kind, value) in [("user", Some(user)), ("group", group)] {
+        let Some(value) = value else {
+            continue;
+        };
+        // Empty, `-1` and `none` keep the current owner.
+        if value.is_empty() || value == "-1" || value.eq_ignore_ascii_case("none") {
+            continue;
+        }
+        if value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!(
+                "Owner \"{}\" has a numeric {} ID, but cloud-init looks {}s up by name",
+                owner, kind, kind
+            ));
+        }
+        if !name.is_match(value) {
+            return Err(format!(
+                "Owner \"{}\" is not a \"user:group\" pair: \"{}\" is not a {} name",
+                owner, value, kind
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// A content as cloud-init decodes it.
+#[derive(Debug, PartialEq)]
+struct Decoded {
+    content: Vec<u8>,
+    /// What cloud-init discards of the content while decoding it, if anything.
+    discarded: Option<String>,
+}
+
+/// Decodes `content` as cloud-init's `extract_contents`, which fails the module if it cannot.
+fn decode(encoding: &str, content: &str) -> Result<Decoded, String> {
+    match encoding {
+        "gz+base64" | "gzip+base64" | "gz+b64" | "gzip+b64" => {
+            let decoded = decode_base64(content)?;
+            Ok(Decoded {
+                content: decompress_gzip(&decoded.content)?,
+                ..decoded
+            })
+        }
+        "b64" | "base64" => decode_base64(content),
+        // Binary contents, tagged `!!binary`, are loaded as their base64 encoding.
+        "gz" | "gzip" => decode_base64(content)
+            .ok()
+            .and_then(|decoded| decompress_gzip(&decoded.content).ok())
+            .map(|content| Decoded {
+                content,
+                discarded: None,
+            })
+            .ok_or_else(|| {
+                "Content is not gzip-compressed: use the \"gzip+base64\" encoding for \
+                 base64-encoded content"
+                    .to_string()
+            }),
+        _ => Ok(Decoded {
+            content: content.as_bytes().to_vec(),
+            discarded: None,
+        }),
+    }
+}
+
+/// Decodes `content` as Python's `base64.b64decode`, which discards the characters out of the
+/// base64 alphabet and everything after the first padding.
+fn decode_base64(content: &str) -> Result<Decoded, String> {
+    let mut decoded = vec![];
+    let mut discarded = None;
+    // The characters of the current quantum of 4, and their bits.
+    let (mut quantum, mut bits, mut pads) = (0, 0u32, 0);
+    let mut characters = 0;
+    for (index, c) in content.char_indices() {
+        let sextet = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' => 62,
+            '/' => 63,
+            '=' => {
+                if quantum >= 2 {
+                    pads += 1;
+                    if quantum + pads >= 4 {
+                        if quantum == 2 {
+                            decoded.push((bits >> 4) as u8);
+                        } else {
+                            decoded.extend_from_slice(&[(bits >> 10) as u8, (bits >> 2) as u8]);
+                        }
+                        quantum = 0;
+                        let rest = &content[index + 1..];
+                        if !rest
+                            .trim_matches(|c: char| c == '=' || c.is_whitespace())
+                            .is_empty()
+                        {
+                            discarded = Some(
+                                "Content goes on after its base64 padding, which cloud-init \
+                                 discards: it may be several base64 contents concatenated"
+                                    .to_string(),
+                            );
+                        }
+                        break;
+                    }
+                }
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            c => {
+                discarded.get_or_insert_with(|| {
+                    format!(
+                        "Content has characters out of the base64 alphabet, such as {:?}, which \
+                         cloud-init discards",
+                        c
+                    )
+                });
+                continue;
+            }
+        };
+        characters += 1;
+        pads = 0;
+        bits = bits << 6 | sextet;
+        quantum += 1;
+        if quantum == 4 {
+            decoded.extend_from_slice(&[(bits >> 16) as u8, (bits >> 8) as u8, bits as u8]);
+            (quantum, bits) = (0, 0);
+        }
+    }
+    match quantum {
+        0 => Ok(Decoded {
+            content: decoded,
+            discarded,
+        }),
+        1 => Err(format!(
+            "Content is not base64: it is truncated, its {} base64 characters are 1 more than a \
+             multiple of 4",
+            characters
+        )),
+        _ => Err(
+            "Content is not base64: it is not padded with \"=\" to a multiple of 4 characters"
+                .to_string(),
+        ),
+    }
+}
+
+fn decompress_gzip(content: &[u8]) -> Result<Vec<u8>, String> {
+    let mut decompressed = vec![];
+    GzDecoder::new(content)
+        .read_to_end(&mut decompressed)
+        .map_err(|e| format!("Content is not gzip-compressed: {}", e))?;
+    Ok(decompressed)
+}
+
+/// Normalizes `path` as Python's `os.path.normpath`, so that paths to the same file compare
+/// equal.
+fn normalize(path: &str) -> String {
+    let mut components: Vec<&str> = vec![];
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." if components.last().is_some_and(|last| *last != "..") => {
+                components.pop();
+            }
+            ".." if path.starts_with('/') => {}
+            component => components.push(component),
+        }
+    }
+    let normalized = components.join("/");
+    if path.starts_with('/') {
+        format!("/{}", normalized)
+    } else {
+        normalized
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine as _;
+    use flate2::{write::GzEncoder, Compression};
+    use serde_json::json;
+    use std::io::Write;
+
+    fn findings(files: Value) -> Vec<(Code, String, String)> {
+        let mut findings = Findings::default();
+        check(files.as_array().unwrap(), &mut findings);
+        findings.summary()
+    }
+
+    fn gzip(content: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(content.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn contents() {
+        let gzipped = STANDARD.encode(gzip("hello"));
+        let files = json!([
+            {"path": "/a", "content": "aGVsbG8=\n", "encoding": "b64"},
+            {"path": "/b", "content": gzipped, "encoding": "gzip+base64"},
+            {"path": "/c", "content": gzipped, "encoding": "gz"},
+            {"path": "/d", "content": "not base64", "encoding": "base64"},
+            {"path": "/e", "content": "aGVsbG8", "encoding": "b64"},
+            {"path": "/f", "content": "aGVsbG8-", "encoding": "b64"},
+            {"path": "/g", "content": "aGVsbG8=", "encoding": "gzip+b64"},
+            {"path": "/h", "content": "hello", "encoding": "gzip"},
+            {"path": "/i", "content": "hello", "encoding": "text/plain"},
+            {"path": "/j", "content": "aGVsbG8=", "encoding": " B64 "},
+            {"path": "/k", "content": "aGk=aGk=", "encoding": "b64"},
+            {"path": "/l", "content": "aGVs...bG8=", "encoding": "b64"},
+        ]);
+        let codes_paths: Vec<_> = findings(files)
+            .into_iter()
+            .map(|(code, path, _)| (code, path))
+            .collect();
+        assert_eq!(
+            vec![
+                (Code::Malformed, "/write_files/3/content".to_string()),
+                (Code::Malformed, "/write_files/4/content".to_string()),
+                (Code::Malformed, "/write_files/5/content".to_string()),
+                (Code::Malformed, "/write_files/6/content".to_string()),
+                (Code::Malformed, "/write_files/7/content".to_string()),
+                (Code::Discarded, "/write_files/10/content".to_string()),
+                (Code::Discarded, "/write_files/11/content".to_string()),
+            ],
+            codes_paths
+        );
+    }
+
+    /// As Python's `base64.b64decode`.
+    #[test]
+    fn base64() {
+        let decoded = |content: &[u8]| Decoded {
+            content: content.to_vec(),
+            discarded: None,
+        };
+        assert_eq!(
+            Ok(decoded(b"hello")),
+            decode("gz+b64", &STANDARD.encode(gzip("hello")))
+        );
+        assert_eq!(Ok(decoded(b"abc")), decode_base64("YWJj="));
+        assert_eq!(Ok(decoded(b"a")), decode_base64("YQ===\n"));
+        assert_eq!(
+            Ok(Decoded {
+                content: b"a".to_vec(),
+                discarded: Some(
+                    "Content goes on after its base64 padding, which cloud-init discards: it \
+                     may be several base64 contents concatenated"
+                        .to_string()
+                ),
+            }),
+            decode_base64("YQ==YQ==")
+        );
+        assert_eq!(
+            Ok(Decoded {
+                content: b"hello".to_vec(),
+                discarded: Some(
+                    "Content has characters out of the base64 alphabet, such as '.', which \
+                     cloud-init discards"
+                        .to_string()
+                ),
+            }),
+            decode_base64("aGVs...bG8=")
+        );
+        assert_eq!(
+            Err(
+                "Content is not base64: it is truncated, its 9 base64 characters are 1 more \
+                 than a multiple of 4"
+                    .to_string()
+            ),
+            decode_base64("not base64")
+        );
+        assert_eq!(
+            Err(
+                "Content is not base64: it is not padded with \"=\" to a multiple of 4 \
+                 characters"
+                    .to_string()
+            ),
+            decode_base64("YQ=")
+        );
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!(Ok(0o644), parse_mode("0644"));
+        assert_eq!(Ok(0o644), parse_mode("644"));
+        assert_eq!(Ok(0o755), parse_mode("0o755"));
+        assert_eq!(Ok(0o4755), parse_mode(" 0o4_755 "));
+        assert!(parse_mode("0x1a4").is_err());
+        assert!(parse_mode("0648").is_err());
+        assert!(parse_mode("u+x").is_err());
+        assert!(parse_mode("").is_err());
+        assert!(parse_mode("0_").is_err());
+        assert_eq!(
+            Err("Permissions \"017777\" are not a file mode, which is at most 07777".to_string()),
+            parse_mode("017777")
+        );
+    }
+
+    #[test]
+    fn owners() {
+        for owner in [
+            "root",
+            "root:root",
+            ":adm",
+            "www-data:www-data",
+            "-1:none",
+            "user$",
+        ] {
+            assert_eq!(Ok(()), check_owner(owner), "{}", owner);
+        }
+        assert_eq!(
+            Err(
+                "Owner \"1000:1000\" has a numeric user ID, but cloud-init looks users up by \
+                 name"
+                    .to_string()
+            ),
+            check_owner("1000:1000")
+        );
+        assert!(check_owner("root:adm:x").is_err());
+        assert!(check_owner("my user").is_err());
+    }
+
+    #[test]
+    fn paths() {
+        let files = json!([
+            {"path": "/etc/motd", "content": "a"},
+            {"path": "etc/motd"},
+            {"path": ""},
+            {"path": "/etc//./motd", "content": "b", "append": true},
+            {"path": "/etc/x/../motd", "content": "c", "defer": true},
+            {"path": "/etc/motd", "content": "d"},
+            "not a file",
+        ]);
+        assert_eq!(
+            vec![
+                (
+                    Code::Malformed,
+                    "/write_files/2/path".to_string(),
+                    "Path is empty, cloud-init skips the file".to_string()
+                ),
+                (
+                    Code::RelativePath,
+                    "/write_files/1/path".to_string(),
+                    "Path \"etc/motd\" is relative, cloud-init writes it relative to its working \
+                     directory"
+                        .to_string()
+                ),
+                (
+                    Code::Conflict,
+                    "/write_files/5/path".to_string(),
+                    "Overwrites \"/etc/motd\", written before by /write_files/3: set \"append: \
+                     true\" to append to it"
+                        .to_string()
+                ),
+                (
+                    Code::Conflict,
+                    "/write_files/4/path".to_string(),
+                    "Overwrites \"/etc/x/../motd\", written before by /write_files/5: set \
+                     \"append: true\" to append to it"
+                        .to_string()
+                ),
+            ],
+            findings(files)
+        );
+        assert_eq!("/etc/motd", normalize("/../etc/./x/..//motd/"));
+        assert_eq!("../etc", normalize("../etc"));
+    }
+}
@@ -0,0 +1,533 @@
+//! Source positions of the nodes of a YAML payload, addressed by JSON pointer.
+use std::collections::HashMap;
+
+use saphyr_parser::{Event, Marker, Parser, Span as EventSpan, SpannedEventReceiver};
+use serde::{Deserialize, Serialize};
+
+/// A location within a payload.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct Position {
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column number, counted in characters.
+    pub column: usize,
+    /// 0-based byte offset.
+    pub offset: usize,
+}
+
+impl Position {
+    /// Position of the byte `offset` of `payload`.
+    pub(crate) fn at(payload: &str, offset: usize) -> Self {
+        let before = &payload[..offset];
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        Self {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            offset,
+        }
+    }
+}
+
+/// A region of a payload, from `start` (inclusive) to `end` (exclusive).
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    /// Span of the bytes `start..end` of `payload`.
+    pub(crate) fn from_offsets(payload: &str, start: usize, end: usize) -> Self {
+        Self {
+            start: Position::at(payload, start),
+            end: Position::at(payload, end),
+        }
+    }
+
+    /// The span in `payload` of this span of its substring starting at byte `offset`.
+    pub(crate) fn relocate(self, payload: &str, offset: usize) -> Self {
+        Self::from_offsets(
+            payload,
+            offset + self.start.offset,
+            offset + self.end.offset,
+        )
+    }
+
+    /// Span of the first line of `payload`, without its line break.
+    pub(crate) fn first_line(payload: &str) -> Self {
+        Self::line(payload, 1)
+    }
+
+    /// Span of the 1-based `line` of `payload`, without its line break. Lines past the end of
+    /// `payload` are empty and located at its end.
+    pub(crate) fn line(payload: &str, line: usize) -> Self {
+        let mut offset = 0;
+        let mut content = "";
+        for (index, current) in payload.split_inclusive('\n').enumerate() {
+            content = current.trim_end_matches(['\n', '\r']);
+            if index + 1 == line {
+                break;
+            }
+            offset += current.len();
+            content = "";
+        }
+        Self {
+            start: Position {
+                line,
+                column: 1,
+                offset,
+            },
+            end: Position {
+                line,
+                column: content.chars().count() + 1,
+                offset: offset + content.len(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    key: Option<Span>,
+    value: Span,
+}
+
+/// Maps the JSON pointers of a YAML document to their location in the source.
+///
+/// Only the first document of the stream is mapped. Building the map is best-effort: if the
+/// payload is not valid YAML, the nodes parsed before the error are kept.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    nodes: HashMap<String, Node>,
+}
+
+impl SourceMap {
+    pub fn from_yaml(payload: &str) -> Self {
+        let mut builder = Builder::new(payload);
+        // Scan errors are reported by the deserializer, keep what has been mapped so far.
+        let _ = Parser::new_from_str(payload).load(&mut builder, false);
+        Self {
+            nodes: builder.nodes,
+        }
+    }
+
+    /// Span of the value at `instance_path` or, if it is not mapped, of its closest ancestor.
+    pub fn span(&self, instance_path: &str) -> Option<Span> {
+        let mut path = instance_path;
+        loop {
+            if let Some(node) = self.nodes.get(path) {
+                return Some(node.value);
+            }
+            match path.rsplit_once('/') {
+                Some((parent, _)) => path = parent,
+                None => return None,
+            }
+        }
+    }
+
+    /// Span of the mapping key holding the value at `instance_path`.
+    pub fn key_span(&self, instance_path: &str) -> Option<Span> {
+        self.nodes.get(instance_path).and_then(|node| node.key)
+    }
+}
+
+enum Frame {
+    Mapping {
+        path: String,
+        key: Option<Span>,
+        start: Position,
+        end: Position,
+        pending_key: Option<(String, Span)>,
+    },
+    Sequence {
+        path: String,
+        key: Option<Span>,
+        start: Position,
+        end: Position,
+        index: usize,
+    },
+    /// A complex mapping key, or its value, which cannot be addressed by a JSON pointer.
+    ///
+    /// A `depth` of zero means the value of the complex key has not started yet.
+    Skipped { depth: usize, key: bool },
+}
+
+/// Converts the markers of the events of a payload, which count chars, to positions.
+pub(crate) struct Markers {
+    /// Byte offset of every char index, plus the length of the payload.
+    offsets: Vec<usize>,
+}
+
+impl Markers {
+    pub(crate) fn new(payload: &str) -> Self {
+        let mut offsets: Vec<usize> = payload.char_indices().map(|(i, _)| i).collect();
+        offsets.push(payload.len());
+        Self { offsets }
+    }
+
+    pub(crate) fn position(&self, marker: &Marker) -> Position {
+        Position {
+            line: marker.line(),
+            column: marker.col() + 1,
+            offset: self
+                .offsets
+                .get(marker.index())
+                .copied()
+                .unwrap_or_else(|| *self.offsets.last().expect("Cannot be empty")),
+        }
+    }
+
+    pub(crate) fn span(&self, span: &EventSpan) -> Span {
+        Span {
+            start: self.position(&span.start),
+            end: self.position(&span.end),
+        }
+    }
+}
+
+struct Builder {
+    markers: Markers,
+    stack: Vec<Frame>,
+    nodes: HashMap<String, Node>,
+    done: bool,
+}
+
+impl Builder {
+    fn new(payload: &str) -> Self {
+        Self {
+            markers: Markers::new(payload),
+            stack: vec![],
+            nodes: HashMap::new(),
+            done: false,
+        }
+    }
+
+    /// Where the node starting now lives: `None` if it is a mapping key, otherwise its pointer
+    /// and the span of the key holding it.
+    fn slot(&self) -> Option<(String, Option<Span>)> {
+        match self.stack.last() {
+            None => Some((String::new(), None)),
+            Some(Frame::Mapping {
+                path, pending_key, ..
+            }) => pending_key
+                .as_ref()
+                .map(|(key, span)| (format!("{}/{}", path, escape(key)), Some(*span))),
+            Some(Frame::Sequence { path, index, .. }) => {
+                Some((format!("{}/{}", path, index), None))
+            }
+            Some(Frame::Skipped { .. }) => unreachable!("skipped frames consume their children"),
+        }
+    }
+
+    /// Records that a child node of the current container ended at `end`.
+    fn child_ended(&mut self, end: Position) {
+        match self.stack.last_mut() {
+            Some(Frame::Mapping {
+                end: parent_end,
+                pending_key,
+                ..
+            }) => {
+                *parent_end = end;
+                pending_key.take();
+            }
+            Some(Frame::Sequence {
+                end: parent_end,
+                index,
+                ..
+            }) => {
+                *parent_end = end;
+                *index += 1;
+            }
+            _ => {}
+        }
+    }
+
+    fn start_node(&mut self, event: &Event, span: Span) {
+        if let Some(Frame::Skipped { depth, .. }) = self.stack.last_mut() {
+            if matches!(event, Event::MappingStart(..) | Event::SequenceStart(..)) {
+                *depth += 1;
+            }
+            return;
+        }
+
+        let Some((path, key)) = self.slot() else {
+            // A mapping key
+            match event {
+                Event::Scalar(value, ..) => {
+                    if let Some(Frame::Mapping { pending_key, .. }) = self.stack.last_mut() {
+                        *pending_key = Some((value.to_string(), span));
+                    }
+                }
+                Event::Alias(_) => {
+                    if let Some(Frame::Mapping { pending_key, .. }) = self.stack.last_mut() {
+                        *pending_key = Some((String::new(), span));
+                    }
+                }
+                _ => self.stack.push(Frame::Skipped {
+                    depth: 1,
+                    key: true,
+                }),
+            }
+            return;
+        };
+
+        match event {
+            Event::MappingStart(..) => self.stack.push(Frame::Mapping {
+                path,
+                key,
+                start: span.start,
+                end: span.end,
+                pending_key: None,
+            }),
+            Event::SequenceStart(..) => self.stack.push(Frame::Sequence {
+                path,
+                key,
+                start: span.start,
+                end: span.end,
+                index: 0,
+            }),
+            _ => {
+                self.nodes.insert(path, Node { key, value: span });
+                self.child_ended(span.end);
+            }
+        }
+    }
+
+    fn end_node(&mut self, span: Span) {
+        match self.stack.pop() {
+            Some(Frame::Skipped { depth, key }) => {
+                if depth > 1 {
+                    self.stack.push(Frame::Skipped {
+                        depth: depth - 1,
+                        key,
+                    });
+                } else if key {
+                    // The value of a complex key is not addressable either.
+                    self.stack.push(Frame::Skipped {
+                        depth: 0,
+                        key: false,
+                    });
+                } else {
+                    self.child_ended(span.end);
+                }
+            }
+            Some(
+                Frame::Mapping {
+                    path,
+                    key,
+                    start,
+                    end,
+                    ..
+                }
+                | Frame::Sequence {
+                    path,
+                    key,
+                    start,
+                    end,
+                    ..
+                },
+            ) => {
+                // Block collections end where their next sibling starts, prefer the end of
+                // their last child. Flow collections end after their closing bracket.
+                let end = if span.start == span.end {
+                    end
+                } else {
+                    span.end
+                };
+                self.nodes.insert(
+                    path,
+                    Node {
+                        key,
+                        value: Span { start, end },
+                    },
+                );
+                self.child_ended(end);
+            }
+            None => {}
+        }
+    }
+}
+
+impl<'input> SpannedEventReceiver<'input> for Builder {
+    fn on_event(&mut self, event: Event<'input>, span: EventSpan) {
+        if self.done {
+            return;
+        }
+        let span = self.markers.span(&span);
+
+        // Values of complex keys are skipped as a single node.
+        if let Some(Frame::Skipped { depth: 0, .. }) = self.stack.last() {
+            self.stack.pop();
+            match event {
+                Event::MappingStart(..) | Event::SequenceStart(..) => {
+                    self.stack.push(Frame::Skipped {
+                        depth: 1,
+                        key: false,
+                    })
+                }
+                _ => self.child_ended(span.end),
+            }
+            return;
+        }
+
+        match event {
+            Event::Scalar(..)
+            | Event::Alias(_)
+            | Event::MappingStart(..)
+            | Event::SequenceStart(..) => self.start_node(&event, span),
+            Event::MappingEnd | Event::SequenceEnd => self.end_node(span),
+            Event::DocumentEnd => self.done = true,
+            _ => {}
+        }
+    }
+}
+
+/// Escapes a mapping key as a JSON pointer reference token.
+pub(crate) fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn position(line: usize, column: usize, offset: usize) -> Position {
+        Position {
+            line,
+            column,
+            offset,
+        }
+    }
+
+    #[test]
+    fn nested_nodes() {
+        let payload = "#cloud-config\nusers:\n  - name: \"a b\"\n    groups: [x, y]\n  - c\n";
+        let source_map = SourceMap::from_yaml(payload);
+
+        assert_eq!(
+            Some(Span {
+                start: position(3, 11, 31),
+                end: position(3, 16, 36),
+            }),
+            source_map.span("/users/0/name")
+        );
+        assert_eq!(
+            Some(Span {
+                start: position(3, 5, 25),
+                end: position(3, 9, 29),
+            }),
+            source_map.key_span("/users/0/name")
+        );
+        assert_eq!(
+            Some(Span {
+                start: position(4, 13, 49),
+                end: position(4, 19, 55),
+            }),
+            source_map.span("/users/0/groups")
+        );
+        assert_eq!(
+            Some(Span {
+                start: position(3, 3, 23),
+                end: position(5, 6, 61),
+            }),
+            source_map.span("/users")
+        );
+        assert_eq!(
+            Some(Span {
+                start: position(5, 5, 60),
+                end: position(5, 6, 61),
+            }),
+            source_map.span("/users/1")
+        );
+    }
+
+    #[test]
+    fn lines() {
+        let payload = "#!/bin/sh\r\nécho\n";
+        assert_eq!(
+            Span {
+                start: position(1, 1, 0),
+                end: position(1, 10, 9),
+            },
+            Span::first_line(payload)
+        );
+        assert_eq!(
+            Span {
+                start: position(2, 1, 11),
+                end: position(2, 5, 16),
+            },
+            Span::line(payload, 2)
+        );
+        assert_eq!(
+            Span {
+                start: position(3, 1, 17),
+                end: position(3, 1, 17),
+            },
+            Span::line(payload, 3)
+        );
+    }
+
+    #[test]
+    fn relocated() {
+        let payload = "a\r\nb: ü\nc: 1\n";
+        let span = SourceMap::from_yaml("c: 1\n").span("/c").unwrap();
+        assert_eq!(
+            Span {
+                start: position(3, 4, 12),
+                end: position(3, 5, 13),
+            },
+            span.relocate(payload, 9)
+        );
+        assert_eq!(position(2, 4, 6), Position::at(payload, 6));
+    }
+
+    #[test]
+    fn closest_ancestor() {
+        let source_map = SourceMap::from_yaml("a:\n  b: 1\n");
+        assert_eq!(source_map.span("/a"), source_map.span("/a/c/d"));
+        assert_eq!(None, source_map.key_span("/a/c/d"));
+    }
+
+    #[test]
+    fn multibyte_offsets() {
+        let source_map = SourceMap::from_yaml("ü: x\n\"a/b~\": y\n");
+        assert_eq!(
+            Some(Span {
+                start: position(1, 4, 4),
+                end: position(1, 5, 5),
+            }),
+            source_map.span("/ü")
+        );
+        assert_eq!(
+            Some(Span {
+                start: position(2, 9, 14),
+                end: position(2, 10, 15),
+            }),
+            source_map.span("/a~1b~0")
+        );
+    }
+
+    #[test]
+    fn complex_keys_are_skipped() {
+        let source_map = SourceMap::from_yaml("? [a, b]\n: {c: 1}\nd: 2\n");
+        assert_eq!(
+            Some(Span {
+                start: position(3, 4, 21),
+                end: position(3, 5, 22),
+            }),
+            source_map.span("/d")
+        );
+    }
+
+    #[test]
+    fn invalid_yaml_keeps_parsed_nodes() {
+        let source_map = SourceMap::from_yaml("a: 1\nb: \"c\n");
+        assert_eq!(
+            Some(Span {
+                start: position(1, 4, 3),
+                end: position(1, 5, 4),
+            }),
+            source_map.span("/a")
+        );
+    }
+}
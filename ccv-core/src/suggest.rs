@@ -0,0 +1,103 @@
+//! "Did you mean" suggestions for unexpected property names and values.
+
+/// Maximum number of candidates suggested for an unexpected name or value.
+const MAX_CANDIDATES: usize = 3;
+
+/// The `allowed` names or values likely meant instead of `found`, closest first: the ones which
+/// differ in case or `_`/`-` separators, then the ones with at most a typo every three chars.
+pub(crate) fn candidates<'a>(
+    found: &str,
+    allowed: impl IntoIterator<Item = &'a str>,
+) -> Vec<String> {
+    let normalized = normalize(found);
+    let mut ranked: Vec<(usize, &str)> = allowed
+        .into_iter()
+        .filter(|candidate| *candidate != found)
+        .filter_map(|candidate| {
+            let distance = distance(&normalized, &normalize(candidate));
+            let max_distance = (found.chars().count().max(candidate.chars().count()) / 3).max(1);
+            (distance <= max_distance).then_some((distance, candidate))
+        })
+        .collect();
+    ranked.sort_unstable();
+    ranked.dedup();
+    ranked
+        .into_iter()
+        .take(MAX_CANDIDATES)
+        .map(|(_, candidate)| candidate.to_string())
+        .collect()
+}
+
+/// Lowercases `name` and unifies its separators, as cloud-init keys mix `_` and `-`.
+fn normalize(name: &str) -> Vec<char> {
+    name.chars()
+        .map(|c| match c {
+            '-' => '_',
+            c => c.to_ascii_lowercase(),
+        })
+        .collect()
+}
+
+/// Optimal string alignment distance: the Levenshtein distance, with the transposition of two
+/// adjacent chars, the most common typo, counted as a single edit.
+fn distance(a: &[char], b: &[char]) -> usize {
+    // distances[i][j] is the distance between the first i chars of `a` and the first j of `b`.
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + substitution);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ranked_candidates() {
+        let allowed = [
+            "packages",
+            "package_update",
+            "package_upgrade",
+            "snap",
+            "ntp",
+        ];
+        assert_eq!(vec!["packages"], candidates("pacakges", allowed));
+        assert_eq!(
+            vec!["package_update", "package_upgrade"],
+            candidates("Package-Update", allowed)
+        );
+        assert_eq!(
+            vec!["package", "packages"],
+            candidates("pakage", ["packages", "package"])
+        );
+        assert_eq!(vec!["snap"], candidates("snp", allowed));
+        assert!(candidates("bogus", allowed).is_empty());
+        assert!(candidates("packages", allowed).is_empty());
+    }
+
+    #[test]
+    fn distances() {
+        let distance = |a: &str, b: &str| distance(&normalize(a), &normalize(b));
+        assert_eq!(0, distance("lock-passwd", "lock_passwd"));
+        assert_eq!(1, distance("reboto", "reboot"));
+        assert_eq!(1, distance("b65", "b64"));
+        assert_eq!(3, distance("", "abc"));
+        assert_eq!(3, distance("kitten", "sitting"));
+    }
+}
@@ -0,0 +1,964 @@
+//! Detection of the type of user-data, the way cloud-init does, and checks specific to each type.
+use std::io::Read;
+
+use base64::Engine as _;
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::code::Code;
+use crate::error::Result;
+use crate::span::{SourceMap, Span};
+use crate::validator::ConfigError;
+
+/// Type of a user-data, serialized as its MIME type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContentType {
+    #[serde(rename = "text/cloud-config")]
+    CloudConfig,
+    #[serde(rename = "text/cloud-config-archive")]
+    CloudConfigArchive,
+    #[serde(rename = "text/cloud-config-jsonp")]
+    CloudConfigJsonp,
+    #[serde(rename = "text/cloud-boothook")]
+    CloudBoothook,
+    #[serde(rename = "text/part-handler")]
+    PartHandler,
+    #[serde(rename = "text/x-include-url")]
+    IncludeUrl,
+    #[serde(rename = "text/x-include-once-url")]
+    IncludeOnceUrl,
+    #[serde(rename = "text/x-shellscript")]
+    ShellScript,
+    #[serde(rename = "text/x-shellscript-per-boot")]
+    ShellScriptPerBoot,
+    #[serde(rename = "text/x-shellscript-per-instance")]
+    ShellScriptPerInstance,
+    #[serde(rename = "text/x-shellscript-per-once")]
+    ShellScriptPerOnce,
+    #[serde(rename = "text/jinja2")]
+    Jinja,
+    /// A MIME message, usually multipart.
+    #[serde(rename = "multipart/mixed")]
+    Multipart,
+    /// Not a type cloud-init handles: it ignores the user-data.
+    #[serde(rename = "text/x-not-multipart")]
+    Unknown,
+}
+
+/// Prefixes identifying each type, longest first, as in cloud-init's `INCLUSION_SRCH`.
+const PREFIXES: [(&str, ContentType); 12] = [
+    (
+        "text/x-shellscript-per-instance",
+        ContentType::ShellScriptPerInstance,
+    ),
+    (
+        "text/x-shellscript-per-boot",
+        ContentType::ShellScriptPerBoot,
+    ),
+    (
+        "text/x-shellscript-per-once",
+        ContentType::ShellScriptPerOnce,
+    ),
+    ("#cloud-config-archive", ContentType::CloudConfigArchive),
+    ("#cloud-config-jsonp", ContentType::CloudConfigJsonp),
+    ("## template: jinja", ContentType::Jinja),
+    ("#cloud-boothook", ContentType::CloudBoothook),
+    ("#include-once", ContentType::IncludeOnceUrl),
+    ("#cloud-config", ContentType::CloudConfig),
+    ("#part-handler", ContentType::PartHandler),
+    ("#include", ContentType::IncludeUrl),
+    ("#!", ContentType::ShellScript),
+];
+
+/// MIME types of the parts cloud-init handles, other than multipart and unknown.
+const MIME_TYPES: [(&str, ContentType); 12] = [
+    ("text/cloud-config", ContentType::CloudConfig),
+    ("text/cloud-config-archive", ContentType::CloudConfigArchive),
+    ("text/cloud-config-jsonp", ContentType::CloudConfigJsonp),
+    ("text/cloud-boothook", ContentType::CloudBoothook),
+    ("text/part-handler", ContentType::PartHandler),
+    ("text/x-include-url", ContentType::IncludeUrl),
+    ("text/x-include-once-url", ContentType::IncludeOnceUrl),
+    ("text/x-shellscript", ContentType::ShellScript),
+    (
+        "text/x-shellscript-per-boot",
+        ContentType::ShellScriptPerBoot,
+    ),
+    (
+        "text/x-shellscript-per-instance",
+        ContentType::ShellScriptPerInstance,
+    ),
+    (
+        "text/x-shellscript-per-once",
+        ContentType::ShellScriptPerOnce,
+    ),
+    ("text/jinja2", ContentType::Jinja),
+];
+
+/// MIME types of parts whose type is detected from their content, as cloud-init's `TYPE_NEEDED`.
+const TYPE_NEEDED: [&str; 2] = ["text/plain", "text/x-not-multipart"];
+
+/// MIME types of parts cloud-init decompresses, as its `DECOMP_TYPES`.
+const GZIP_TYPES: [&str; 8] = [
+    "application/gzip",
+    "application/gzip-compressed",
+    "application/gzipped",
+    "application/x-compress",
+    "application/x-compressed",
+    "application/x-gunzip",
+    "application/x-gzip",
+    "application/x-gzip-compressed",
+];
+
+/// Headers of cloud-config parts declaring how they are merged.
+const MERGE_HEADERS: [&str; 2] = ["Merge-Type", "X-Merge-Type"];
+
+const BOM: char = '\u{feff}';
+
+impl ContentType {
+    /// Type of `payload` by its prefix, ignoring case and leading whitespace, as cloud-init's
+    /// `type_from_starts_with`.
+    pub fn from_starts_with(payload: &str) -> Option<Self> {
+        let payload = payload
+            .trim_start_matches(is_python_whitespace)
+            .to_lowercase();
+        PREFIXES
+            .iter()
+            .find(|(prefix, _)| payload.starts_with(prefix))
+            .map(|(_, content_type)| *content_type)
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Multipart => "multipart/mixed",
+            Self::Unknown => "text/x-not-multipart",
+            _ => MIME_TYPES
+                .iter()
+                .find(|(_, content_type)| *content_type == self)
+                .map(|(mime_type, _)| *mime_type)
+                .expect("Every handled type has a MIME type"),
+        }
+    }
+
+    /// The type handled by cloud-init for the MIME type of a part.
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        MIME_TYPES
+            .iter()
+            .find(|(known, _)| *known == mime_type)
+            .map(|(_, content_type)| *content_type)
+    }
+
+    pub(crate) fn is_shell_script(self) -> bool {
+        matches!(
+            self,
+            Self::ShellScript
+                | Self::ShellScriptPerBoot
+                | Self::ShellScriptPerInstance
+                | Self::ShellScriptPerOnce
+        )
+    }
+}
+
+/// Python's `str.isspace`, which unlike [`char::is_whitespace`] includes the separators
+/// `\x1c`-`\x1f`. Neither includes the byte order mark.
+fn is_python_whitespace(c: char) -> bool {
+    c.is_whitespace() || ('\x1c'..='\x1f').contains(&c)
+}
+
+/// An encoding undone to reach the user-data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Base64,
+    Gzip,
+    #[serde(rename = "quoted-printable")]
+    QuotedPrintable,
+}
+
+/// A user-data and its detected type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserData {
+    pub content_type: ContentType,
+    /// Encodings of the payload, outermost first.
+    pub encodings: Vec<Encoding>,
+    /// The decoded user-data.
+    pub content: String,
+}
+
+impl UserData {
+    /// Decodes and classifies `payload`.
+    ///
+    /// As cloud-init, gzip-compressed payloads are decompressed and payloads of no known type
+    /// are ignored. Base64 is not decoded by cloud-init but by the datasources which declare it,
+    /// so it is only decoded if the payload is entirely base64 and decodes to a gzip stream or
+    /// to a known type.
+    pub fn detect(payload: &[u8]) -> Self {
+        let mut encodings = vec![];
+        let mut bytes = payload.to_vec();
+        if let Some(decoded) = decode_base64(&bytes) {
+            encodings.push(Encoding::Base64);
+            bytes = decoded;
+        }
+        if let Some(decompressed) = decompress_gzip(&bytes) {
+            encodings.push(Encoding::Gzip);
+            bytes = decompressed;
+        }
+        let content = String::from_utf8_lossy(&bytes).into_owned();
+        let content_type = if is_mime(&content) {
+            ContentType::Multipart
+        } else {
+            ContentType::from_starts_with(&content).unwrap_or(ContentType::Unknown)
+        };
+        Self {
+            content_type,
+            encodings,
+            content,
+        }
+    }
+}
+
+/// Whether cloud-init parses `payload` as a MIME message, as its `convert_string`.
+fn is_mime(payload: &str) -> bool {
+    let mut end = payload.len().min(4096);
+    while !payload.is_char_boundary(end) {
+        end -= 1;
+    }
+    payload[..end].to_lowercase().contains("mime-version:")
+}
+
+/// Where a part is within a multipart user-data.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartRef {
+    /// 0-based index of the part, counting the parts which are not multipart themselves.
+    pub index: usize,
+    /// File name from the part headers, otherwise the one cloud-init gives it.
+    pub filename: String,
+}
+
+/// A part of a multipart user-data and its type.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Part {
+    #[serde(flatten)]
+    pub reference: PartRef,
+    /// The MIME type cloud-init handles the part as, after detecting it if needed.
+    pub content_type: String,
+    /// Encodings of the part, outermost first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub encodings: Vec<Encoding>,
+}
+
+/// A part of a multipart user-data, decoded and classified as cloud-init's `_process_msg`.
+#[derive(Debug)]
+pub(crate) struct MimePart {
+    pub(crate) part: Part,
+    /// The part as a user-data, `None` if cloud-init ignores it.
+    pub(crate) user_data: Option<UserData>,
+    /// Byte offset of the part content in the message, if it is not encoded.
+    pub(crate) offset: Option<usize>,
+    /// Mergers declared by the `Merge-Type` header.
+    pub(crate) merge_type: Option<String>,
+    /// Errors of the part headers, located in the message.
+    pub(crate) errors: Vec<ConfigError>,
+}
+
+/// A cloud-config or JSON patch of a user-data, which cloud-init merges in order.
+#[derive(Debug)]
+pub(crate) struct ConfigPart {
+    /// `None` unless the user-data is multipart or an archive.
+    pub(crate) reference: Option<PartRef>,
+    pub(crate) content_type: ContentType,
+    pub(crate) content: String,
+    /// Mergers declared by the `Merge-Type` header.
+    pub(crate) merge_type: Option<String>,
+    /// Byte offset of the content in the user-data, if it is not encoded.
+    pub(crate) offset: Option<usize>,
+}
+
+/// Splits the MIME `message` into the parts cloud-init handles.
+pub(crate) fn parts(message: &str) -> Vec<MimePart> {
+    crate::mime::Entity::parse(message)
+        .leaves(message)
+        .into_iter()
+        .enumerate()
+        .map(|(index, entity)| mime_part(message, index, &entity))
+        .collect()
+}
+
+fn mime_part(message: &str, index: usize, entity: &crate::mime::Entity) -> MimePart {
+    let reference = PartRef {
+        index,
+        filename: entity
+            .filename()
+            .unwrap_or_else(|| format!("part-{:03}", index)),
+    };
+    let first_line_span = Span::first_line(entity.body).relocate(message, entity.body_offset);
+    let content_type_span = entity
+        .header("Content-Type")
+        .map_or(first_line_span, |header| header.span(message));
+    let error = |description: String, span: Span| {
+        let mut error = ConfigError::new(Code::Mime, description, String::new(), Some(span));
+        error.part = Some(reference.clone());
+        error
+    };
+
+    let mut errors = vec![];
+    let mut encodings = vec![];
+    let mut mime_type = entity.mime_type();
+    let mut offset = Some(entity.body_offset);
+    let mut bytes = match entity.decoded_body() {
+        Ok(None) => entity.body.as_bytes().to_vec(),
+        Ok(Some((encoding, decoded))) => {
+            encodings.push(encoding);
+            offset = None;
+            decoded
+        }
+        Err(e) => {
+            errors.push(error(e, first_line_span));
+            vec![]
+        }
+    };
+    if GZIP_TYPES.contains(&mime_type.as_str()) {
+        match decompress_gzip(&bytes) {
+            Some(decompressed) => {
+                encodings.push(Encoding::Gzip);
+                offset = None;
+                bytes = decompressed;
+                mime_type = TYPE_NEEDED[0].to_string();
+            }
+            None => errors.push(error(
+                format!(
+                    "Part of type \"{}\" is not gzip-compressed, cloud-init ignores it",
+                    mime_type
+                ),
+                content_type_span,
+            )),
+        }
+    }
+    let content = String::from_utf8_lossy(&bytes).into_owned();
+    let decoded = errors.is_empty();
+
+    let mut content_type = ContentType::from_mime_type(&mime_type);
+    if TYPE_NEEDED.contains(&mime_type.as_str()) {
+        content_type = ContentType::from_starts_with(&content);
+        if let Some(content_type) = content_type {
+            mime_type = content_type.mime_type().to_string();
+        } else if decoded {
+            errors.push(error(
+                format!(
+                    "Part of type \"{}\" does not begin with a known header, such as \
+                     \"#cloud-config\", cloud-init ignores it",
+                    mime_type
+                ),
+                first_line_span,
+            ));
+        }
+    } else if content_type.is_none() && decoded {
+        errors.push(error(
+            format!(
+                "Unknown content type \"{}\", cloud-init ignores the part",
+                mime_type
+            ),
+            content_type_span,
+        ));
+    }
+
+    let merge_type = MERGE_HEADERS
+        .iter()
+        .filter_map(|name| entity.header(name))
+        .map(|header| header.value.trim())
+        .find(|value| !value.is_empty())
+        .map(str::to_string);
+    if content_type == Some(ContentType::CloudConfig) {
+        for header in MERGE_HEADERS.iter().filter_map(|name| entity.header(name)) {
+            if let Err(e) = crate::merge::parse_mergers(&header.value) {
+                errors.push(error(
+                    format!("Invalid {} header: {}", header.name, e),
+                    header.span(message),
+                ));
+            }
+        }
+    }
+
+    MimePart {
+        part: Part {
+            reference,
+            content_type: mime_type,
+            encodings: encodings.clone(),
+        },
+        user_data: content_type
+            .filter(|_| decoded)
+            .map(|content_type| UserData {
+                content_type,
+                encodings,
+                content,
+            }),
+        offset,
+        merge_type,
+        errors,
+    }
+}
+
+/// The cloud-configs and JSON patches of `user_data`, as the parts cloud-init's cloud-config
+/// handler receives: multipart messages are split and archives exploded.
+pub(crate) fn config_parts(user_data: &UserData) -> Vec<ConfigPart> {
+    let content = &user_data.content;
+    match user_data.content_type {
+        ContentType::CloudConfig | ContentType::CloudConfigJsonp => vec![ConfigPart {
+            reference: None,
+            content_type: user_data.content_type,
+            content: content.clone(),
+            merge_type: None,
+            offset: Some(0),
+        }],
+        ContentType::CloudConfigArchive => archive_parts(content, None),
+        ContentType::Multipart => parts(content)
+            .into_iter()
+            .flat_map(|mime_part| {
+                let Some(part_data) = mime_part.user_data else {
+                    return vec![];
+                };
+                let reference = mime_part.part.reference;
+                match part_data.content_type {
+                    ContentType::CloudConfig | ContentType::CloudConfigJsonp => vec![ConfigPart {
+                        reference: Some(reference),
+                        content_type: part_data.content_type,
+                        content: part_data.content,
+                        merge_type: mime_part.merge_type,
+                        offset: mime_part.offset,
+                    }],
+                    ContentType::CloudConfigArchive => {
+                        archive_parts(&part_data.content, Some(&reference))
+                    }
+                    _ => vec![],
+                }
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// The cloud-configs and JSON patches of an archive. Entries of an archive within a multipart
+/// message are referred to by the part of the archive.
+fn archive_parts(content: &str, archive: Option<&PartRef>) -> Vec<ConfigPart> {
+    let Ok(Value::Array(entries)) = serde_yaml::from_str(content) else {
+        return vec![];
+    };
+    let string =
+        |entry: &Value, key: &str| entry.get(key).and_then(Value::as_str).map(str::to_string);
+    entries
+        .iter()
+        .enumerate()
+        .filter_map(|(index, entry)| {
+            let content = match entry {
+                Value::String(content) => content.clone(),
+                Value::Object(_) => string(entry, "content").unwrap_or_default(),
+                _ => return None,
+            };
+            let content_type = match string(entry, "type") {
+                Some(mime_type) => ContentType::from_mime_type(&mime_type.to_lowercase())?,
+                None => ContentType::from_starts_with(&content).unwrap_or(ContentType::CloudConfig),
+            };
+            if !matches!(
+                content_type,
+                ContentType::CloudConfig | ContentType::CloudConfigJsonp
+            ) {
+                return None;
+            }
+            let reference = archive.cloned().unwrap_or_else(|| PartRef {
+                index,
+                filename: string(entry, "filename").unwrap_or_else(|| format!("part-{:03}", index)),
+            });
+            Some(ConfigPart {
+                reference: Some(reference),
+                content_type,
+                content,
+                merge_type: MERGE_HEADERS.iter().find_map(|name| string(entry, name)),
+                offset: None,
+            })
+        })
+        .collect()
+}
+
+fn decode_base64(payload: &[u8]) -> Option<Vec<u8>> {
+    let payload: Vec<u8> = payload
+        .iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(payload)
+        .ok()?;
+    let known = is_gzip(&decoded)
+        || std::str::from_utf8(&decoded)
+            .ok()
+            .and_then(ContentType::from_starts_with)
+            .is_some();
+    known.then_some(decoded)
+}
+
+fn is_gzip(payload: &[u8]) -> bool {
+    payload.starts_with(&[0x1f, 0x8b])
+}
+
+fn decompress_gzip(payload: &[u8]) -> Option<Vec<u8>> {
+    if !is_gzip(payload) {
+        return None;
+    }
+    let mut decompressed = vec![];
+    GzDecoder::new(payload)
+        .read_to_end(&mut decompressed)
+        .ok()?;
+    Some(decompressed)
+}
+
+/// Checks a user-data which is neither a cloud-config nor of an unknown type.
+pub(crate) fn check(user_data: &UserData) -> Result<Vec<ConfigError>> {
+    let content = &user_data.content;
+    let errors = match user_data.content_type {
+        content_type if content_type.is_shell_script() => check_shell_script(content),
+        ContentType::IncludeUrl | ContentType::IncludeOnceUrl => check_include(content),
+        ContentType::PartHandler => check_part_handler(content),
+        ContentType::CloudConfigArchive => check_archive(content)?,
+        ContentType::CloudConfigJsonp => check_json_patch(content)?,
+        // Boothooks are converted to LF line endings by cloud-init, jinja templates are checked
+        // once rendered.
+        _ => vec![],
+    };
+    Ok(errors)
+}
+
+/// Errors for payloads cloud-init ignores because of a leading byte order mark.
+pub(crate) fn check_unknown(content: &str) -> Vec<ConfigError> {
+    match content.strip_prefix(BOM) {
+        Some(rest) if ContentType::from_starts_with(rest).is_some() => vec![ConfigError::new(
+            Code::Bom,
+            "User-data starts with a byte order mark (BOM), which prevents cloud-init from \
+             detecting its type"
+                .to_string(),
+            String::new(),
+            Some(Span::first_line(content)),
+        )],
+        _ => vec![],
+    }
+}
+
+/// Scripts are executed, which requires the interpreter line to be at their very beginning.
+fn check_shell_script(content: &str) -> Vec<ConfigError> {
+    let first_line = content.split('\n').next().unwrap_or_default();
+    if !content.starts_with("#!") {
+        vec![ConfigError::new(
+            Code::Script,
+            "Shell script does not begin with \"#!\" and cannot be executed".to_string(),
+            String::new(),
+            Some(Span::first_line(content)),
+        )]
+    } else if first_line.ends_with('\r') {
+        vec![ConfigError::new(
+            Code::Script,
+            format!(
+                "Shell script interpreter line ends with a carriage return, \"{}\" cannot be \
+                 found: convert the script to LF line endings",
+                first_line.trim_end_matches('\r').trim_start_matches("#!")
+            ),
+            String::new(),
+            Some(Span::first_line(content)),
+        )]
+    } else {
+        vec![]
+    }
+}
+
+/// Every line which is not a comment must be a URL or an absolute path, as cloud-init's
+/// `_do_include`.
+fn check_include(content: &str) -> Vec<ConfigError> {
+    let mut errors = vec![];
+    for (index, line) in content.lines().enumerate() {
+        let lowercase = line.to_lowercase();
+        let line = if lowercase.starts_with("#include-once") {
+            line["#include-once".len()..].trim_start()
+        } else if lowercase.starts_with("#include") {
+            line["#include".len()..].trim_start()
+        } else {
+            line
+        };
+        let url = line.trim();
+        if line.starts_with('#') || url.is_empty() {
+            continue;
+        }
+        if !url.starts_with('/') && reqwest::Url::parse(url).is_err() {
+            errors.push(ConfigError::new(
+                Code::Include,
+                format!("Included \"{}\" is not a URL", url),
+                String::new(),
+                Some(Span::line(content, index + 1)),
+            ));
+        }
+    }
+    errors
+}
+
+fn check_part_handler(content: &str) -> Vec<ConfigError> {
+    ["list_types", "handle_part"]
+        .into_iter()
+        .filter(|function| !content.contains(&format!("def {}(", function)))
+        .map(|function| {
+            ConfigError::new(
+                Code::PartHandler,
+                format!("Part handler does not define \"{}\"", function),
+                String::new(),
+                Some(Span::first_line(content)),
+            )
+        })
+        .collect()
+}
+
+/// A list of parts, each a string or a mapping with its `content`, as cloud-init's
+/// `_explode_archive`.
+fn check_archive(content: &str) -> Result<Vec<ConfigError>> {
+    let archive: Value = serde_yaml::from_str(content)?;
+    let source_map = SourceMap::from_yaml(content);
+    let error = |description: &str, instance_path: String| {
+        let span = source_map.span(&instance_path);
+        ConfigError::new(Code::Archive, description.to_string(), instance_path, span)
+    };
+
+    let Value::Array(parts) = archive else {
+        return Ok(vec![error(
+            "Cloud-config archive is not a list, cloud-init ignores it",
+            String::new(),
+        )]);
+    };
+    let mut errors = vec![];
+    for (index, part) in parts.iter().enumerate() {
+        let path = format!("/{}", index);
+        match part {
+            Value::String(_) => {}
+            Value::Object(part) => {
+                for key in ["content", "type", "filename"] {
+                    if matches!(part.get(key), Some(value) if !value.is_string()) {
+                        errors.push(error("Value is not a string", format!("{}/{}", path, key)));
+                    }
+                }
+                if matches!(part.get("launch-index"), Some(value) if !value.is_i64()) {
+                    errors.push(error(
+                        "Value is not an integer",
+                        format!("{}/launch-index", path),
+                    ));
+                }
+            }
+            _ => errors.push(error(
+                "Part is neither a string nor a mapping, cloud-init ignores it",
+                path,
+            )),
+        }
+    }
+    Ok(errors)
+}
+
+/// A JSON patch (RFC 6902) applied to the merged cloud-config.
+fn check_json_patch(content: &str) -> Result<Vec<ConfigError>> {
+    // The header is optional in parts declaring their type.
+    let trimmed = content.trim_start_matches(is_python_whitespace);
+    let patch = trimmed
+        .strip_prefix("#cloud-config-jsonp")
+        .unwrap_or(trimmed);
+    let patch: Value = serde_json::from_str(patch)?;
+    // The header is a YAML comment, so positions in JSON are the ones in the YAML document.
+    let source_map = SourceMap::from_yaml(content);
+    let error = |description: String, instance_path: String| {
+        let span = source_map.span(&instance_path);
+        ConfigError::new(Code::JsonPatch, description, instance_path, span)
+    };
+
+    let Value::Array(operations) = patch else {
+        return Ok(vec![error(
+            "JSON patch is not a list of operations".to_string(),
+            String::new(),
+        )]);
+    };
+    let mut errors = vec![];
+    for (index, operation) in operations.iter().enumerate() {
+        let path = format!("/{}", index);
+        let Value::Object(operation) = operation else {
+            errors.push(error("Operation is not an object".to_string(), path));
+            continue;
+        };
+        let required: &[&str] = match operation.get("op").and_then(Value::as_str) {
+            Some("add" | "replace" | "test") => &["path", "value"],
+            Some("remove") => &["path"],
+            Some("move" | "copy") => &["path", "from"],
+            Some(op) => {
+                errors.push(error(
+                    format!("\"{}\" is not a JSON patch operation", op),
+                    format!("{}/op", path),
+                ));
+                continue;
+            }
+            None => &["op"],
+        };
+        for member in required {
+            match operation.get(*member) {
+                None => errors.push(error(
+                    format!("\"{}\" is a required property", member),
+                    path.clone(),
+                )),
+                Some(value) if *member != "value" && !value.is_string() => errors.push(error(
+                    format!("{} is not of type \"string\"", value),
+                    format!("{}/{}", path, member),
+                )),
+                Some(_) => {}
+            }
+        }
+    }
+    Ok(errors)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    fn detect(payload: &str) -> ContentType {
+        UserData::detect(payload.as_bytes()).content_type
+    }
+
+    fn descriptions(errors: Vec<ConfigError>) -> Vec<String> {
+        errors.into_iter().map(|e| e.description).collect()
+    }
+
+    #[test]
+    fn content_types() {
+        assert_eq!(ContentType::CloudConfig, detect("#cloud-config\na: 1"));
+        assert_eq!(ContentType::CloudConfig, detect(" \r\n\t#Cloud-Config\r\n"));
+        assert_eq!(
+            ContentType::CloudConfigArchive,
+            detect("#cloud-config-archive\n- a")
+        );
+        assert_eq!(
+            ContentType::CloudConfigJsonp,
+            detect("#cloud-config-jsonp\n[]")
+        );
+        assert_eq!(
+            ContentType::IncludeOnceUrl,
+            detect("#include-once\nhttp://a")
+        );
+        assert_eq!(ContentType::IncludeUrl, detect("#include http://a"));
+        assert_eq!(ContentType::ShellScript, detect("#!/bin/sh\n"));
+        assert_eq!(
+            ContentType::Jinja,
+            detect("## template: jinja\n#cloud-config")
+        );
+        assert_eq!(ContentType::CloudBoothook, detect("#cloud-boothook\n"));
+        assert_eq!(ContentType::PartHandler, detect("#part-handler\n"));
+        assert_eq!(ContentType::Unknown, detect("a: 1"));
+        assert_eq!(ContentType::Unknown, detect("\u{feff}#cloud-config\n"));
+        assert_eq!(ContentType::CloudConfig, detect("\x1c#cloud-config\n"));
+    }
+
+    #[test]
+    fn encodings() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(b"#!/bin/sh\necho hi\n").unwrap();
+        let gzip = encoder.finish().unwrap();
+
+        let user_data = UserData::detect(&gzip);
+        assert_eq!(ContentType::ShellScript, user_data.content_type);
+        assert_eq!(vec![Encoding::Gzip], user_data.encodings);
+        assert_eq!("#!/bin/sh\necho hi\n", user_data.content);
+
+        let base64 = base64::engine::general_purpose::STANDARD.encode(&gzip);
+        let user_data = UserData::detect(format!("{}\n", base64).as_bytes());
+        assert_eq!(ContentType::ShellScript, user_data.content_type);
+        assert_eq!(vec![Encoding::Base64, Encoding::Gzip], user_data.encodings);
+
+        let user_data = UserData::detect(b"I2Nsb3VkLWNvbmZpZwo=");
+        assert_eq!(ContentType::CloudConfig, user_data.content_type);
+        assert_eq!("#cloud-config\n", user_data.content);
+
+        // Valid base64 which does not decode to user-data is left alone.
+        let user_data = UserData::detect(b"abcd");
+        assert_eq!(ContentType::Unknown, user_data.content_type);
+        assert!(user_data.encodings.is_empty());
+    }
+
+    #[test]
+    fn shell_scripts() {
+        let check =
+            |payload: &str| descriptions(check(&UserData::detect(payload.as_bytes())).unwrap());
+        assert!(check("#!/bin/sh\necho hi\n").is_empty());
+        assert_eq!(
+            vec![
+                "Shell script interpreter line ends with a carriage return, \"/bin/bash\" cannot \
+                  be found: convert the script to LF line endings"
+            ],
+            check("#!/bin/bash\r\necho hi\r\n")
+        );
+        assert_eq!(
+            vec!["Shell script does not begin with \"#!\" and cannot be executed"],
+            check("\n#!/bin/sh\n")
+        );
+        assert!(check("#cloud-boothook\r\necho hi\r\n").is_empty());
+    }
+
+    #[test]
+    fn includes() {
+        let errors = check(&UserData::detect(
+            b"#include https://a.example/x\n# comment\n\n/etc/cloud/x.cfg\n#include-once not a url\n",
+        ))
+        .unwrap();
+        assert_eq!(1, errors.len());
+        assert_eq!("Included \"not a url\" is not a URL", errors[0].description);
+        assert_eq!(5, errors[0].span.unwrap().start.line);
+    }
+
+    #[test]
+    fn part_handlers() {
+        let errors = check(&UserData::detect(
+            b"#part-handler\ndef list_types():\n    return []\n",
+        ))
+        .unwrap();
+        assert_eq!(
+            vec!["Part handler does not define \"handle_part\""],
+            descriptions(errors)
+        );
+    }
+
+    #[test]
+    fn archives() {
+        let archive =
+            "#cloud-config-archive\n- '#!/bin/sh'\n- content: 1\n  launch-index: a\n- 2\n";
+        let errors = check(&UserData::detect(archive.as_bytes())).unwrap();
+        let paths: Vec<_> = errors.iter().map(|e| e.instance_path.as_str()).collect();
+        assert_eq!(vec!["/1/content", "/1/launch-index", "/2"], paths);
+        assert_eq!(3, errors[0].span.unwrap().start.line);
+
+        let errors = check(&UserData::detect(b"#cloud-config-archive\na: 1\n")).unwrap();
+        assert_eq!(
+            vec!["Cloud-config archive is not a list, cloud-init ignores it"],
+            descriptions(errors)
+        );
+    }
+
+    #[test]
+    fn json_patches() {
+        let patch = r#"#cloud-config-jsonp
+[
+  {"op": "add", "path": "/runcmd/-", "value": "ls"},
+  {"op": "move", "path": "/a"},
+  {"op": "delete", "path": "/a"},
+  {"path": 1}
+]"#;
+        let errors = check(&UserData::detect(patch.as_bytes())).unwrap();
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|e| (e.instance_path.as_str(), e.description.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("/1", "\"from\" is a required property"),
+                ("/2/op", "\"delete\" is not a JSON patch operation"),
+                ("/3", "\"op\" is a required property"),
+            ],
+            errors
+        );
+
+        assert!(check(&UserData::detect(b"#cloud-config-jsonp\n{")).is_err());
+    }
+
+    const MULTIPART: &str = "Content-Type: multipart/mixed; boundary=\"b\"\n\
+MIME-Version: 1.0\n\
+\n\
+--b\n\
+Content-Type: text/cloud-config\n\
+Merge-Type: list(append)+dict(recurse_arrays)\n\
+\n\
+runcmd: [ls]\n\
+--b\n\
+Content-Type: text/plain\n\
+Content-Transfer-Encoding: base64\n\
+\n\
+IyEvYmluL3NoCg==\n\
+--b\n\
+Content-Type: text/yaml\n\
+Content-Disposition: attachment; filename=\"extra.yaml\"\n\
+\n\
+a: 1\n\
+--b--\n";
+
+    #[test]
+    fn multipart() {
+        assert_eq!(ContentType::Multipart, detect(MULTIPART));
+
+        let parts = parts(MULTIPART);
+        let summary: Vec<_> = parts
+            .iter()
+            .map(|part| {
+                (
+                    part.part.reference.filename.as_str(),
+                    part.part.content_type.as_str(),
+                    part.user_data
+                        .as_ref()
+                        .map(|user_data| user_data.content_type),
+                    part.offset,
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    "part-000",
+                    "text/cloud-config",
+                    Some(ContentType::CloudConfig),
+                    MULTIPART.find("runcmd")
+                ),
+                (
+                    "part-001",
+                    "text/x-shellscript",
+                    Some(ContentType::ShellScript),
+                    None
+                ),
+                ("extra.yaml", "text/yaml", None, MULTIPART.find("a: 1")),
+            ],
+            summary
+        );
+        assert_eq!(vec![Encoding::Base64], parts[1].part.encodings);
+
+        let errors: Vec<_> = parts
+            .iter()
+            .flat_map(|part| &part.errors)
+            .map(|error| {
+                (
+                    error.part.as_ref().unwrap().index,
+                    error.description.as_str(),
+                    error.span.unwrap().start.line,
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    0,
+                    "Invalid Merge-Type header: Unknown option \"recurse_arrays\" of merger \
+                     \"dict\", expected one of \"allow_delete\", \"no_replace\", \
+                     \"recurse_array\", \"recurse_list\", \"recurse_str\", \"replace\"",
+                    6
+                ),
+                (
+                    2,
+                    "Unknown content type \"text/yaml\", cloud-init ignores the part",
+                    15
+                ),
+            ],
+            errors
+        );
+    }
+
+    #[test]
+    fn byte_order_mark() {
+        let errors = check_unknown("\u{feff}#cloud-config\na: 1\n");
+        assert_eq!(1, errors.len());
+        assert!(check_unknown("a: 1\n").is_empty());
+    }
+}
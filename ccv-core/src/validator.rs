@@ -1,60 +1,966 @@
-use std::collections::VecDeque;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
 
-use crate::error::Result;
-use crate::schema::{ConfigKind, Schema};
-use jsonschema::output::{Annotations, BasicOutput, ErrorDescription, OutputUnit};
+use crate::code::{Code, Severity};
+use crate::error::{Error, Result};
+use crate::jinja::{self, InstanceData};
+use crate::lifecycle::{self, CloudInitVersion, Compatibility, DeprecationPolicy, Lifecycle};
+use crate::merge;
+use crate::schema::{ConfigKind, NetworkVersion, Schema, SchemaSource};
+use crate::semantic;
+use crate::span::{SourceMap, Span};
+use crate::suggest;
+use crate::userdata::{self, ConfigPart, ContentType, Encoding, Part, PartRef, UserData};
+use crate::yaml::{self, YamlVersion};
+use jsonschema::output::{BasicOutput, ErrorDescription, OutputUnit};
 use jsonschema::JSONSchema;
-use serde::{Deserialize, Serialize};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 
 const CLOUD_CONFIG_HEADER: &str = "#cloud-config";
+/// Top-level key of network-configs, which cloud-init accepts without it too.
+const NETWORK_KEY: &str = "network";
 
 #[derive(Debug, Deserialize)]
 pub struct CloudConfig {
     payload: String,
+    /// Whether to validate the cloud-config merged from the payload too.
+    #[serde(default)]
+    merged: bool,
+    /// Vendor-data merged with the payload, implying `merged`.
+    vendor_data: Option<String>,
+    /// Instance-data jinja templates are rendered with.
+    instance_data: Option<Value>,
+    #[serde(default)]
+    deprecations: DeprecationPolicy,
+    /// Bundled cloud-init release to validate against, such as `23.4`.
+    cloud_init_version: Option<String>,
 }
 
 impl CloudConfig {
     pub fn payload(&self) -> &str {
         &self.payload
     }
+
+    pub fn merged(&self) -> bool {
+        self.merged || self.vendor_data.is_some()
+    }
+
+    pub fn vendor_data(&self) -> Option<&str> {
+        self.vendor_data.as_deref()
+    }
+
+    pub fn instance_data(&self) -> Option<&Value> {
+        self.instance_data.as_ref()
+    }
+
+    pub fn deprecations(&self) -> DeprecationPolicy {
+        self.deprecations
+    }
+
+    pub fn cloud_init_version(&self) -> Option<&str> {
+        self.cloud_init_version.as_deref()
+    }
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize)]
-struct ConfigAnnotation {
+#[derive(Debug, Deserialize)]
+pub struct NetworkConfig {
+    payload: String,
+    /// Overrides the version detected from the payload.
+    version: Option<NetworkVersion>,
+    #[serde(default)]
+    deprecations: DeprecationPolicy,
+    /// Bundled cloud-init release to validate against, such as `23.4`.
+    cloud_init_version: Option<String>,
+}
+
+impl NetworkConfig {
+    pub fn payload(&self) -> &str {
+        &self.payload
+    }
+
+    /// The requested version, otherwise the one detected from the payload, defaulting to 1.
+    pub fn version(&self) -> NetworkVersion {
+        self.version
+            .or_else(|| NetworkVersion::detect(&self.payload))
+            .unwrap_or_default()
+    }
+
+    pub fn deprecations(&self) -> DeprecationPolicy {
+        self.deprecations
+    }
+
+    pub fn cloud_init_version(&self) -> Option<&str> {
+        self.cloud_init_version.as_deref()
+    }
+}
+
+/// A warning about a configuration cloud-init accepts.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ConfigAnnotation {
+    code: Code,
+    severity: Severity,
     description: String,
     instance_path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    span: Option<Span>,
+    #[serde(flatten)]
+    location: SchemaLocation,
+    #[serde(flatten)]
+    pub(crate) lifecycle: Lifecycle,
+    /// Part of a multipart user-data the instance path is relative to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    part: Option<PartRef>,
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize)]
-struct ConfigError {
-    description: String,
+/// An error cloud-init fails on, or ignores part of the configuration for.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ConfigError {
+    code: Code,
+    severity: Severity,
+    pub(crate) description: String,
+    pub(crate) instance_path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) span: Option<Span>,
+    #[serde(flatten)]
+    location: SchemaLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    schema_description: Option<String>,
+    /// Allowed property names or values close to the unexpected ones.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    suggestions: Vec<Suggestion>,
+    /// Every error of the `oneOf`/`anyOf` branches collapsed into this one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    detail: Vec<ErrorDetail>,
+    /// Part of a multipart user-data the instance path is relative to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) part: Option<PartRef>,
+}
+
+/// Where in the schema, and on which value, an error or annotation of a schema validation arose.
+/// Empty for the other ones.
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+struct SchemaLocation {
+    /// The failing JSON Schema keyword.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keyword: Option<String>,
+    /// JSON pointer to the keyword in the resolved schema, following the validation path.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    keyword_location: String,
+    /// URI of the keyword within the schema document it was resolved from, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    absolute_keyword_location: Option<String>,
+    /// The offending value of the instance.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_some",
+        skip_serializing_if = "Option::is_none"
+    )]
+    value: Option<Value>,
+}
+
+/// Deserializes a present value, `null` included, as `Some`.
+fn deserialize_some<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+impl SchemaLocation {
+    fn new(keyword_location: String) -> Self {
+        Self {
+            keyword: keyword_location
+                .rsplit_once('/')
+                .map(|(_, keyword)| keyword.to_string()),
+            keyword_location,
+            absolute_keyword_location: None,
+            value: None,
+        }
+    }
+
+    /// Sets the absolute location of the keyword in `schema` and the value at `instance_path` of
+    /// `instance`.
+    fn complete(&mut self, schema: &Schema, instance: &Value, instance_path: &str) {
+        if self.keyword.is_none() {
+            return;
+        }
+        self.absolute_keyword_location = schema.absolute_location(&self.keyword_location);
+        self.value = instance.pointer(instance_path).cloned();
+    }
+}
+
+/// An error of a `oneOf`/`anyOf` branch.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ErrorDetail {
+    pub description: String,
+    pub instance_path: String,
+    pub keyword_location: String,
+}
+
+impl From<&ConfigError> for ErrorDetail {
+    fn from(error: &ConfigError) -> Self {
+        Self {
+            description: error.description.clone(),
+            instance_path: error.instance_path.clone(),
+            keyword_location: error.location.keyword_location.clone(),
+        }
+    }
+}
+
+/// A `oneOf`/`anyOf` branch an error comes from.
+struct Branch {
+    /// Keyword location of the combinator.
+    combinator: String,
+    index: usize,
+    /// Instance path the combinator applies to.
     instance_path: String,
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize)]
+impl Branch {
+    /// The first branch in the keyword location of `error` past its byte `from`.
+    fn of(error: &ConfigError, from: usize) -> Option<Self> {
+        let keyword_location = &error.location.keyword_location;
+        // Keywords and their end offset.
+        let segments: Vec<(&str, usize)> = keyword_location
+            .split('/')
+            .skip(1)
+            .scan(0, |end, segment| {
+                *end += 1 + segment.len();
+                Some((segment, *end))
+            })
+            .collect();
+        // Instance path segments consumed by the keywords so far.
+        let mut depth = 0;
+        let mut i = 0;
+        while i < segments.len() {
+            let (keyword, end) = segments[i];
+            match keyword {
+                "oneOf" | "anyOf" if end > from => {
+                    let index = segments.get(i + 1)?.0.parse().ok()?;
+                    let instance_path = error
+                        .instance_path
+                        .split('/')
+                        .take(depth + 1)
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    return Some(Self {
+                        combinator: keyword_location[..end].to_string(),
+                        index,
+                        instance_path,
+                    });
+                }
+                "properties" | "patternProperties" => {
+                    depth += 1;
+                    i += 1;
+                }
+                "additionalProperties" | "additionalItems" | "contains" => depth += 1,
+                "items" => {
+                    depth += 1;
+                    // Tuple items are followed by their index.
+                    if segments
+                        .get(i + 1)
+                        .is_some_and(|(index, _)| index.parse::<usize>().is_ok())
+                    {
+                        i += 1;
+                    }
+                }
+                "allOf" | "oneOf" | "anyOf" | "dependencies" => i += 1,
+                _ => {}
+            }
+            i += 1;
+        }
+        None
+    }
+}
+
+/// The allowed property names or values likely meant instead of an unexpected one.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Suggestion {
+    pub found: String,
+    /// Closest first.
+    pub candidates: Vec<String>,
+}
+
+impl Suggestion {
+    fn new<'a>(found: &str, allowed: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        let candidates = suggest::candidates(found, allowed);
+        (!candidates.is_empty()).then(|| Self {
+            found: found.to_string(),
+            candidates,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Validation {
     pub is_valid: bool,
     annotations: Vec<ConfigAnnotation>,
     errors: VecDeque<ConfigError>,
+    /// Detected type of a user-data.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_type: Option<ContentType>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    encodings: Vec<Encoding>,
+    /// Parts of a multipart user-data.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    parts: Vec<Part>,
+    /// The cloud-config merged from the user-data, if requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    merged: Option<Box<Merged>>,
+    /// Compatibility with a target cloud-init version, if requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    compatibility: Option<Compatibility>,
 }
 
-impl From<&OutputUnit<Annotations<'_>>> for ConfigAnnotation {
-    fn from(output_unit: &OutputUnit<Annotations>) -> Self {
+/// The cloud-config cloud-init merges from the parts of a user-data, and possibly vendor-data,
+/// before running its modules.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Merged {
+    pub config: Value,
+    /// Validation of the merged cloud-config. Errors and annotations are located in the user-data
+    /// part which last set the value they refer to.
+    pub validation: Validation,
+}
+
+/// A merged cloud-config part and the configuration it contributed.
+struct MergedPart {
+    part: ConfigPart,
+    config: Value,
+}
+
+impl ConfigError {
+    pub fn new(code: Code, description: String, instance_path: String, span: Option<Span>) -> Self {
         Self {
-            description: output_unit.value().to_string(),
-            instance_path: output_unit.instance_location().to_string(),
+            code,
+            severity: code.severity(),
+            description,
+            instance_path,
+            span,
+            location: SchemaLocation::default(),
+            schema_description: None,
+            suggestions: vec![],
+            detail: vec![],
+            part: None,
+        }
+    }
+
+    pub fn code(&self) -> Code {
+        self.code
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// JSON pointer to the offending value, relative to the part in `part`, if any.
+    pub fn instance_path(&self) -> &str {
+        &self.instance_path
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    /// The failing JSON Schema keyword, for errors of the schema validation.
+    pub fn keyword(&self) -> Option<&str> {
+        self.location.keyword.as_deref()
+    }
+
+    /// JSON pointer to the failing keyword in the resolved schema, empty for errors which are not
+    /// of the schema validation.
+    pub fn keyword_location(&self) -> &str {
+        &self.location.keyword_location
+    }
+
+    pub fn absolute_keyword_location(&self) -> Option<&str> {
+        self.location.absolute_keyword_location.as_deref()
+    }
+
+    /// The offending value, for errors of the schema validation.
+    pub fn value(&self) -> Option<&Value> {
+        self.location.value.as_ref()
+    }
+
+    pub fn schema_description(&self) -> Option<&str> {
+        self.schema_description.as_deref()
+    }
+
+    /// Allowed property names or values close to the unexpected ones.
+    pub fn suggestions(&self) -> &[Suggestion] {
+        &self.suggestions
+    }
+
+    /// Every error of the `oneOf`/`anyOf` branches collapsed into this one.
+    pub fn detail(&self) -> &[ErrorDetail] {
+        &self.detail
+    }
+
+    /// Part of a multipart user-data the instance path is relative to.
+    pub fn part(&self) -> Option<&PartRef> {
+        self.part.as_ref()
+    }
+
+    /// Sets the location of the failing keyword in the schema.
+    pub fn with_keyword_location(mut self, keyword_location: &str) -> Self {
+        self.location = SchemaLocation {
+            value: self.location.value,
+            ..SchemaLocation::new(keyword_location.to_string())
+        };
+        self
+    }
+
+    /// Sets the offending value.
+    pub fn with_value(mut self, value: Value) -> Self {
+        self.location.value = Some(value);
+        self
+    }
+
+    pub fn with_part(mut self, part: PartRef) -> Self {
+        self.part = Some(part);
+        self
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_problem(
+            f,
+            self.severity,
+            self.code,
+            &self.description,
+            &self.instance_path,
+            self.span,
+        )
+    }
+}
+
+impl ConfigAnnotation {
+    pub fn new(code: Code, description: String, instance_path: String, span: Option<Span>) -> Self {
+        Self {
+            code,
+            severity: code.severity(),
+            description,
+            instance_path,
+            span,
+            location: SchemaLocation::default(),
+            lifecycle: Lifecycle::default(),
+            part: None,
+        }
+    }
+
+    pub fn code(&self) -> Code {
+        self.code
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// JSON pointer to the value annotated, relative to the part in `part`, if any.
+    pub fn instance_path(&self) -> &str {
+        &self.instance_path
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    /// The annotating JSON Schema keyword, such as `deprecated`, for annotations of the schema
+    /// validation.
+    pub fn keyword(&self) -> Option<&str> {
+        self.location.keyword.as_deref()
+    }
+
+    /// JSON pointer to the annotating keyword in the resolved schema, empty for annotations which
+    /// are not of the schema validation.
+    pub fn keyword_location(&self) -> &str {
+        &self.location.keyword_location
+    }
+
+    pub fn absolute_keyword_location(&self) -> Option<&str> {
+        self.location.absolute_keyword_location.as_deref()
+    }
+
+    /// The value annotated, for annotations of the schema validation.
+    pub fn value(&self) -> Option<&Value> {
+        self.location.value.as_ref()
+    }
+
+    /// The versions the annotated key or value was added, changed or deprecated in.
+    pub fn lifecycle(&self) -> &Lifecycle {
+        &self.lifecycle
+    }
+
+    /// Whether the annotation is about a deprecated or changed key or value.
+    pub fn is_deprecation(&self) -> bool {
+        matches!(self.code, Code::Deprecated | Code::Changed)
+    }
+
+    /// Part of a multipart user-data the instance path is relative to.
+    pub fn part(&self) -> Option<&PartRef> {
+        self.part.as_ref()
+    }
+
+    /// Sets the location of the annotating keyword in the schema.
+    pub fn with_keyword_location(mut self, keyword_location: &str) -> Self {
+        self.location = SchemaLocation {
+            value: self.location.value,
+            ..SchemaLocation::new(keyword_location.to_string())
+        };
+        self
+    }
+
+    /// Sets the value annotated.
+    pub fn with_value(mut self, value: Value) -> Self {
+        self.location.value = Some(value);
+        self
+    }
+
+    pub fn with_part(mut self, part: PartRef) -> Self {
+        self.part = Some(part);
+        self
+    }
+}
+
+impl fmt::Display for ConfigAnnotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_problem(
+            f,
+            self.severity,
+            self.code,
+            &self.description,
+            &self.instance_path,
+            self.span,
+        )
+    }
+}
+
+/// A deprecation reported as an error, keeping its code.
+impl From<ConfigAnnotation> for ConfigError {
+    fn from(annotation: ConfigAnnotation) -> Self {
+        Self {
+            severity: Severity::Error,
+            location: annotation.location,
+            part: annotation.part,
+            ..Self::new(
+                annotation.code,
+                annotation.description,
+                annotation.instance_path,
+                annotation.span,
+            )
+        }
+    }
+}
+
+/// Writes a problem as `error[CODE]: description at /instance/path (line 1 column 2)`.
+fn write_problem(
+    f: &mut fmt::Formatter<'_>,
+    severity: Severity,
+    code: Code,
+    description: &str,
+    instance_path: &str,
+    span: Option<Span>,
+) -> fmt::Result {
+    write!(f, "{}[{}]: {}", severity, code, description)?;
+    if !instance_path.is_empty() {
+        write!(f, " at {}", instance_path)?;
+    }
+    if let Some(span) = span {
+        write!(
+            f,
+            " (line {} column {})",
+            span.start.line, span.start.column
+        )?;
+    }
+    Ok(())
+}
+
+/// An error or annotation of a [`Validation`].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Diagnostic<'a> {
+    Error(&'a ConfigError),
+    Annotation(&'a ConfigAnnotation),
+}
+
+impl Diagnostic<'_> {
+    pub fn code(&self) -> Code {
+        match self {
+            Self::Error(error) => error.code(),
+            Self::Annotation(annotation) => annotation.code(),
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::Error(error) => error.severity(),
+            Self::Annotation(annotation) => annotation.severity(),
+        }
+    }
+
+    pub fn description(&self) -> &str {
+        match self {
+            Self::Error(error) => error.description(),
+            Self::Annotation(annotation) => annotation.description(),
+        }
+    }
+
+    pub fn instance_path(&self) -> &str {
+        match self {
+            Self::Error(error) => error.instance_path(),
+            Self::Annotation(annotation) => annotation.instance_path(),
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::Error(error) => error.span(),
+            Self::Annotation(annotation) => annotation.span(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error(error) => error.fmt(f),
+            Self::Annotation(annotation) => annotation.fmt(f),
         }
     }
 }
 
+impl From<yaml::Warning> for ConfigAnnotation {
+    fn from(warning: yaml::Warning) -> Self {
+        Self::new(
+            warning.code,
+            warning.description,
+            warning.instance_path,
+            Some(warning.span),
+        )
+    }
+}
+
 impl From<&OutputUnit<ErrorDescription>> for ConfigError {
     fn from(output_unit: &OutputUnit<ErrorDescription>) -> Self {
+        let location = SchemaLocation::new(output_unit.keyword_location().to_string());
+        let mut error = Self::new(
+            location
+                .keyword
+                .as_deref()
+                .map_or(Code::Schema, Code::from_keyword),
+            output_unit.error_description().to_string(),
+            output_unit.instance_location().to_string(),
+            None,
+        );
+        error.location = location;
+        error
+    }
+}
+
+impl Validation {
+    fn from_errors(errors: Vec<ConfigError>) -> Self {
         Self {
-            description: output_unit.error_description().to_string(),
-            instance_path: output_unit.instance_location().to_string(),
+            is_valid: errors.is_empty(),
+            annotations: vec![],
+            errors: errors.into(),
+            content_type: None,
+            encodings: vec![],
+            parts: vec![],
+            merged: None,
+            compatibility: None,
         }
     }
+
+    /// Starts a validation to be completed with errors and annotations, mostly for tests.
+    pub fn builder() -> ValidationBuilder {
+        ValidationBuilder(Self::from_errors(vec![]))
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &ConfigError> {
+        self.errors.iter()
+    }
+
+    pub fn annotations(&self) -> impl Iterator<Item = &ConfigAnnotation> {
+        self.annotations.iter()
+    }
+
+    /// Every error, then every annotation.
+    pub fn diagnostics(&self) -> impl Iterator<Item = Diagnostic<'_>> {
+        self.errors
+            .iter()
+            .map(Diagnostic::Error)
+            .chain(self.annotations.iter().map(Diagnostic::Annotation))
+    }
+
+    /// The errors and annotations of `severity`.
+    pub fn with_severity(&self, severity: Severity) -> impl Iterator<Item = Diagnostic<'_>> {
+        self.diagnostics()
+            .filter(move |diagnostic| diagnostic.severity() == severity)
+    }
+
+    /// Detected type of a user-data, `None` for network-configs.
+    pub fn content_type(&self) -> Option<ContentType> {
+        self.content_type
+    }
+
+    /// Encodings of a user-data, outermost first.
+    pub fn encodings(&self) -> &[Encoding] {
+        &self.encodings
+    }
+
+    /// Parts of a multipart user-data.
+    pub fn parts(&self) -> &[Part] {
+        &self.parts
+    }
+
+    /// The cloud-config merged from the user-data, if requested.
+    pub fn merged(&self) -> Option<&Merged> {
+        self.merged.as_deref()
+    }
+
+    /// Compatibility with a target cloud-init version, if requested.
+    pub fn compatibility(&self) -> Option<&Compatibility> {
+        self.compatibility.as_ref()
+    }
+
+    /// Sets the absolute keyword location and the value of `instance` of every error and
+    /// annotation.
+    fn complete(&mut self, schema: &Schema, instance: &Value) {
+        for error in self.errors.iter_mut() {
+            error
+                .location
+                .complete(schema, instance, &error.instance_path);
+        }
+        for annotation in self.annotations.iter_mut() {
+            annotation
+                .location
+                .complete(schema, instance, &annotation.instance_path);
+        }
+    }
+
+    /// Sets the description of the schema every error failed to validate against.
+    fn describe(&mut self, schema: &Schema) {
+        for error in self.errors.iter_mut() {
+            error.schema_description = schema
+                .description(&error.location.keyword_location)
+                .map(str::to_string);
+        }
+    }
+
+    /// Collapses the errors of every failed `oneOf`/`anyOf` of `instance` into the ones of its most
+    /// likely intended branch. The first of them details the errors of every branch.
+    fn collapse(&mut self, schema: &Schema, instance: &Value) {
+        let errors = std::mem::take(&mut self.errors);
+        self.errors = collapse(errors.into(), 0, schema.schema(), instance).into();
+    }
+
+    /// Suggests the allowed names and values closest to the unexpected properties of
+    /// `additionalProperties` errors and the values of `enum` ones of `instance`.
+    fn suggest(&mut self, schema: &Schema, instance: &Value) {
+        for error in self.errors.iter_mut() {
+            let Some((parent, keyword)) = error.location.keyword_location.rsplit_once('/') else {
+                continue;
+            };
+            error.suggestions = match (keyword, instance.pointer(&error.instance_path)) {
+                ("additionalProperties", Some(Value::Object(object))) => {
+                    let subschema = schema.schema().pointer(parent);
+                    let properties = subschema
+                        .and_then(|subschema| subschema.get("properties"))
+                        .and_then(Value::as_object);
+                    let patterns: Vec<Regex> = subschema
+                        .and_then(|subschema| subschema.get("patternProperties"))
+                        .and_then(Value::as_object)
+                        .map(|patterns| {
+                            patterns
+                                .keys()
+                                .filter_map(|pattern| Regex::new(pattern).ok())
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    // Deprecated aliases are not worth suggesting.
+                    let allowed: Vec<&str> = properties
+                        .into_iter()
+                        .flatten()
+                        .filter(|(_, property)| {
+                            property.get("deprecated") != Some(&Value::Bool(true))
+                        })
+                        .map(|(name, _)| name.as_str())
+                        .collect();
+                    object
+                        .keys()
+                        .filter(|key| {
+                            !properties.is_some_and(|properties| properties.contains_key(*key))
+                                && !patterns.iter().any(|pattern| pattern.is_match(key))
+                        })
+                        .filter_map(|key| Suggestion::new(key, allowed.iter().copied()))
+                        .collect()
+                }
+                ("enum", Some(Value::String(value))) => {
+                    let allowed = schema
+                        .schema()
+                        .pointer(&error.location.keyword_location)
+                        .and_then(Value::as_array);
+                    Suggestion::new(
+                        value,
+                        allowed.into_iter().flatten().filter_map(Value::as_str),
+                    )
+                    .into_iter()
+                    .collect()
+                }
+                _ => vec![],
+            };
+        }
+    }
+
+    /// Sets the location in the source of every annotation and error.
+    fn locate(&mut self, source_map: &SourceMap) {
+        for annotation in self.annotations.iter_mut() {
+            annotation.span = source_map.span(&annotation.instance_path);
+        }
+        for error in self.errors.iter_mut() {
+            error.span = source_map.span(&error.instance_path);
+        }
+    }
+
+    /// Marks every annotation and error as relative to `part`, whose content starts at `offset`
+    /// of `message`. Spans are relocated to `message`, or dropped if the part is encoded.
+    fn place(&mut self, part: &PartRef, message: &str, offset: Option<usize>) {
+        let relocate = |span: Option<Span>| {
+            span.zip(offset)
+                .map(|(span, offset)| span.relocate(message, offset))
+        };
+        for annotation in self.annotations.iter_mut() {
+            annotation.span = relocate(annotation.span);
+            annotation.part = Some(part.clone());
+        }
+        for error in self.errors.iter_mut() {
+            error.span = relocate(error.span);
+            error.part = Some(part.clone());
+        }
+    }
+
+    /// Analyzes the compatibility with `target` of the validated configuration, and of the merged
+    /// one, if any, from the lifecycle of the keys and values it was annotated with. Validity is
+    /// left as is.
+    pub fn with_compatibility(mut self, target: CloudInitVersion) -> Self {
+        self.compatibility = Some(Compatibility::analyze(&self.annotations, target));
+        match self.merged.take() {
+            Some(mut merged) => {
+                merged.validation = merged.validation.with_compatibility(target);
+                self.with_merged(*merged)
+            }
+            None => self,
+        }
+    }
+
+    /// Reports deprecations, including changed keys and values, following `policy`: as errors, as
+    /// annotations, which they are by default, or not at all.
+    pub fn with_deprecation_policy(mut self, policy: DeprecationPolicy) -> Self {
+        if policy != DeprecationPolicy::Warn {
+            let (deprecations, annotations) = std::mem::take(&mut self.annotations)
+                .into_iter()
+                .partition(ConfigAnnotation::is_deprecation);
+            self.annotations = annotations;
+            if policy == DeprecationPolicy::Error {
+                self.errors.extend(
+                    deprecations
+                        .into_iter()
+                        .map(ConfigError::from)
+                        .collect::<Vec<_>>(),
+                );
+                self.is_valid &= self.errors.is_empty();
+            }
+        }
+        match self.merged.take() {
+            Some(mut merged) => {
+                merged.validation = merged.validation.with_deprecation_policy(policy);
+                self.with_merged(*merged)
+            }
+            None => self,
+        }
+    }
+
+    /// Adds the cloud-config merged from the validated user-data, which is then valid only if the
+    /// merged one is too.
+    pub fn with_merged(mut self, merged: Merged) -> Self {
+        self.is_valid &= merged.validation.is_valid;
+        self.merged = Some(Box::new(merged));
+        self
+    }
+
+    /// Removes `prefix` from every instance path, for instances validated wrapped in a key.
+    fn strip_prefix(&mut self, prefix: &str) {
+        let strip = |path: &mut String| {
+            if let Some(stripped) = path.strip_prefix(prefix) {
+                *path = stripped.to_string();
+            }
+        };
+        self.annotations
+            .iter_mut()
+            .for_each(|annotation| strip(&mut annotation.instance_path));
+        for error in self.errors.iter_mut() {
+            strip(&mut error.instance_path);
+            error
+                .detail
+                .iter_mut()
+                .for_each(|detail| strip(&mut detail.instance_path));
+        }
+    }
+}
+
+/// Writes every error and annotation on a line of its own.
+impl fmt::Display for Validation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in self.diagnostics() {
+            writeln!(f, "{}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds a [`Validation`], valid unless it is given errors.
+#[derive(Debug)]
+pub struct ValidationBuilder(Validation);
+
+impl ValidationBuilder {
+    pub fn error(mut self, error: ConfigError) -> Self {
+        self.0.errors.push_back(error);
+        self
+    }
+
+    pub fn annotation(mut self, annotation: ConfigAnnotation) -> Self {
+        self.0.annotations.push(annotation);
+        self
+    }
+
+    pub fn content_type(mut self, content_type: ContentType) -> Self {
+        self.0.content_type = Some(content_type);
+        self
+    }
+
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.0.encodings.push(encoding);
+        self
+    }
+
+    pub fn part(mut self, part: Part) -> Self {
+        self.0.parts.push(part);
+        self
+    }
+
+    pub fn build(mut self) -> Validation {
+        self.0.is_valid = self.0.errors.is_empty();
+        self.0
+    }
 }
 
 impl From<BasicOutput<'_>> for Validation {
@@ -65,53 +971,22 @@ impl From<BasicOutput<'_>> for Validation {
                 for annotation in out_annotations {
                     // XXX: avoid to_mut copy
                     if let Value::Object(obj) = &annotation.value().to_mut() {
-                        // Build deprecation description
-                        if let Some(Value::Bool(true)) = obj.get("deprecated") {
-                            let mut description = String::from("Deprecated");
-                            if let Some(Value::String(cloud_init_version)) =
-                                obj.get("changed_version")
-                            {
-                                description.push_str(" in version ");
-                                description.push_str(cloud_init_version.as_str());
-                            }
-
-                            if let Some(Value::String(dsc)) = obj.get("deprecated_description") {
-                                description.push_str(". ");
-                                description.push_str(dsc.as_str());
-                            }
-
-                            let new_annotation: ConfigAnnotation = ConfigAnnotation {
-                                description,
-                                instance_path: annotation.instance_location().to_string(),
-                            };
-                            annotations.push(new_annotation);
-                        } else if let Some(Value::Bool(true)) = obj.get("changed") {
-                            let mut description = String::from("Changed");
-
-                            if let Some(Value::String(cloud_init_version)) =
-                                obj.get("changed_version")
-                            {
-                                description.push_str(" in version ");
-                                description.push_str(cloud_init_version.as_str());
-                            }
-
-                            if let Some(Value::String(dsc)) = obj.get("changed_description") {
-                                description.push_str(". ");
-                                description.push_str(dsc.as_str());
-                            }
-
-                            let new_annotation: ConfigAnnotation = ConfigAnnotation {
-                                description,
-                                instance_path: annotation.instance_location().to_string(),
-                            };
-                            annotations.push(new_annotation);
-                        }
+                        annotations.extend(lifecycle::annotation(
+                            obj,
+                            &annotation.keyword_location().to_string(),
+                            &annotation.instance_location().to_string(),
+                        ));
                     }
                 }
                 Self {
                     is_valid: true,
                     annotations,
                     errors: VecDeque::new(),
+                    content_type: None,
+                    encodings: vec![],
+                    parts: vec![],
+                    merged: None,
+                    compatibility: None,
                 }
             }
             BasicOutput::Invalid(out_errors) => {
@@ -123,6 +998,11 @@ impl From<BasicOutput<'_>> for Validation {
                     is_valid: false,
                     annotations: vec![],
                     errors,
+                    content_type: None,
+                    encodings: vec![],
+                    parts: vec![],
+                    merged: None,
+                    compatibility: None,
                 }
             }
         }
@@ -132,52 +1012,306 @@ impl From<BasicOutput<'_>> for Validation {
 #[derive(Debug)]
 pub struct Validator {
     json_schema: JSONSchema,
-    kind: ConfigKind,
+    schema: Schema,
+    yaml_version: YamlVersion,
 }
 
 impl Validator {
     pub async fn new(kind: ConfigKind) -> Result<Self> {
-        let schema = Schema::get(kind.clone()).await?;
-        Ok(Validator {
-            kind,
-            json_schema: jsonschema_try_from(schema.schema())?,
-        })
+        Self::from_schema(Schema::get(kind).await?)
+    }
+
+    pub async fn from_source(source: &dyn SchemaSource, kind: ConfigKind) -> Result<Self> {
+        Self::from_schema(Schema::from_source(source, kind).await?)
     }
 
     pub fn from_vendored_schema() -> Result<Self> {
-        let schema = Schema::from_vendored()?;
+        Self::from_schema(Schema::from_vendored()?)
+    }
+
+    /// Validates against the schema of `kind` of the bundled cloud-init `release`, such as `23.4`.
+    pub fn for_release(release: &str, kind: ConfigKind) -> Result<Self> {
+        Self::from_schema(Schema::from_release(release, kind)?)
+    }
+
+    pub fn from_schema(schema: Schema) -> Result<Self> {
+        let yaml_version = match schema.kind() {
+            ConfigKind::CloudConfig => YamlVersion::V1_1,
+            _ => YamlVersion::V1_2,
+        };
         Ok(Validator {
-            kind: ConfigKind::CloudConfig,
             json_schema: jsonschema_try_from(schema.schema())?,
+            schema,
+            yaml_version,
         })
     }
 
-    pub fn validate(&self, inst: &Value) -> Validation {
-        self.json_schema.apply(inst).basic().into()
+    /// Loads payloads as YAML `version`, instead of YAML 1.1 for cloud-configs, as cloud-init
+    /// does, and YAML 1.2 for network-configs.
+    pub fn with_yaml_version(mut self, version: YamlVersion) -> Self {
+        self.yaml_version = version;
+        self
+    }
+
+    pub fn validate(&self, inst: &Value) -> Validation {
+        let mut validation: Validation = self.json_schema.apply(inst).basic().into();
+        if !validation.is_valid {
+            // Annotations of invalid instances are dropped by `jsonschema`.
+            validation.annotations = lifecycle::annotate(self.schema.schema(), inst);
+        }
+        validation.collapse(&self.schema, inst);
+        validation.complete(&self.schema, inst);
+        validation.describe(&self.schema);
+        validation.suggest(&self.schema, inst);
+
+        let findings = semantic::check(self.schema.kind(), inst);
+        validation.annotations.extend(findings.annotations);
+        validation.errors.extend(findings.errors);
+        validation.is_valid = validation.errors.is_empty();
+        validation
+    }
+
+    pub fn validate_yaml(&self, payload: &str) -> Result<Validation> {
+        let check_header = matches!(self.schema.kind(), ConfigKind::CloudConfig);
+        // Templates are not YAML until rendered.
+        if check_header && ContentType::from_starts_with(payload) == Some(ContentType::Jinja) {
+            return Ok(analyze_template(payload));
+        }
+        self.validate_document(payload, check_header)
+    }
+
+    /// Validates a user-data according to its detected type: cloud-configs against the schema,
+    /// other types with the checks specific to them.
+    ///
+    /// User-data of an unknown type is validated as a cloud-config missing its header.
+    pub fn validate_user_data(&self, user_data: &UserData) -> Result<Validation> {
+        self.validate_user_data_with(user_data, None)
+    }
+
+    /// Validates a user-data as `validate_user_data`, rendering jinja templates with
+    /// `instance_data` first, as cloud-init does. Errors of rendered templates are located in the
+    /// template and their undefined variables are reported as annotations.
+    pub fn validate_templated_user_data(
+        &self,
+        user_data: &UserData,
+        instance_data: &InstanceData,
+    ) -> Result<Validation> {
+        self.validate_user_data_with(user_data, Some(instance_data))
+    }
+
+    fn validate_user_data_with(
+        &self,
+        user_data: &UserData,
+        instance_data: Option<&InstanceData>,
+    ) -> Result<Validation> {
+        let content = &user_data.content;
+        let mut validation = match (user_data.content_type, instance_data) {
+            (ContentType::CloudConfig, _) => self.validate_document(content, false)?,
+            (ContentType::Multipart, _) => self.validate_multipart(content, instance_data),
+            (ContentType::Jinja, Some(instance_data)) => {
+                self.validate_template(content, instance_data)
+            }
+            (ContentType::Jinja, None) => analyze_template(content),
+            (ContentType::Unknown, _) => {
+                let mut validation = self.validate_document(content, true)?;
+                validation.errors.extend(userdata::check_unknown(content));
+                validation.is_valid = validation.errors.is_empty();
+                validation
+            }
+            _ => Validation::from_errors(userdata::check(user_data)?),
+        };
+        validation.content_type = Some(user_data.content_type);
+        validation.encodings = user_data.encodings.clone();
+        Ok(validation)
+    }
+
+    /// Validates every part of a MIME user-data. Errors and annotations refer to their part and
+    /// are located in the whole message.
+    fn validate_multipart(
+        &self,
+        message: &str,
+        instance_data: Option<&InstanceData>,
+    ) -> Validation {
+        let mut validation = Validation::from_errors(vec![]);
+        for mime_part in userdata::parts(message) {
+            validation.errors.extend(mime_part.errors);
+            if let Some(user_data) = &mime_part.user_data {
+                let mut part_validation = self
+                    .validate_user_data_with(user_data, instance_data)
+                    // Unlike a whole user-data, a part which cannot be parsed is one more error.
+                    .unwrap_or_else(|e| {
+                        Validation::from_errors(vec![parse_error(&e, &user_data.content)])
+                    });
+                part_validation.place(&mime_part.part.reference, message, mime_part.offset);
+                validation.annotations.extend(part_validation.annotations);
+                validation.errors.extend(part_validation.errors);
+            }
+            validation.parts.push(mime_part.part);
+        }
+        validation.is_valid = validation.errors.is_empty();
+        validation
+    }
+
+    /// Validates a jinja template rendered with `instance_data` according to the type it renders
+    /// to, as cloud-init's jinja template handler. Errors and annotations are located in the
+    /// template.
+    fn validate_template(&self, template: &str, instance_data: &InstanceData) -> Validation {
+        let rendered = match jinja::render(template, instance_data) {
+            Ok(rendered) => rendered,
+            Err(e) => {
+                return Validation::from_errors(vec![ConfigError::new(
+                    Code::Template,
+                    e.description,
+                    String::new(),
+                    e.span,
+                )])
+            }
+        };
+        let content = &rendered.content;
+        let content_type = ContentType::from_starts_with(content).filter(|content_type| {
+            matches!(
+                content_type,
+                ContentType::CloudConfig | ContentType::CloudBoothook
+            ) || content_type.is_shell_script()
+        });
+        let mut validation = match content_type {
+            Some(content_type) => {
+                let user_data = UserData {
+                    content_type,
+                    encodings: vec![],
+                    content: content.clone(),
+                };
+                self.validate_user_data(&user_data)
+                    .unwrap_or_else(|e| Validation::from_errors(vec![parse_error(&e, content)]))
+            }
+            None => Validation::from_errors(vec![ConfigError::new(
+                Code::Template,
+                "Rendered template is neither a cloud-config, a boothook nor a shell script, \
+                 cloud-init ignores it"
+                    .to_string(),
+                String::new(),
+                Some(Span::first_line(content)),
+            )]),
+        };
+        for annotation in validation.annotations.iter_mut() {
+            annotation.span = annotation.span.and_then(|span| rendered.locate(span));
+        }
+        for error in validation.errors.iter_mut() {
+            error.span = error.span.and_then(|span| rendered.locate(span));
+        }
+        for undefined in rendered.undefined {
+            let name = undefined.variable.rsplit('.').next().unwrap_or_default();
+            validation.annotations.push(ConfigAnnotation::new(
+                Code::TemplateVariable,
+                format!(
+                    "Could not render jinja template variable '{}', cloud-init renders it as \
+                     \"{}{}\"",
+                    undefined.variable,
+                    jinja::MISSING_VARIABLE_PREFIX,
+                    name
+                ),
+                String::new(),
+                undefined.span,
+            ));
+        }
+        validation
+    }
+
+    /// Merges the cloud-configs of `user_data` with cloud-init's merge semantics, then with the
+    /// ones of `vendor_data`, and validates the result.
+    ///
+    /// Parts cloud-init fails to merge are skipped, with an error each.
+    pub fn validate_merged(&self, user_data: &UserData, vendor_data: Option<&UserData>) -> Merged {
+        let (mut config, merged_parts, mut errors) = merge_parts(user_data, self.yaml_version);
+        if let Some(vendor_data) = vendor_data {
+            let (vendor_config, _, vendor_errors) = merge_parts(vendor_data, self.yaml_version);
+            // Vendor-data parts are located in another document.
+            errors.extend(vendor_errors.into_iter().map(|mut error| {
+                error.description = format!("Vendor-data: {}", error.description);
+                error.span = None;
+                error.part = None;
+                error
+            }));
+            let mergers = merge::parse_mergers(merge::DEFAULT_MERGERS)
+                .expect("The default mergers must be valid");
+            config = merge::merge(&mergers, config, vendor_config);
+        }
+
+        let mut validation = self.validate(&config);
+        let attribute = |instance_path: &str| {
+            let merged_value = config.pointer(instance_path);
+            let mut candidates = merged_parts
+                .iter()
+                .rev()
+                .filter(|merged_part| merged_part.config.pointer(instance_path).is_some());
+            let merged_part = candidates
+                .clone()
+                .find(|merged_part| merged_part.config.pointer(instance_path) == merged_value)
+                .or_else(|| candidates.next())?;
+            let part = &merged_part.part;
+            let span = part.offset.and_then(|offset| {
+                SourceMap::from_yaml(&part.content)
+                    .span(instance_path)
+                    .map(|span| span.relocate(&user_data.content, offset))
+            });
+            Some((span, part.reference.clone()))
+        };
+        for annotation in validation.annotations.iter_mut() {
+            if let Some((span, part)) = attribute(&annotation.instance_path) {
+                annotation.span = span;
+                annotation.part = part;
+            }
+        }
+        for error in validation.errors.iter_mut() {
+            if let Some((span, part)) = attribute(&error.instance_path) {
+                error.span = span;
+                error.part = part;
+            }
+        }
+        for error in errors.into_iter().rev() {
+            validation.errors.push_front(error);
+        }
+        validation.is_valid = validation.errors.is_empty();
+        Merged { config, validation }
     }
 
-    pub fn validate_yaml(&self, payload: &str) -> Result<Validation> {
-        let format_error = if matches!(self.kind, ConfigKind::CloudConfig)
-            && !payload.starts_with(CLOUD_CONFIG_HEADER)
-        {
-            Some(ConfigError {
-                description: format!(
+    fn validate_document(&self, payload: &str, check_header: bool) -> Result<Validation> {
+        let format_error = if check_header && !payload.starts_with(CLOUD_CONFIG_HEADER) {
+            Some(ConfigError::new(
+                Code::Header,
+                format!(
                     "Cloud-config needs to begin with \"{}\"",
                     CLOUD_CONFIG_HEADER
                 ),
-                instance_path: String::new(), // XXX None
-            })
+                String::new(), // XXX None
+                Some(Span::first_line(payload)),
+            ))
         } else {
             None
         };
 
-        let payload: Value = match serde_yaml::from_str(payload) {
-            Ok(p) => p,
-            Err(e) => {
-                return Err(crate::error::Error::InvalidYaml(e));
-            }
+        let source_map = SourceMap::from_yaml(payload);
+        let document = yaml::load(payload, self.yaml_version)?;
+        let payload = document.value;
+        // The v2 schema requires the `network` key.
+        let wrap = matches!(self.schema.kind(), ConfigKind::NetworkConfigV2)
+            && payload.is_object()
+            && payload.get(NETWORK_KEY).is_none();
+        let mut validation = if wrap {
+            let mut validation = self.validate(&serde_json::json!({ NETWORK_KEY: payload }));
+            validation.strip_prefix(&format!("/{}", NETWORK_KEY));
+            validation
+        } else {
+            self.validate(&payload)
         };
-        let mut validation = self.validate(&payload);
+        validation.locate(&source_map);
+        // Network-configs are read by netplan too, whose typing of scalars differs from PyYAML's.
+        if matches!(self.schema.kind(), ConfigKind::CloudConfig) {
+            validation.annotations.splice(
+                0..0,
+                document.warnings.into_iter().map(ConfigAnnotation::from),
+            );
+        }
 
         if let Some(format_error) = format_error {
             validation.errors.push_front(format_error);
@@ -187,6 +1321,287 @@ impl Validator {
     }
 }
 
+/// Merges the cloud-config parts of `user_data` in order, as cloud-init's cloud-config handler.
+///
+/// Returns the merged cloud-config, the parts merged into it and an error per part cloud-init
+/// would fail to merge.
+fn merge_parts(
+    user_data: &UserData,
+    yaml_version: YamlVersion,
+) -> (Value, Vec<MergedPart>, Vec<ConfigError>) {
+    let mut config = Value::Object(Default::default());
+    let mut merged_parts = vec![];
+    let mut errors = vec![];
+    for part in userdata::config_parts(user_data) {
+        let merged = match part.content_type {
+            ContentType::CloudConfigJsonp => {
+                let patch = part.content.trim_start();
+                let patch = patch.strip_prefix("#cloud-config-jsonp").unwrap_or(patch);
+                serde_json::from_str(patch)
+                    .map_err(|e| e.to_string())
+                    .and_then(|patch| merge::apply_patch(&config, &patch))
+                    .map(|merged| (merged, None))
+            }
+            _ => merge_part(&config, &part, yaml_version),
+        };
+        match merged {
+            Ok((merged, part_config)) => {
+                config = merged;
+                if let Some(part_config) = part_config {
+                    merged_parts.push(MergedPart {
+                        part,
+                        config: part_config,
+                    });
+                }
+            }
+            Err(e) => {
+                let span = part.offset.map(|offset| {
+                    Span::first_line(&part.content).relocate(&user_data.content, offset)
+                });
+                let mut error = ConfigError::new(
+                    Code::Merge,
+                    format!("Part is not merged: {}", e),
+                    String::new(),
+                    span,
+                );
+                error.part = part.reference;
+                errors.push(error);
+            }
+        }
+    }
+    (config, merged_parts, errors)
+}
+
+/// Merges a cloud-config part into `config` with the mergers it declares, returning the merged
+/// cloud-config and the part's own, `None` if the part is empty.
+fn merge_part(
+    config: &Value,
+    part: &ConfigPart,
+    yaml_version: YamlVersion,
+) -> std::result::Result<(Value, Option<Value>), String> {
+    let mut part_config = yaml::load(&part.content, yaml_version)
+        .map_err(|e| e.to_string())?
+        .value;
+    if part_config.is_null() {
+        return Ok((config.clone(), None));
+    }
+    if !part_config.is_object() {
+        return Err("Cloud-config is not a mapping".to_string());
+    }
+    let mut mergers = merge::extract_mergers(&mut part_config)
+        .map_err(|e| format!("Invalid merge_how: {}", e))?;
+    if let Some(merge_type) = &part.merge_type {
+        mergers.extend(
+            merge::parse_mergers(merge_type)
+                .map_err(|e| format!("Invalid Merge-Type header: {}", e))?,
+        );
+    }
+    if mergers.is_empty() {
+        mergers = merge::parse_mergers(merge::DEFAULT_PART_MERGERS)
+            .expect("The default mergers must be valid");
+    }
+    let merged = merge::merge(&mergers, config.clone(), part_config.clone());
+    Ok((merged, Some(part_config)))
+}
+
+/// Collapses the errors of every `oneOf`/`anyOf` in their keyword location past byte `from`, in
+/// the order of their first error.
+fn collapse(
+    errors: Vec<ConfigError>,
+    from: usize,
+    schema: &Value,
+    instance: &Value,
+) -> Vec<ConfigError> {
+    /// The errors of the branches of a `oneOf`/`anyOf` at `location`.
+    struct Combinator {
+        location: String,
+        instance_path: String,
+        errors: Vec<(usize, ConfigError)>,
+    }
+    enum Item {
+        Error(Box<ConfigError>),
+        Combinator(usize),
+    }
+    let mut items = vec![];
+    let mut combinators: Vec<Combinator> = vec![];
+    for error in errors {
+        let Some(branch) = Branch::of(&error, from) else {
+            items.push(Item::Error(Box::new(error)));
+            continue;
+        };
+        let position = combinators
+            .iter()
+            .position(|combinator| {
+                combinator.location == branch.combinator
+                    && combinator.instance_path == branch.instance_path
+            })
+            .unwrap_or_else(|| {
+                items.push(Item::Combinator(combinators.len()));
+                combinators.push(Combinator {
+                    location: branch.combinator,
+                    instance_path: branch.instance_path,
+                    errors: vec![],
+                });
+                combinators.len() - 1
+            });
+        combinators[position].errors.push((branch.index, error));
+    }
+
+    let mut combinators: Vec<_> = combinators.into_iter().map(Some).collect();
+    let mut collapsed = vec![];
+    for item in items {
+        match item {
+            Item::Error(error) => collapsed.push(*error),
+            Item::Combinator(position) => {
+                let combinator = combinators[position]
+                    .take()
+                    .expect("Combinators are listed once");
+                collapsed.extend(collapse_combinator(
+                    &combinator.location,
+                    combinator.instance_path,
+                    combinator.errors,
+                    schema,
+                    instance,
+                ));
+            }
+        }
+    }
+    collapsed
+}
+
+/// Collapses the errors of the branches of a failed `oneOf`/`anyOf` at `combinator` into the ones
+/// of the branch which failed on the deepest instance, preferring the ones declaring a type which
+/// matches, then the ones declaring no type, and fewer errors. If no branch accepts the type of the
+/// instance, they become a single type error.
+fn collapse_combinator(
+    combinator: &str,
+    instance_path: String,
+    errors: Vec<(usize, ConfigError)>,
+    schema: &Value,
+    instance: &Value,
+) -> Vec<ConfigError> {
+    let detail: Vec<ErrorDetail> = errors.iter().map(|(_, error)| error.into()).collect();
+    let mut branches: BTreeMap<usize, Vec<ConfigError>> = BTreeMap::new();
+    for (index, error) in errors {
+        branches.entry(index).or_default().push(error);
+    }
+    let type_location = |index: usize| format!("{}/{}/type", combinator, index);
+    let mismatches_type = |index: usize, errors: &[ConfigError]| {
+        let type_location = type_location(index);
+        errors
+            .iter()
+            .any(|error| error.location.keyword_location == type_location)
+    };
+
+    if branches
+        .iter()
+        .all(|(index, errors)| mismatches_type(*index, errors))
+    {
+        let mut types: Vec<String> = vec![];
+        for index in branches.keys() {
+            match schema.pointer(&type_location(*index)) {
+                Some(Value::String(name)) => types.push(format!("{:?}", name)),
+                Some(Value::Array(names)) => types.extend(
+                    names
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(|name| format!("{:?}", name)),
+                ),
+                _ => {}
+            }
+        }
+        types.dedup();
+        let value = instance.pointer(&instance_path).unwrap_or(&Value::Null);
+        let mut error = ConfigError::new(
+            Code::Type,
+            format!("{} is not of type {}", value, alternatives(&types)),
+            instance_path,
+            None,
+        );
+        error.location = SchemaLocation::new(combinator.to_string());
+        error.detail = detail;
+        return vec![error];
+    }
+
+    let (index, errors) = branches
+        .into_iter()
+        .min_by_key(|(index, errors)| {
+            let depth = errors
+                .iter()
+                .map(|error| error.instance_path.matches('/').count())
+                .max();
+            let declares_type = schema.pointer(&type_location(*index)).is_some();
+            (
+                mismatches_type(*index, errors),
+                !declares_type,
+                Reverse(depth),
+                errors.len(),
+            )
+        })
+        .expect("A failed combinator has failed branches");
+    let branch_end = combinator.len() + 1 + index.to_string().len();
+    let mut errors = collapse(errors, branch_end, schema, instance);
+    if let Some(error) = errors.first_mut() {
+        error.detail = detail;
+    }
+    errors
+}
+
+/// Joins `choices` as `a, b or c`.
+fn alternatives(choices: &[String]) -> String {
+    match choices {
+        [] => String::new(),
+        [choice] => choice.clone(),
+        [rest @ .., last] => format!("{} or {}", rest.join(", "), last),
+    }
+}
+
+/// Checks a jinja template which cannot be rendered for lack of instance-data. Variables which
+/// are not in cloud-init's instance-data are reported as annotations.
+fn analyze_template(template: &str) -> Validation {
+    let analysis = jinja::analyze(template);
+    let mut validation = Validation::from_errors(
+        analysis
+            .errors
+            .into_iter()
+            .map(|error| {
+                ConfigError::new(Code::Template, error.description, String::new(), error.span)
+            })
+            .collect(),
+    );
+    validation.annotations = analysis
+        .warnings
+        .into_iter()
+        .map(|warning| {
+            ConfigAnnotation::new(
+                Code::TemplateVariable,
+                warning.description,
+                String::new(),
+                warning.span,
+            )
+        })
+        .collect();
+    validation
+}
+
+/// An error for a payload which cannot be parsed, located at the line of the syntax error if it is
+/// known.
+fn parse_error(e: &Error, content: &str) -> ConfigError {
+    let span = match e {
+        Error::InvalidYaml(yaml_error) => yaml_error
+            .location()
+            .map(|location| Span::line(content, location.line())),
+        Error::YamlLoad { line, .. } => Some(Span::line(content, *line)),
+        _ => None,
+    };
+    ConfigError::new(
+        Code::Syntax,
+        e.to_string(),
+        String::new(),
+        Some(span.unwrap_or_else(|| Span::first_line(content))),
+    )
+}
+
 fn jsonschema_try_from(schema: &Value) -> Result<JSONSchema> {
     let compiled = JSONSchema::options()
         .with_draft(jsonschema::Draft::Draft4)
@@ -205,6 +1620,7 @@ mod test_validate {
     use serde_json::json;
 
     use crate::error::Error;
+    use crate::span::Position;
 
     use super::*;
     #[test]
@@ -240,10 +1656,26 @@ mod test_validate {
         let expected_validation = Validation {
             is_valid: true,
             annotations: vec![ConfigAnnotation {
+                code: Code::Deprecated,
+                severity: Severity::Warning,
                 description: "Deprecated. my description".to_string(),
                 instance_path: "/x/y".to_string(),
+                span: None,
+                location: SchemaLocation {
+                    keyword: Some("deprecated".to_string()),
+                    keyword_location: "/properties/x/properties/y/deprecated".to_string(),
+                    absolute_keyword_location: None,
+                    value: None,
+                },
+                lifecycle: Lifecycle::default(),
+                part: None,
             }],
             errors: VecDeque::new(),
+            content_type: None,
+            encodings: vec![],
+            parts: vec![],
+            merged: None,
+            compatibility: None,
         };
         dbg!(&validation);
         assert_eq!(expected_validation, validation);
@@ -283,14 +1715,217 @@ mod test_validate {
             is_valid: false,
             annotations: vec![],
             errors: VecDeque::from(vec![ConfigError {
+                code: Code::Type,
+                severity: Severity::Error,
                 description: "1.5 is not of type \"integer\"".to_string(),
                 instance_path: "/x/y".to_string(),
+                span: None,
+                location: SchemaLocation {
+                    keyword: Some("type".to_string()),
+                    keyword_location: "/properties/x/properties/y/type".to_string(),
+                    absolute_keyword_location: None,
+                    value: None,
+                },
+                schema_description: None,
+                suggestions: vec![],
+                detail: vec![],
+                part: None,
             }]),
+            content_type: None,
+            encodings: vec![],
+            parts: vec![],
+            merged: None,
+            compatibility: None,
         };
         dbg!(&validation);
         assert_eq!(expected_validation, validation);
     }
 
+    #[test]
+    fn deprecations_of_invalid_configs() {
+        let validator = Validator::from_vendored_schema().unwrap();
+        let validation = validator
+            .validate_yaml("#cloud-config\napt_update: 1\nusers:\n  - name: a\n    uid: '1'\n")
+            .unwrap();
+
+        assert_eq!(
+            vec!["/apt_update"],
+            validation
+                .errors()
+                .map(ConfigError::instance_path)
+                .collect::<Vec<_>>()
+        );
+        let annotations: Vec<_> = validation
+            .annotations()
+            .map(|annotation| (annotation.code(), annotation.instance_path()))
+            .collect();
+        assert_eq!(
+            vec![
+                (Code::Deprecated, "/apt_update"),
+                (Code::Changed, "/users/0/uid"),
+            ],
+            annotations
+        );
+        let deprecation = validation.annotations().next().unwrap();
+        assert_eq!(
+            "Deprecated in version 22.2. Use ``package_update`` instead.",
+            deprecation.description()
+        );
+        assert_eq!(
+            &Lifecycle {
+                deprecated_version: Some("22.2".to_string()),
+                removal_version: Some("27.2".to_string()),
+                replacement: Some("package_update".to_string()),
+                ..Default::default()
+            },
+            deprecation.lifecycle()
+        );
+        assert_eq!(Some(2), deprecation.span().map(|span| span.start.line));
+    }
+
+    /// The annotations of valid instances are the same whether `jsonschema` or the lifecycle
+    /// walker collects them.
+    #[test]
+    fn walked_annotations() {
+        let validator = Validator::from_vendored_schema().unwrap();
+        let instance = json!({
+            "apt_update": true,
+            "ca-certs": {"remove-defaults": true},
+            "power_state": {"mode": "reboot", "delay": "+5"},
+            "users": [{"name": "a", "lock-passwd": true, "sudo": false}],
+        });
+        let annotations = |annotations: &[ConfigAnnotation]| {
+            let mut annotations: Vec<_> = annotations
+                .iter()
+                .map(|annotation| {
+                    (
+                        annotation.code.as_str(),
+                        annotation.instance_path.clone(),
+                        annotation.location.keyword_location.clone(),
+                    )
+                })
+                .collect();
+            annotations.sort();
+            annotations
+        };
+
+        let validation = validator.validate(&instance);
+        assert!(validation.is_valid);
+        assert_eq!(6, validation.annotations.len());
+        assert_eq!(
+            annotations(&validation.annotations),
+            annotations(&lifecycle::annotate(validator.schema.schema(), &instance))
+        );
+    }
+
+    #[test]
+    fn deprecation_policies() {
+        let validator = Validator::from_vendored_schema().unwrap();
+        let payload = "#cloud-config\napt_update: true\nssh_pwauth: 'no'\n";
+        let validation = || validator.validate_yaml(payload).unwrap();
+
+        let warned = validation().with_deprecation_policy(DeprecationPolicy::Warn);
+        assert!(warned.is_valid);
+        assert_eq!(2, warned.annotations.len());
+
+        let ignored = validation().with_deprecation_policy(DeprecationPolicy::Ignore);
+        assert!(ignored.is_valid);
+        assert!(ignored.annotations.is_empty());
+
+        let errors = validation().with_deprecation_policy(DeprecationPolicy::Error);
+        assert!(!errors.is_valid);
+        assert!(errors.annotations.is_empty());
+        let codes: Vec<_> = errors
+            .errors()
+            .map(|error| (error.code(), error.severity()))
+            .collect();
+        assert_eq!(
+            vec![
+                (Code::Deprecated, Severity::Error),
+                (Code::Changed, Severity::Error)
+            ],
+            codes
+        );
+    }
+
+    #[cfg(feature = "release-23-1")]
+    #[test]
+    fn releases() {
+        let validator = Validator::for_release("23.1", ConfigKind::CloudConfig).unwrap();
+        let validation = validator
+            .validate_yaml("#cloud-config\nssh_pwauth: 1\n")
+            .unwrap();
+        assert!(!validation.is_valid);
+        assert!(validation
+            .errors()
+            .all(|error| error.instance_path() == "/ssh_pwauth"));
+        assert!(Validator::for_release("23.1", ConfigKind::NetworkConfigV2).is_err());
+        assert!(Validator::for_release("9.1", ConfigKind::CloudConfig).is_err());
+    }
+
+    #[test]
+    fn bundled_releases() {
+        for release in crate::schema::Release::all() {
+            let version = release.version().to_string();
+            for kind in [
+                ConfigKind::CloudConfig,
+                ConfigKind::NetworkConfig,
+                ConfigKind::NetworkConfigV2,
+            ] {
+                let validator = Validator::for_release(&version, kind.clone());
+                if !release.ships(&kind) {
+                    assert!(
+                        matches!(validator, Err(Error::UnshippedSchema { .. })),
+                        "{} {}",
+                        version,
+                        kind
+                    );
+                    continue;
+                }
+                let payload = match kind {
+                    ConfigKind::CloudConfig => "#cloud-config\nhostname: host\n",
+                    ConfigKind::NetworkConfig => "version: 1\nconfig: []\n",
+                    ConfigKind::NetworkConfigV2 => "version: 2\nethernets: {}\n",
+                };
+                let validation = validator
+                    .unwrap_or_else(|e| panic!("{} {}: {}", version, kind, e))
+                    .validate_yaml(payload)
+                    .unwrap();
+                assert!(validation.is_valid, "{} {}", version, kind);
+            }
+        }
+    }
+
+    #[test]
+    fn semantic_checks() {
+        let validator = Validator::from_vendored_schema().unwrap();
+        let validation = validator
+            .validate_yaml(
+                "#cloud-config\nwrite_files:\n  - path: /a\n    content: aGk\n    encoding: b64\n  \
+                 - path: a\n    permissions: rw\n",
+            )
+            .unwrap();
+        assert!(!validation.is_valid);
+        let diagnostics: Vec<_> = validation
+            .diagnostics()
+            .map(|diagnostic| {
+                (
+                    diagnostic.code(),
+                    diagnostic.instance_path().to_string(),
+                    diagnostic.span().unwrap().start.line,
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (Code::Malformed, "/write_files/0/content".to_string(), 4),
+                (Code::Malformed, "/write_files/1/permissions".to_string(), 7),
+                (Code::RelativePath, "/write_files/1/path".to_string(), 6),
+            ],
+            diagnostics
+        );
+    }
+
     #[test]
     fn invalid_yaml() {
         let validator = Validator::from_vendored_schema().unwrap();
@@ -304,4 +1939,666 @@ mod test_validate {
             error_msg
         );
     }
+
+    #[tokio::test]
+    async fn error_spans() {
+        let validator = Validator::from_vendored_schema().unwrap();
+        let validation = validator
+            .validate_yaml("ubuntu_advantage:\n  features:\n    disable_auto_attach: 1\n")
+            .unwrap();
+
+        let spans: Vec<_> = validation
+            .errors
+            .iter()
+            .map(|error| error.span.expect("located error"))
+            .collect();
+        assert_eq!(
+            vec![
+                Span::first_line("ubuntu_advantage:"),
+                Span {
+                    start: Position {
+                        line: 3,
+                        column: 26,
+                        offset: 55,
+                    },
+                    end: Position {
+                        line: 3,
+                        column: 27,
+                        offset: 56,
+                    },
+                },
+            ],
+            spans
+        );
+        // Bundled schemas are resolved already.
+        assert!(validation
+            .errors()
+            .all(|error| error.absolute_keyword_location().is_none()));
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root.json");
+        std::fs::write(
+            &root,
+            json!({"properties": {"features": {"$ref": "common.json#/$defs/features"}}})
+                .to_string(),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("common.json"),
+            json!({"$defs": {"features": {"properties": {"auto_attach": {"type": "boolean"}}}}})
+                .to_string(),
+        )
+        .unwrap();
+        let schema = Schema::from_source(
+            &crate::schema::FileSource::new(&root),
+            ConfigKind::CloudConfig,
+        )
+        .await
+        .unwrap();
+        let validation = Validator::from_schema(schema)
+            .unwrap()
+            .validate_yaml("#cloud-config\nfeatures:\n  auto_attach: 1\n")
+            .unwrap();
+        let error = validation.errors().next().unwrap();
+        assert_eq!(
+            "/properties/features/properties/auto_attach/type",
+            error.keyword_location()
+        );
+        let common = reqwest::Url::from_file_path(dir.path().join("common.json")).unwrap();
+        assert_eq!(
+            Some(format!("{}#/$defs/features/properties/auto_attach/type", common).as_str()),
+            error.absolute_keyword_location()
+        );
+    }
+
+    #[test]
+    fn schema_descriptions() {
+        let validator = Validator::from_vendored_schema().unwrap();
+        let validation = validator
+            .validate_yaml(
+                "#cloud-config\nubuntu_advantage:\n  features:\n    disable_auto_attach: 1\n",
+            )
+            .unwrap();
+
+        let descriptions: Vec<_> = validation
+            .errors
+            .iter()
+            .map(|error| error.schema_description.as_deref())
+            .collect();
+        assert_eq!(
+            vec![Some(
+                "Optional boolean for controlling if ua-auto-attach.service (in Ubuntu Pro \
+                 instances) will be attempted each boot. Default: ``false``"
+            )],
+            descriptions
+        );
+    }
+
+    #[test]
+    fn yaml_versions() {
+        let payload =
+            "#cloud-config\npackage_update: yes\nwrite_files:\n  - path: /a\n    permissions: 0644\n";
+        let lines = |validation: &Validation| {
+            let errors: Vec<_> = validation
+                .errors
+                .iter()
+                .map(|error| (error.instance_path.clone(), error.span.unwrap().start.line))
+                .collect();
+            let annotations: Vec<_> = validation
+                .annotations
+                .iter()
+                .map(|annotation| annotation.span.unwrap().start.line)
+                .collect();
+            (errors, annotations)
+        };
+
+        let validator = Validator::from_vendored_schema().unwrap();
+        let validation = validator.validate_yaml(payload).unwrap();
+        assert_eq!(
+            (
+                vec![("/write_files/0/permissions".to_string(), 5)],
+                vec![2, 5]
+            ),
+            lines(&validation)
+        );
+        assert!(validation.annotations[1]
+            .description
+            .starts_with("\"0644\" is the integer 420 in YAML 1.1"));
+
+        let validation = validator
+            .with_yaml_version(YamlVersion::V1_2)
+            .validate_yaml(payload)
+            .unwrap();
+        assert_eq!(
+            (vec![("/package_update".to_string(), 2)], vec![2, 5]),
+            lines(&validation)
+        );
+    }
+
+    #[test]
+    fn suggestions() {
+        let validator = Validator::from_vendored_schema().unwrap();
+        let validation = validator
+            .validate_yaml(
+                "#cloud-config\n\
+apt:\n  Sources: {}\n  bogus: 1\n\
+power_state:\n  mode: reboto\n\
+write_files:\n  - path: /a\n    encoding: b65\n",
+            )
+            .unwrap();
+        let suggestions: Vec<_> = validation
+            .errors
+            .iter()
+            .map(|error| (error.instance_path.as_str(), error.suggestions.clone()))
+            .collect();
+        let suggestion = |found: &str, candidates: &[&str]| Suggestion {
+            found: found.to_string(),
+            candidates: candidates.iter().map(|c| c.to_string()).collect(),
+        };
+        assert_eq!(
+            vec![
+                ("/apt", vec![suggestion("Sources", &["sources"])]),
+                ("/power_state/mode", vec![suggestion("reboto", &["reboot"])]),
+                ("/write_files/0/encoding", vec![suggestion("b65", &["b64"])]),
+            ],
+            suggestions
+        );
+    }
+
+    #[test]
+    fn codes() {
+        let validator = Validator::from_vendored_schema().unwrap();
+        let validation = validator
+            .validate_yaml(
+                "ntp:\n  enabled: 1\n  bogus: 2\npower_state:\n  mode: reboto\nssh_pwauth: no\n\
+ssh_pwauth: yes\n",
+            )
+            .unwrap();
+        let errors: Vec<_> = validation
+            .errors()
+            .map(|error| {
+                (
+                    error.code(),
+                    error.severity(),
+                    error.keyword(),
+                    error.instance_path(),
+                    error.value().cloned(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (Code::Header, Severity::Error, None, "", None),
+                (
+                    Code::Type,
+                    Severity::Error,
+                    Some("type"),
+                    "/ntp/enabled",
+                    Some(json!(1))
+                ),
+                (
+                    Code::AdditionalProperty,
+                    Severity::Error,
+                    Some("additionalProperties"),
+                    "/ntp",
+                    Some(json!({"bogus": 2, "enabled": 1}))
+                ),
+                (
+                    Code::Enum,
+                    Severity::Error,
+                    Some("enum"),
+                    "/power_state/mode",
+                    Some(json!("reboto"))
+                ),
+            ],
+            errors
+        );
+        let error = validation.errors().nth(1).unwrap();
+        assert_eq!(
+            "/allOf/25/properties/ntp/properties/enabled/type",
+            error.keyword_location()
+        );
+
+        let annotations: Vec<_> = validation
+            .annotations()
+            .map(|annotation| {
+                (
+                    annotation.code(),
+                    annotation.severity(),
+                    annotation.keyword(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (Code::YamlType, Severity::Warning, None),
+                (Code::DuplicateKey, Severity::Warning, None),
+                (Code::YamlType, Severity::Warning, None),
+            ],
+            annotations
+        );
+
+        let validation = validator
+            .validate_yaml("#cloud-config\nusers:\n  - name: a\n    uid: \"1743\"\n")
+            .unwrap();
+        let annotation = validation.annotations().next().unwrap();
+        assert_eq!(Code::Changed, annotation.code());
+        assert_eq!(Some("changed"), annotation.keyword());
+        assert_eq!(Some(&json!("1743")), annotation.value());
+    }
+
+    #[test]
+    fn round_trip() {
+        let validator = Validator::from_vendored_schema().unwrap();
+        let payload = "Content-Type: multipart/mixed; boundary=\"b\"\nMIME-Version: 1.0\n\n\
+--b\nContent-Type: text/cloud-config\n\n#cloud-config\nusers:\n  - name: a\n    uid: \"1743\"\n\
+    groups: 1\npower_state:\n  mode: reboto\nntp:\n  enabled: ~\nssh_pwauth: no\n--b--\n";
+        let user_data = UserData::detect(payload.as_bytes());
+        let validation = validator
+            .validate_user_data(&user_data)
+            .unwrap()
+            .with_merged(validator.validate_merged(&user_data, None));
+        let error = validation
+            .errors()
+            .find(|error| error.instance_path() == "/ntp/enabled")
+            .unwrap();
+        assert_eq!(Some(&Value::Null), error.value());
+
+        let json = serde_json::to_string(&validation).unwrap();
+        assert_eq!(
+            validation,
+            serde_json::from_str::<Validation>(&json).unwrap()
+        );
+    }
+
+    #[test]
+    fn builder() {
+        let validation = Validation::builder()
+            .content_type(ContentType::CloudConfig)
+            .error(
+                ConfigError::new(
+                    Code::Type,
+                    "1 is not of type \"boolean\"".to_string(),
+                    "/ntp/enabled".to_string(),
+                    Some(Span::line("#cloud-config\nntp:\n  enabled: 1\n", 3)),
+                )
+                .with_keyword_location("/properties/ntp/properties/enabled/type")
+                .with_value(json!(1)),
+            )
+            .annotation(ConfigAnnotation::new(
+                Code::DuplicateKey,
+                "Duplicate key \"ntp\", cloud-init uses its last value".to_string(),
+                "/ntp".to_string(),
+                None,
+            ))
+            .build();
+        assert!(!validation.is_valid);
+        assert_eq!(Some(ContentType::CloudConfig), validation.content_type());
+        let error = validation.errors().next().unwrap();
+        assert_eq!(Some("type"), error.keyword());
+        assert_eq!(Some(&json!(1)), error.value());
+        assert_eq!(
+            vec![Code::DuplicateKey],
+            validation
+                .with_severity(Severity::Warning)
+                .map(|diagnostic| diagnostic.code())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "error[CCV-E-TYPE]: 1 is not of type \"boolean\" at /ntp/enabled (line 3 column 1)\n\
+warning[CCV-W-DUPLICATE-KEY]: Duplicate key \"ntp\", cloud-init uses its last value at /ntp\n",
+            validation.to_string()
+        );
+        assert!(Validation::builder().build().is_valid);
+    }
+
+    #[test]
+    fn collapsed_combinators() {
+        let validator = Validator::from_vendored_schema().unwrap();
+        let validation = validator
+            .validate_yaml(
+                "#cloud-config\nusers:\n  - name: a\n    groups: 1\n  - name: b\n    lock_passwd: 1\n\
+runcmd: [1]\nswap:\n  size: 2XB\n",
+            )
+            .unwrap();
+        let errors: Vec<_> = validation
+            .errors
+            .iter()
+            .map(|error| {
+                (
+                    error.instance_path.as_str(),
+                    error.description.as_str(),
+                    error.detail.len(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                // The branch declaring the matching type, not the `enum` one declaring none.
+                (
+                    "/swap/size",
+                    "\"2XB\" does not match \"^([0-9]+)?\\.?[0-9]+[BKMGT]$\"",
+                    3
+                ),
+                (
+                    "/runcmd/0",
+                    "1 is not of type \"array\", \"string\" or \"null\"",
+                    3
+                ),
+                (
+                    "/users/0/groups",
+                    "1 is not of type \"string\", \"array\" or \"object\"",
+                    5
+                ),
+                ("/users/1/lock_passwd", "1 is not of type \"boolean\"", 3),
+            ],
+            errors
+        );
+        assert_eq!(
+            ErrorDetail {
+                description: "{\"groups\":1,\"name\":\"a\"} is not of type \"string\"".to_string(),
+                instance_path: "/users/0".to_string(),
+                keyword_location: "/allOf/50/properties/users/items/oneOf/0/type".to_string(),
+            },
+            validation.errors[2].detail[0]
+        );
+        assert_eq!(
+            Some(4),
+            validation.errors[2].span.map(|span| span.start.line)
+        );
+        assert!(validation.errors[2].schema_description.is_some());
+    }
+
+    #[test]
+    fn user_data() {
+        let validator = Validator::from_vendored_schema().unwrap();
+        let validate = |payload: &str| {
+            validator
+                .validate_user_data(&UserData::detect(payload.as_bytes()))
+                .unwrap()
+        };
+
+        let validation = validate("#cloud-config\nubuntu_advantage:\n  token: 1\n");
+        assert_eq!(Some(ContentType::CloudConfig), validation.content_type);
+        assert_eq!(1, validation.errors.len());
+
+        let validation = validate("#!/bin/sh\r\necho hi\r\n");
+        assert_eq!(Some(ContentType::ShellScript), validation.content_type);
+        assert!(!validation.is_valid);
+
+        let validation = validate("\u{feff}#cloud-config\npackages: [a]\n");
+        assert_eq!(Some(ContentType::Unknown), validation.content_type);
+        let descriptions: Vec<_> = validation
+            .errors
+            .iter()
+            .map(|error| error.description.as_str())
+            .collect();
+        assert_eq!(
+            vec![
+                "Cloud-config needs to begin with \"#cloud-config\"",
+                "User-data starts with a byte order mark (BOM), which prevents cloud-init from \
+                 detecting its type",
+            ],
+            descriptions
+        );
+    }
+
+    #[test]
+    fn multipart_user_data() {
+        let validator = Validator::from_vendored_schema().unwrap();
+        let message = "MIME-Version: 1.0\r\n\
+Content-Type: multipart/mixed; boundary=b\r\n\
+\r\n\
+--b\r\n\
+Content-Type: text/cloud-config\r\n\
+Content-Disposition: attachment; filename=\"config.yaml\"\r\n\
+\r\n\
+ubuntu_advantage:\r\n\
+  token: 1\r\n\
+--b\r\n\
+Content-Type: text/x-shellscript\r\n\
+\r\n\
+#!/bin/sh\r\n\
+echo hi\r\n\
+--b\r\n\
+Content-Type: text/cloud-config\r\n\
+\r\n\
+a: [\r\n\
+--b--\r\n";
+        let validation = validator
+            .validate_user_data(&UserData::detect(message.as_bytes()))
+            .unwrap();
+        assert_eq!(Some(ContentType::Multipart), validation.content_type);
+        assert_eq!(3, validation.parts.len());
+
+        let errors: Vec<_> = validation
+            .errors
+            .iter()
+            .map(|error| {
+                let part = error.part.as_ref().expect("error in a part");
+                (
+                    part.index,
+                    part.filename.as_str(),
+                    error.instance_path.as_str(),
+                    error.span.map(|span| span.start.line),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (0, "config.yaml", "/ubuntu_advantage", Some(8)),
+                (1, "part-001", "", Some(13)),
+                (2, "part-002", "", Some(18)),
+            ],
+            errors
+        );
+    }
+
+    #[test]
+    fn templated_user_data() {
+        let validator = Validator::from_vendored_schema().unwrap();
+        let instance_data =
+            InstanceData::from_json(r#"{"v1": {"local_hostname": "host", "region": 1}}"#).unwrap();
+        let template = "## template: jinja\n\
+#cloud-config\n\
+hostname: {{ v1.local_hostname }}\n\
+package_update: {{ v1.region }}\n\
+fqdn: {{ v1.fqdn }}\n";
+        let user_data = UserData::detect(template.as_bytes());
+        assert_eq!(ContentType::Jinja, user_data.content_type);
+
+        let validation = validator
+            .validate_templated_user_data(&user_data, &instance_data)
+            .unwrap();
+        assert!(!validation.is_valid);
+        assert_eq!(Some(ContentType::Jinja), validation.content_type);
+        let errors: Vec<_> = validation
+            .errors
+            .iter()
+            .map(|error| (error.instance_path.as_str(), error.span))
+            .collect();
+        assert_eq!(
+            vec![("/package_update", Some(Span::line(template, 4)))],
+            errors
+        );
+        assert_eq!(1, validation.annotations.len());
+        assert_eq!(
+            "Could not render jinja template variable 'v1.fqdn', cloud-init renders it as \
+             \"CI_MISSING_JINJA_VAR/fqdn\"",
+            validation.annotations[0].description
+        );
+        assert_eq!(
+            Some(5),
+            validation.annotations[0].span.map(|span| span.start.line)
+        );
+
+        let validation = validator
+            .validate_templated_user_data(
+                &UserData::detect(b"## template: jinja\n{{ v1.region }}\n"),
+                &instance_data,
+            )
+            .unwrap();
+        assert_eq!(
+            vec![(
+                "Rendered template is neither a cloud-config, a boothook nor a shell script, \
+                 cloud-init ignores it",
+                Some(2)
+            )],
+            validation
+                .errors
+                .iter()
+                .map(|error| (
+                    error.description.as_str(),
+                    error.span.map(|span| span.start.line)
+                ))
+                .collect::<Vec<_>>()
+        );
+
+        // Without instance-data, templates are only checked for what does not depend on it.
+        let validation = validator.validate_user_data(&user_data).unwrap();
+        assert!(validation.is_valid);
+        assert_eq!(
+            vec![
+                "'v1.fqdn' is not an instance-data variable, cloud-init renders it as \
+                  \"CI_MISSING_JINJA_VAR/fqdn\""
+            ],
+            validation
+                .annotations
+                .iter()
+                .map(|annotation| annotation.description.as_str())
+                .collect::<Vec<_>>()
+        );
+        let validation = validator
+            .validate_yaml("## template: jinja\n#cloud-config\nhostname: {{ v1.local_hostname\n")
+            .unwrap();
+        assert!(!validation.is_valid);
+        assert!(validation.errors[0]
+            .description
+            .starts_with("Template cannot be rendered: syntax error"));
+    }
+
+    #[test]
+    fn merged_user_data() {
+        let validator = Validator::from_vendored_schema().unwrap();
+        let message = "Content-Type: multipart/mixed; boundary=b\n\
+MIME-Version: 1.0\n\
+\n\
+--b\n\
+Content-Type: text/cloud-config\n\
+\n\
+runcmd: [ls]\n\
+--b\n\
+Content-Type: text/cloud-config\n\
+Merge-Type: list(append)+dict(no_replace,recurse_list)+str()\n\
+\n\
+runcmd: [pwd]\n\
+package_update: 1\n\
+--b\n\
+Content-Type: text/cloud-config-jsonp\n\
+\n\
+[{\"op\": \"add\", \"path\": \"/runcmd/-\", \"value\": \"id\"}]\n\
+--b\n\
+Content-Type: text/cloud-config\n\
+\n\
+merge_how: bogus\n\
+--b--\n";
+        let vendor_data = UserData::detect(b"#cloud-config\nruncmd: [x]\npackage_upgrade: true\n");
+        let merged =
+            validator.validate_merged(&UserData::detect(message.as_bytes()), Some(&vendor_data));
+        assert_eq!(
+            json!({
+                "runcmd": ["ls", "pwd", "id"],
+                "package_update": 1,
+                "package_upgrade": true,
+            }),
+            merged.config
+        );
+
+        let errors: Vec<_> = merged
+            .validation
+            .errors
+            .iter()
+            .map(|error| {
+                (
+                    error.part.as_ref().map(|part| part.index),
+                    error.instance_path.as_str(),
+                    error.span.map(|span| span.start.line),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (Some(3), "", Some(21)),
+                (Some(1), "/package_update", Some(13))
+            ],
+            errors
+        );
+        assert!(merged.validation.errors[0]
+            .description
+            .starts_with("Part is not merged: Invalid merge_how: "));
+
+        let validation = Validation::from_errors(vec![]).with_merged(merged);
+        assert!(!validation.is_valid);
+    }
+}
+
+#[cfg(test)]
+mod test_network_config {
+    use super::*;
+    use crate::schema::VendoredSource;
+
+    const V2: &str = "version: 2\nethernets:\n  eth0:\n    dhcp4: true\n    mtu: \"a\"\n";
+
+    async fn validator(kind: ConfigKind) -> Validator {
+        Validator::from_source(&VendoredSource, kind)
+            .await
+            .expect("valid schema")
+    }
+
+    #[tokio::test]
+    async fn v2_with_and_without_wrapper() {
+        let validator = validator(ConfigKind::NetworkConfigV2).await;
+        let valid = "version: 2\nethernets:\n  eth0:\n    dhcp4: true\n";
+        assert!(validator.validate_yaml(valid).unwrap().is_valid);
+        let wrapped = "network:\n  version: 2\n  ethernets:\n    eth0:\n      dhcp4: true\n";
+        assert!(validator.validate_yaml(wrapped).unwrap().is_valid);
+
+        let validation = validator.validate_yaml(V2).unwrap();
+        assert!(!validation.is_valid);
+        assert_eq!(1, validation.errors.len());
+        let error = &validation.errors[0];
+        assert_eq!("/ethernets/eth0/mtu", error.instance_path);
+        assert_eq!(5, error.span.unwrap().start.line);
+    }
+
+    #[tokio::test]
+    async fn v2_as_v1() {
+        let request: NetworkConfig =
+            serde_json::from_value(serde_json::json!({"payload": V2, "version": 1})).unwrap();
+        assert_eq!(NetworkVersion::V1, request.version());
+
+        let validation = validator(request.version().kind())
+            .await
+            .validate_yaml(request.payload())
+            .unwrap();
+        assert!(!validation.is_valid);
+        assert!(validation
+            .errors
+            .iter()
+            .all(|error| error.instance_path.is_empty() || error.instance_path == "/version"));
+    }
+
+    #[test]
+    fn detected_version() {
+        let request: NetworkConfig =
+            serde_json::from_value(serde_json::json!({"payload": V2})).unwrap();
+        assert_eq!(NetworkVersion::V2, request.version());
+        let request: NetworkConfig =
+            serde_json::from_value(serde_json::json!({"payload": "a: 1"})).unwrap();
+        assert_eq!(NetworkVersion::V1, request.version());
+        assert!(serde_json::from_value::<NetworkConfig>(
+            serde_json::json!({"payload": V2, "version": 3})
+        )
+        .is_err());
+    }
 }
@@ -0,0 +1,656 @@
+//! Loading of YAML as cloud-init does, with PyYAML's `safe_load`, which implements YAML 1.1.
+//!
+//! YAML 1.1 types more plain scalars than YAML 1.2, which `serde_yaml` implements: `yes`/`no` and
+//! `on`/`off` are booleans, `0644` is an octal integer and `1:30` a sexagesimal one.
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+use regex::Regex;
+use saphyr_parser::{Event, Parser, ScalarStyle, Span as EventSpan, Tag};
+use serde_json::{Map, Number, Value};
+
+use crate::code::Code;
+use crate::error::{Error, Result};
+use crate::span::{self, Markers, Span};
+
+// Implicit resolvers of PyYAML's `resolver.py`.
+static BOOL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:yes|Yes|YES|no|No|NO|true|True|TRUE|false|False|FALSE|on|On|ON|off|Off|OFF)$")
+        .expect("valid regex")
+});
+static FLOAT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?:[-+]?(?:[0-9][0-9_]*)\.[0-9_]*(?:[eE][-+][0-9]+)?|\.[0-9_]+(?:[eE][-+][0-9]+)?|[-+]?[0-9][0-9_]*(?::[0-5]?[0-9])+\.[0-9_]*|[-+]?\.(?:inf|Inf|INF)|\.(?:nan|NaN|NAN))$",
+    )
+    .expect("valid regex")
+});
+static INT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?:[-+]?0b[0-1_]+|[-+]?0[0-7_]+|[-+]?(?:0|[1-9][0-9_]*)|[-+]?0x[0-9a-fA-F_]+|[-+]?[1-9][0-9_]*(?::[0-5]?[0-9])+)$",
+    )
+    .expect("valid regex")
+});
+static NULL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(?:~|null|Null|NULL|)$").expect("valid regex"));
+static TIMESTAMP: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?:[0-9][0-9][0-9][0-9]-[0-9][0-9]-[0-9][0-9]|[0-9][0-9][0-9][0-9]-[0-9][0-9]?-[0-9][0-9]?(?:[Tt]|[ \t]+)[0-9][0-9]?:[0-9][0-9]:[0-9][0-9](?:\.[0-9]*)?(?:[ \t]*(?:Z|[-+][0-9][0-9]?(?::[0-9][0-9])?))?)$",
+    )
+    .expect("valid regex")
+});
+
+/// The YAML version payloads are loaded with.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum YamlVersion {
+    /// YAML 1.1, as PyYAML, which cloud-init loads its configurations with.
+    V1_1,
+    /// YAML 1.2, as `serde_yaml`.
+    V1_2,
+}
+
+impl fmt::Display for YamlVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::V1_1 => write!(f, "1.1"),
+            Self::V1_2 => write!(f, "1.2"),
+        }
+    }
+}
+
+impl FromStr for YamlVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "1.1" => Ok(Self::V1_1),
+            "1.2" => Ok(Self::V1_2),
+            _ => Err(format!("Not a supported YAML version: {}", s)),
+        }
+    }
+}
+
+/// A loaded YAML document.
+#[derive(Debug)]
+pub(crate) struct Document {
+    pub(crate) value: Value,
+    pub(crate) warnings: Vec<Warning>,
+}
+
+/// A node whose meaning depends on the YAML implementation loading it: a plain scalar typed
+/// differently by YAML 1.1 and 1.2, or the value of a duplicate key.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Warning {
+    /// [`Code::YamlType`] or [`Code::DuplicateKey`].
+    pub(crate) code: Code,
+    pub(crate) description: String,
+    pub(crate) instance_path: String,
+    pub(crate) span: Span,
+}
+
+/// Loads the single document of `payload` as `version`.
+///
+/// Warnings are reported whatever the version.
+pub(crate) fn load(payload: &str, version: YamlVersion) -> Result<Document> {
+    // Syntax errors are reported by libyaml, whose messages are PyYAML's.
+    serde_yaml::from_str::<serde::de::IgnoredAny>(payload)?;
+    // Unlike libyaml, saphyr does not skip byte order marks. A space keeps the char indices.
+    let unmarked = payload
+        .strip_prefix('\u{feff}')
+        .map(|rest| format!(" {}", rest));
+    let source = unmarked.as_deref().unwrap_or(payload);
+    match version {
+        YamlVersion::V1_1 => Loader::load(source, payload),
+        YamlVersion::V1_2 => Ok(Document {
+            value: serde_yaml::from_str(payload)?,
+            warnings: Loader::load(source, payload)
+                .map(|document| document.warnings)
+                .unwrap_or_default(),
+        }),
+    }
+}
+
+/// The type of a scalar.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Scalar {
+    Null,
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    /// A date or a date and time. They are kept as strings.
+    Timestamp,
+    Str,
+}
+
+impl Scalar {
+    /// Resolves a plain scalar as PyYAML's `SafeLoader`.
+    fn resolve_1_1(text: &str) -> Self {
+        if NULL.is_match(text) {
+            Self::Null
+        } else if BOOL.is_match(text) {
+            Self::Bool(
+                matches!(&text[..1], "y" | "Y" | "t" | "T") || text.eq_ignore_ascii_case("on"),
+            )
+        } else if FLOAT.is_match(text) {
+            float_1_1(text).map_or(Self::Str, Self::Float)
+        } else if INT.is_match(text) {
+            int_1_1(text).map_or(Self::Str, Self::Int)
+        } else if TIMESTAMP.is_match(text) {
+            Self::Timestamp
+        } else {
+            Self::Str
+        }
+    }
+
+    /// Resolves a plain scalar as `serde_yaml`, following the YAML 1.2 core schema.
+    fn resolve_1_2(text: &str) -> Self {
+        let unsigned = text.strip_prefix(['-', '+']).unwrap_or(text);
+        // Leading zeros followed by digits make a string in YAML 1.2.
+        let leading_zero = unsigned.len() > 1
+            && unsigned.starts_with('0')
+            && unsigned[1..].bytes().all(|b| b.is_ascii_digit());
+        match text {
+            "" | "~" | "null" | "Null" | "NULL" => Self::Null,
+            "true" | "True" | "TRUE" => Self::Bool(true),
+            "false" | "False" | "FALSE" => Self::Bool(false),
+            _ if leading_zero => Self::Str,
+            _ => int_1_2(text)
+                .map(Self::Int)
+                .or_else(|| float_1_2(text).map(Self::Float))
+                .unwrap_or(Self::Str),
+        }
+    }
+
+    fn differs(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Float(a), Self::Float(b)) => a != b && !(a.is_nan() && b.is_nan()),
+            _ => self != other,
+        }
+    }
+
+    fn describe(&self, text: &str) -> String {
+        match self {
+            Self::Null => "null".to_string(),
+            Self::Bool(b) => format!("the boolean {}", b),
+            Self::Int(int) => format!("the integer {}", int),
+            Self::Float(float) => format!("the number {}", float),
+            Self::Timestamp => "a timestamp".to_string(),
+            Self::Str => format!("the string {:?}", text),
+        }
+    }
+
+    fn into_value(self, text: &str) -> Value {
+        let number = match self {
+            Self::Null => return Value::Null,
+            Self::Bool(b) => return Value::Bool(b),
+            Self::Int(int) => i64::try_from(int)
+                .map(Number::from)
+                .or_else(|_| u64::try_from(int).map(Number::from))
+                .ok()
+                .or_else(|| Number::from_f64(int as f64)),
+            Self::Float(float) => Number::from_f64(float),
+            Self::Timestamp | Self::Str => None,
+        };
+        // Infinities and NaN have no JSON representation.
+        number.map_or_else(|| Value::String(text.to_string()), Value::Number)
+    }
+}
+
+/// Value of a scalar matching `INT`, as PyYAML's `construct_yaml_int`.
+fn int_1_1(text: &str) -> Option<i128> {
+    let value = text.replace('_', "");
+    let (sign, value) = match value.strip_prefix('-') {
+        Some(value) => (-1, value),
+        None => (1, value.strip_prefix('+').unwrap_or(&value)),
+    };
+    let int = if value == "0" {
+        0
+    } else if let Some(binary) = value.strip_prefix("0b") {
+        i128::from_str_radix(binary, 2).ok()?
+    } else if let Some(hexadecimal) = value.strip_prefix("0x") {
+        i128::from_str_radix(hexadecimal, 16).ok()?
+    } else if value.starts_with('0') {
+        i128::from_str_radix(value, 8).ok()?
+    } else if value.contains(':') {
+        value.split(':').try_fold(0i128, |int, digits| {
+            int.checked_mul(60)?.checked_add(digits.parse().ok()?)
+        })?
+    } else {
+        value.parse().ok()?
+    };
+    Some(sign * int)
+}
+
+/// Value of a scalar matching `FLOAT`, as PyYAML's `construct_yaml_float`.
+fn float_1_1(text: &str) -> Option<f64> {
+    let value = text.replace('_', "").to_lowercase();
+    let (sign, value) = match value.strip_prefix('-') {
+        Some(value) => (-1.0, value),
+        None => (1.0, value.strip_prefix('+').unwrap_or(&value)),
+    };
+    let float = if value == ".inf" {
+        f64::INFINITY
+    } else if value == ".nan" {
+        f64::NAN
+    } else if value.contains(':') {
+        value.split(':').try_fold(0.0, |float, digits| {
+            Some(float * 60.0 + digits.parse::<f64>().ok()?)
+        })?
+    } else {
+        value.parse().ok()?
+    };
+    Some(sign * float)
+}
+
+fn int_1_2(text: &str) -> Option<i128> {
+    let (negative, unsigned) = match text.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (radix, digits) = [("0x", 16), ("0o", 8), ("0b", 2)]
+        .into_iter()
+        .find_map(|(prefix, radix)| unsigned.strip_prefix(prefix).map(|digits| (radix, digits)))
+        .unwrap_or((10, unsigned));
+    if digits.starts_with(['-', '+']) {
+        return None;
+    }
+    let int = i128::from_str_radix(digits, radix).ok()?;
+    Some(if negative { -int } else { int })
+}
+
+fn float_1_2(text: &str) -> Option<f64> {
+    let unsigned = match text.strip_prefix('+') {
+        Some(unsigned) if unsigned.starts_with(['-', '+']) => return None,
+        Some(unsigned) => unsigned,
+        None => text,
+    };
+    match unsigned {
+        ".inf" | ".Inf" | ".INF" => Some(f64::INFINITY),
+        "-.inf" | "-.Inf" | "-.INF" => Some(f64::NEG_INFINITY),
+        ".nan" | ".NaN" | ".NAN" => Some(f64::NAN),
+        _ => unsigned
+            .parse()
+            .ok()
+            .filter(|float: &f64| float.is_finite()),
+    }
+}
+
+/// The key cloud-init sees for a mapping key: Python's `str` of the loaded key, `None` if it is
+/// not hashable.
+fn key_string(key: &Value) -> Option<String> {
+    match key {
+        Value::String(s) => Some(s.clone()),
+        Value::Null => Some("None".to_string()),
+        Value::Bool(true) => Some("True".to_string()),
+        Value::Bool(false) => Some("False".to_string()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Array(_) | Value::Object(_) => None,
+    }
+}
+
+/// Builds a document from the events of a payload, as PyYAML's `SafeConstructor`.
+struct Loader<'input> {
+    events: std::vec::IntoIter<(Event<'input>, EventSpan)>,
+    markers: Markers,
+    anchors: HashMap<usize, Value>,
+    warnings: Vec<Warning>,
+}
+
+impl<'input> Loader<'input> {
+    /// Loads `source`, the events of which are located in `payload`.
+    fn load(source: &'input str, payload: &str) -> Result<Document> {
+        let events = Parser::new_from_str(source)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| Error::YamlLoad {
+                description: e.info().to_string(),
+                line: e.marker().line(),
+                column: e.marker().col() + 1,
+            })?;
+        let mut loader = Self {
+            events: events.into_iter(),
+            markers: Markers::new(payload),
+            anchors: HashMap::new(),
+            warnings: vec![],
+        };
+        let mut value = Value::Null;
+        while let Some((event, span)) = loader.events.next() {
+            match event {
+                Event::Scalar(..)
+                | Event::Alias(_)
+                | Event::SequenceStart(..)
+                | Event::MappingStart(..) => value = loader.node(event, span, "")?,
+                _ => {}
+            }
+        }
+        Ok(Document {
+            value,
+            warnings: loader.warnings,
+        })
+    }
+
+    fn next(&mut self) -> (Event<'input>, EventSpan) {
+        self.events
+            .next()
+            .expect("The parser balances collection events")
+    }
+
+    fn error(&self, description: String, span: &EventSpan) -> Error {
+        Error::YamlLoad {
+            description,
+            line: span.start.line(),
+            column: span.start.col() + 1,
+        }
+    }
+
+    fn warn(&mut self, code: Code, description: String, instance_path: String, span: &EventSpan) {
+        self.warnings.push(Warning {
+            code,
+            description,
+            instance_path,
+            span: self.markers.span(span),
+        });
+    }
+
+    /// Loads the node starting with `event`, at `path`.
+    fn node(&mut self, event: Event<'input>, span: EventSpan, path: &str) -> Result<Value> {
+        let (anchor, value) = match event {
+            Event::Alias(anchor) => {
+                return self
+                    .anchors
+                    .get(&anchor)
+                    .cloned()
+                    .ok_or_else(|| self.error("found undefined alias".to_string(), &span))
+            }
+            Event::Scalar(text, style, anchor, tag) => {
+                let (value, divergence) = self.scalar(&text, style, tag.as_deref(), &span)?;
+                if let Some(divergence) = divergence {
+                    self.warn(Code::YamlType, divergence, path.to_string(), &span);
+                }
+                (anchor, value)
+            }
+            Event::SequenceStart(anchor, _) => (anchor, self.sequence(path)?),
+            Event::MappingStart(anchor, _) => (anchor, self.mapping(path)?),
+            _ => unreachable!("Only nodes are loaded"),
+        };
+        // Anchor ids start at 1.
+        if anchor > 0 {
+            self.anchors.insert(anchor, value.clone());
+        }
+        Ok(value)
+    }
+
+    /// Loads a scalar, returning its value and, if it is plain, how YAML 1.2 would type it
+    /// differently.
+    fn scalar(
+        &self,
+        text: &str,
+        style: ScalarStyle,
+        tag: Option<&Tag>,
+        span: &EventSpan,
+    ) -> Result<(Value, Option<String>)> {
+        let scalar = match tag {
+            None if style == ScalarStyle::Plain => {
+                let scalar = Scalar::resolve_1_1(text);
+                let scalar_1_2 = Scalar::resolve_1_2(text);
+                let divergence = scalar.differs(&scalar_1_2).then(|| {
+                    let mut description = format!(
+                        "{:?} is {} in YAML 1.1, which cloud-init uses, but {} in YAML 1.2",
+                        text,
+                        scalar.describe(text),
+                        scalar_1_2.describe(text)
+                    );
+                    if scalar != Scalar::Str {
+                        description.push_str(", quote it if a string is meant");
+                    }
+                    description
+                });
+                return Ok((scalar.into_value(text), divergence));
+            }
+            None => Scalar::Str,
+            Some(tag) if tag.is_yaml_core_schema() => match tag.suffix.as_str() {
+                "str" | "binary" => Scalar::Str,
+                "null" | "bool" | "int" | "float" | "timestamp" => Scalar::resolve_1_1(text),
+                _ => return Err(self.unknown_tag(tag, span)),
+            },
+            Some(tag) => return Err(self.unknown_tag(tag, span)),
+        };
+        Ok((scalar.into_value(text), None))
+    }
+
+    fn unknown_tag(&self, tag: &Tag, span: &EventSpan) -> Error {
+        self.error(
+            format!("could not determine a constructor for the tag '{}'", tag),
+            span,
+        )
+    }
+
+    fn sequence(&mut self, path: &str) -> Result<Value> {
+        let mut items = vec![];
+        loop {
+            let (event, span) = self.next();
+            if let Event::SequenceEnd = event {
+                return Ok(Value::Array(items));
+            }
+            let item_path = format!("{}/{}", path, items.len());
+            items.push(self.node(event, span, &item_path)?);
+        }
+    }
+
+    /// Loads a mapping. Later duplicate keys override earlier ones, and the mappings of `<<`
+    /// merge keys are merged into it, without overriding its own keys.
+    fn mapping(&mut self, path: &str) -> Result<Value> {
+        let mut mapping = Map::new();
+        let mut merged = vec![];
+        loop {
+            let (event, key_span) = self.next();
+            let (key, divergence) = match event {
+                Event::MappingEnd => break,
+                Event::Scalar(text, ScalarStyle::Plain, _, None) if text == "<<" => {
+                    let (event, span) = self.next();
+                    let merges = match self.node(event, span, path)? {
+                        Value::Array(merges) => merges,
+                        merge => vec![merge],
+                    };
+                    // The first mappings of a list take precedence.
+                    for merge in merges.into_iter().rev() {
+                        match merge {
+                            Value::Object(merge) => merged.push(merge),
+                            _ => {
+                                return Err(self.error(
+                                    "expected a mapping or list of mappings for merging"
+                                        .to_string(),
+                                    &span,
+                                ))
+                            }
+                        }
+                    }
+                    continue;
+                }
+                Event::Scalar(text, style, _, tag) => {
+                    self.scalar(&text, style, tag.as_deref(), &key_span)?
+                }
+                event => (self.node(event, key_span, path)?, None),
+            };
+            let key = key_string(&key)
+                .ok_or_else(|| self.error("found unhashable key".to_string(), &key_span))?;
+            let value_path = format!("{}/{}", path, span::escape(&key));
+            if let Some(divergence) = divergence {
+                self.warn(
+                    Code::YamlType,
+                    format!("Key {}", divergence),
+                    value_path.clone(),
+                    &key_span,
+                );
+            }
+            if mapping.contains_key(&key) {
+                self.warn(
+                    Code::DuplicateKey,
+                    format!("Duplicate key {:?}, cloud-init uses its last value", key),
+                    value_path.clone(),
+                    &key_span,
+                );
+            }
+            let (event, span) = self.next();
+            let value = self.node(event, span, &value_path)?;
+            mapping.insert(key, value);
+        }
+        if merged.is_empty() {
+            return Ok(Value::Object(mapping));
+        }
+        let mut flattened = Map::new();
+        for merge in merged {
+            flattened.extend(merge);
+        }
+        flattened.extend(mapping);
+        Ok(Value::Object(flattened))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn resolution() {
+        let resolve = |text: &str| {
+            (
+                Scalar::resolve_1_1(text).into_value(text),
+                Scalar::resolve_1_2(text).into_value(text),
+            )
+        };
+        for (text, yaml_1_1, yaml_1_2) in [
+            ("", json!(null), json!(null)),
+            ("~", json!(null), json!(null)),
+            ("yes", json!(true), json!("yes")),
+            ("Off", json!(false), json!("Off")),
+            ("y", json!("y"), json!("y")),
+            ("True", json!(true), json!(true)),
+            ("0644", json!(420), json!("0644")),
+            ("0o644", json!("0o644"), json!(420)),
+            ("09", json!("09"), json!("09")),
+            ("0x1F", json!(31), json!(31)),
+            ("-0b101", json!(-5), json!(-5)),
+            ("1_000", json!(1000), json!("1_000")),
+            ("1:30", json!(90), json!("1:30")),
+            ("-1:30:00", json!(-5400), json!("-1:30:00")),
+            ("1:30.5", json!(90.5), json!("1:30.5")),
+            ("1.5", json!(1.5), json!(1.5)),
+            ("1e3", json!("1e3"), json!(1000.0)),
+            ("1.0e+3", json!(1000.0), json!(1000.0)),
+            (".5", json!(0.5), json!(0.5)),
+            (".inf", json!(".inf"), json!(".inf")),
+            ("2024-01-31", json!("2024-01-31"), json!("2024-01-31")),
+            ("a: b", json!("a: b"), json!("a: b")),
+        ] {
+            assert_eq!((yaml_1_1, yaml_1_2), resolve(text), "{}", text);
+        }
+        assert!(Scalar::resolve_1_1("2024-01-31").differs(&Scalar::resolve_1_2("2024-01-31")));
+        assert!(!Scalar::resolve_1_1(".nan").differs(&Scalar::resolve_1_2(".nan")));
+    }
+
+    #[test]
+    fn yaml_1_1() {
+        let payload = "\
+a: &a
+  x: yes
+  y: '0644'
+b:
+  <<: *a
+  x: 1
+c: [0644, !!str 0644, \"on\"]
+on: 1:30
+a: 2
+";
+        let document = load(payload, YamlVersion::V1_1).unwrap();
+        assert_eq!(
+            json!({
+                "a": 2,
+                "b": {"x": 1, "y": "0644"},
+                "c": [420, "0644", "on"],
+                "True": 90,
+            }),
+            document.value
+        );
+        let warnings: Vec<_> = document
+            .warnings
+            .iter()
+            .map(|warning| {
+                (
+                    warning.instance_path.as_str(),
+                    warning.span.start.line,
+                    warning.description.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    "/a/x",
+                    2,
+                    "\"yes\" is the boolean true in YAML 1.1, which cloud-init uses, but the \
+                     string \"yes\" in YAML 1.2, quote it if a string is meant"
+                ),
+                (
+                    "/c/0",
+                    7,
+                    "\"0644\" is the integer 420 in YAML 1.1, which cloud-init uses, but the \
+                     string \"0644\" in YAML 1.2, quote it if a string is meant"
+                ),
+                (
+                    "/True",
+                    8,
+                    "Key \"on\" is the boolean true in YAML 1.1, which cloud-init uses, but the \
+                     string \"on\" in YAML 1.2, quote it if a string is meant"
+                ),
+                (
+                    "/True",
+                    8,
+                    "\"1:30\" is the integer 90 in YAML 1.1, which cloud-init uses, but the \
+                     string \"1:30\" in YAML 1.2, quote it if a string is meant"
+                ),
+                (
+                    "/a",
+                    9,
+                    "Duplicate key \"a\", cloud-init uses its last value"
+                ),
+            ],
+            warnings
+        );
+
+        let document = load(payload, YamlVersion::V1_2).unwrap();
+        assert_eq!(json!("0644"), document.value["c"][0]);
+        assert_eq!(json!("1:30"), document.value["on"]);
+        assert_eq!(5, document.warnings.len());
+    }
+
+    #[test]
+    fn load_errors() {
+        let error = load("a: [", YamlVersion::V1_1).unwrap_err();
+        assert!(matches!(error, Error::InvalidYaml(_)));
+
+        for (payload, expected) in [
+            (
+                "a: !foo 1\n",
+                "invalid yaml: could not determine a constructor for the tag '!foo' at line 1 \
+                 column 9",
+            ),
+            (
+                "? [a]\n: 1\n",
+                "invalid yaml: found unhashable key at line 1 column 3",
+            ),
+            (
+                "a:\n  <<: 1\n",
+                "invalid yaml: expected a mapping or list of mappings for merging at line 2 \
+                 column 7",
+            ),
+        ] {
+            assert_eq!(
+                expected,
+                load(payload, YamlVersion::V1_1).unwrap_err().to_string()
+            );
+        }
+    }
+}
@@ -5,30 +5,68 @@ use axum::{
     routing::{get, post},
     Router,
 };
-use ccv_core::schema::ConfigKind;
+use ccv_core::jinja::InstanceData;
+use ccv_core::schema::{ConfigKind, Release};
+use ccv_core::userdata::UserData;
+use serde::Deserialize;
 use serde_json::json;
 use std::sync::RwLock;
 use tokio::time;
 use tower_http::trace::TraceLayer;
 
-use axum::extract::State;
+use axum::extract::{Query, State};
 use axum::http::StatusCode;
 use axum::response;
 use axum::response::IntoResponse;
 
+use crate::config::Config;
 use crate::error::ApiError;
+use crate::releases::ReleaseValidators;
 use crate::validator::CloudConfig;
+use crate::validator::NetworkConfig;
 use crate::validator::Validator;
 
 #[derive(Debug)]
 pub struct AppState {
-    pub cc_validator: Validator,
-    pub nc_validator: Validator,
+    pub cc_validator: Arc<Validator>,
+    pub nc_validator: Arc<Validator>,
+    pub nc_v2_validator: Arc<Validator>,
+    /// Validators of the bundled releases requested.
+    pub releases: Arc<ReleaseValidators>,
+}
+
+/// The validator of `kind` of the bundled cloud-init release `version`, if given, otherwise of the
+/// configured schema source. `state` is only locked to look the validator up: the ones of releases
+/// are compiled unlocked, not to block the refresh of the schemas meanwhile.
+fn validator(
+    state: &RwLock<AppState>,
+    version: Option<&str>,
+    kind: ConfigKind,
+) -> ccv_core::error::Result<Arc<Validator>> {
+    let state = state.read().expect("error unlocking state");
+    let Some(version) = version else {
+        return Ok(match kind {
+            ConfigKind::CloudConfig => state.cc_validator.clone(),
+            ConfigKind::NetworkConfig => state.nc_validator.clone(),
+            ConfigKind::NetworkConfigV2 => state.nc_v2_validator.clone(),
+        });
+    };
+    let releases = state.releases.clone();
+    drop(state);
+    releases.get(version, kind)
+}
+
+/// Query parameters of the validation endpoints.
+#[derive(Debug, Deserialize)]
+pub struct ValidateQuery {
+    /// Bundled cloud-init release to validate against, unless the request body sets one.
+    cloud_init_version: Option<String>,
 }
 
 #[tracing::instrument(level = "info", skip(state, payload))] // do not leak the payload
 pub async fn validate(
     State(state): State<Arc<RwLock<AppState>>>,
+    Query(query): Query<ValidateQuery>,
     Json(payload): Json<CloudConfig>,
 ) -> Result<impl IntoResponse, ApiError> {
     // Note: `validate_yaml` over an unbound yaml could block the async runtime, causing a delay
@@ -48,11 +86,29 @@ pub async fn validate(
     // where N is the number of CPU cores.
     let (send, recv) = tokio::sync::oneshot::channel();
     rayon::spawn(move || {
-        let resp = state
-            .read()
-            .expect("error unlocking state")
-            .cc_validator
-            .validate_yaml(payload.payload());
+        let user_data = UserData::detect(payload.payload().as_bytes());
+        let version = payload
+            .cloud_init_version()
+            .or(query.cloud_init_version.as_deref());
+        let validator = validator(&state, version, ConfigKind::CloudConfig);
+        let resp = validator.and_then(|validator| {
+            let resp = match payload.instance_data() {
+                Some(instance_data) => {
+                    let instance_data = InstanceData::from_value(instance_data.clone())?;
+                    validator.validate_templated_user_data(&user_data, &instance_data)?
+                }
+                None => validator.validate_user_data(&user_data)?,
+            };
+            let resp = if payload.merged() {
+                let vendor_data = payload
+                    .vendor_data()
+                    .map(|vendor_data| UserData::detect(vendor_data.as_bytes()));
+                resp.with_merged(validator.validate_merged(&user_data, vendor_data.as_ref()))
+            } else {
+                resp
+            };
+            Ok(resp.with_deprecation_policy(payload.deprecations()))
+        });
         let _ = send.send(resp);
     });
     let resp = recv.await.expect("Panic in rayon::spawn")?;
@@ -63,15 +119,17 @@ pub async fn validate(
 #[tracing::instrument(level = "info", skip(state, payload))] // do not leak the payload
 pub async fn nc_validate(
     State(state): State<Arc<RwLock<AppState>>>,
-    Json(payload): Json<CloudConfig>,
+    Query(query): Query<ValidateQuery>,
+    Json(payload): Json<NetworkConfig>,
 ) -> Result<impl IntoResponse, ApiError> {
     let (send, recv) = tokio::sync::oneshot::channel();
     rayon::spawn(move || {
-        let resp = state
-            .read()
-            .expect("error unlocking state")
-            .nc_validator
-            .validate_yaml(payload.payload());
+        let version = payload
+            .cloud_init_version()
+            .or(query.cloud_init_version.as_deref());
+        let resp = validator(&state, version, payload.version().kind())
+            .and_then(|validator| validator.validate_yaml(payload.payload()))
+            .map(|resp| resp.with_deprecation_policy(payload.deprecations()));
         let _ = send.send(resp);
     });
     let resp = recv.await.expect("Panic in rayon::spawn")?;
@@ -79,18 +137,80 @@ pub async fn nc_validate(
     Ok((StatusCode::OK, response::Json(resp)))
 }
 
-pub async fn create_api() -> Router {
-    let cc_validator = match Validator::new(ConfigKind::CloudConfig).await {
+/// Lists the bundled cloud-init releases requests can select with `cloud_init_version`, and the
+/// kinds of config each ships a schema for.
+pub async fn versions() -> impl IntoResponse {
+    let releases: Vec<_> = Release::all()
+        .iter()
+        .map(|release| {
+            let kinds: Vec<String> = [
+                ConfigKind::CloudConfig,
+                ConfigKind::NetworkConfig,
+                ConfigKind::NetworkConfigV2,
+            ]
+            .iter()
+            .filter(|kind| release.ships(kind))
+            .map(ToString::to_string)
+            .collect();
+            json!({"version": release.version().to_string(), "kinds": kinds})
+        })
+        .collect();
+    Json(json!({ "releases": releases }))
+}
+
+/// Loads the validator of `kind` through the schema cache.
+async fn load_validator(config: &Config, kind: ConfigKind) -> ccv_core::error::Result<Validator> {
+    let (schema, status) = config.cache.load(config.schema_source(&kind), kind).await?;
+    if let Some(warning) = status.warning() {
+        tracing::warn!("{}", warning);
+    }
+    Validator::from_schema(schema)
+}
+
+/// Revalidates the cached schema of `kind`, returning its validator unless it could not be
+/// fetched.
+async fn refresh_validator(config: &Config, kind: ConfigKind) -> Option<Validator> {
+    let refreshed = match config
+        .cache
+        .refresh(config.schema_source(&kind), kind)
+        .await
+    {
+        Err(e) => Err(e.to_string()),
+        Ok((schema, status)) => match status.warning() {
+            Some(warning) => Err(warning),
+            None => Validator::from_schema(schema).map_err(|e| e.to_string()),
+        },
+    };
+    match refreshed {
+        Err(e) => {
+            tracing::error!(
+                "Error reading new JsonSchema. Re-using the previous one: {}",
+                e
+            );
+            None
+        }
+        Ok(validator) => Some(validator),
+    }
+}
+
+pub async fn create_api(config: Config) -> Router {
+    let cc_validator = match load_validator(&config, ConfigKind::CloudConfig).await {
         Err(e) => panic!("Error reading the JsonSchema: {}", e),
         Ok(v) => v,
     };
-    let nc_validator = match Validator::new(ConfigKind::NetworkConfig).await {
+    let nc_validator = match load_validator(&config, ConfigKind::NetworkConfig).await {
+        Err(e) => panic!("Error reading the JsonSchema: {}", e),
+        Ok(v) => v,
+    };
+    let nc_v2_validator = match load_validator(&config, ConfigKind::NetworkConfigV2).await {
         Err(e) => panic!("Error reading the JsonSchema: {}", e),
         Ok(v) => v,
     };
     let app_state = AppState {
-        cc_validator,
-        nc_validator,
+        cc_validator: Arc::new(cc_validator),
+        nc_validator: Arc::new(nc_validator),
+        nc_v2_validator: Arc::new(nc_v2_validator),
+        releases: Arc::new(ReleaseValidators::new(config.release_validators)),
     };
     let shared_state = Arc::new(RwLock::new(app_state));
 
@@ -104,38 +224,31 @@ pub async fn create_api() -> Router {
             loop {
                 interval.tick().await;
                 tracing::info!("refreshing cloud-config jsonschema");
-                let cc_validator = Validator::new(ConfigKind::CloudConfig).await;
-                match cc_validator {
-                    Err(e) => {
-                        tracing::error!(
-                            "Error reading new JsonSchema. Re-using the previous one: {}",
-                            e
-                        )
-                    }
-                    Ok(validator) => {
-                        shared_state
-                            .write()
-                            .expect("Error locking `ApiState`")
-                            .cc_validator = validator
-                    }
-                };
+                if let Some(validator) = refresh_validator(&config, ConfigKind::CloudConfig).await {
+                    shared_state
+                        .write()
+                        .expect("Error locking `ApiState`")
+                        .cc_validator = Arc::new(validator)
+                }
 
                 tracing::info!("refreshing network-config jsonschema");
-                let nc_validator = Validator::new(ConfigKind::NetworkConfig).await;
-                match nc_validator {
-                    Err(e) => {
-                        tracing::error!(
-                            "Error reading new JsonSchema. Re-using the previous one: {}",
-                            e
-                        )
-                    }
-                    Ok(validator) => {
-                        shared_state
-                            .write()
-                            .expect("Error locking `ApiState`")
-                            .nc_validator = validator
-                    }
-                };
+                if let Some(validator) = refresh_validator(&config, ConfigKind::NetworkConfig).await
+                {
+                    shared_state
+                        .write()
+                        .expect("Error locking `ApiState`")
+                        .nc_validator = Arc::new(validator)
+                }
+
+                tracing::info!("refreshing network-config v2 jsonschema");
+                if let Some(validator) =
+                    refresh_validator(&config, ConfigKind::NetworkConfigV2).await
+                {
+                    shared_state
+                        .write()
+                        .expect("Error locking `ApiState`")
+                        .nc_v2_validator = Arc::new(validator)
+                }
             }
         }
     });
@@ -144,6 +257,7 @@ pub async fn create_api() -> Router {
         .route("/", get(|| async { Json(json!(["/v1"])) }))
         .route("/v1/cloud-config/validate", post(validate))
         .route("/v1/network-config/validate", post(nc_validate))
+        .route("/v1/versions", get(versions))
         .layer(TraceLayer::new_for_http())
         .with_state(shared_state)
 }
@@ -152,9 +266,15 @@ pub async fn create_api() -> Router {
 mod test {
     use super::*;
     use axum_test::TestServer;
+    use ccv_core::code::{Code, Severity};
+    use ccv_core::schema::VendoredSource;
 
     async fn test_client() -> TestServer {
-        let api = create_api().await;
+        let api = create_api(Config {
+            schema_source: Arc::new(VendoredSource),
+            ..Config::default()
+        })
+        .await;
 
         TestServer::new(api).unwrap()
     }
@@ -167,6 +287,112 @@ mod test {
         assert_eq!(res.text(), "[\"/v1\"]");
     }
 
+    #[tokio::test]
+    async fn versions() {
+        let client = test_client().await;
+        let res = client.get("/v1/versions").await;
+        assert_eq!(res.status_code(), StatusCode::OK);
+        #[cfg(feature = "release-23-1")]
+        assert_eq!(
+            res.text(),
+            "{\"releases\":[{\"kinds\":[\"cloudconfig\"],\"version\":\"23.1\"}]}"
+        );
+
+        // Requests can select every listed release, for the kinds it ships.
+        let releases = res.json::<serde_json::Value>()["releases"].clone();
+        for release in releases.as_array().unwrap() {
+            let kinds = release["kinds"].as_array().unwrap();
+            for (kind, uri, payload) in [
+                (
+                    "cloudconfig",
+                    "/v1/cloud-config/validate",
+                    "#cloud-config\n",
+                ),
+                (
+                    "networkconfig",
+                    "/v1/network-config/validate",
+                    "version: 1\nconfig: []\n",
+                ),
+                (
+                    "networkconfigv2",
+                    "/v1/network-config/validate",
+                    "version: 2\n",
+                ),
+            ] {
+                let res = client
+                    .post(uri)
+                    .json(&json!({"payload": payload, "cloud_init_version": release["version"]}))
+                    .await;
+                let status = if kinds.contains(&json!(kind)) {
+                    StatusCode::OK
+                } else {
+                    StatusCode::BAD_REQUEST
+                };
+                assert_eq!(res.status_code(), status, "{} {}", release, kind);
+            }
+        }
+    }
+
+    #[cfg(feature = "release-23-1")]
+    #[tokio::test]
+    async fn cloud_init_versions() {
+        let client = test_client().await;
+        let res = client
+            .post("/v1/cloud-config/validate")
+            .json(&json!({"payload": "#cloud-config\npackages: 1\n", "cloud_init_version": "23.1"}))
+            .await;
+        assert_eq!(res.status_code(), StatusCode::OK);
+        assert!(res.text().contains("\"is_valid\":false"));
+
+        let res = client
+            .post("/v1/network-config/validate")
+            .add_query_param("cloud_init_version", "23.1.2")
+            .json(&json!({"payload": "version: 2\nethernets:\n  eth0:\n    dhcp4: true\n"}))
+            .await;
+        assert_eq!(res.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            res.text(),
+            "{\"errors\":[\"cloud-init 23.1 ships no networkconfigv2 schema\"]}"
+        );
+
+        let res = client
+            .post("/v1/cloud-config/validate")
+            .add_query_param("cloud_init_version", "9.1")
+            .json(&json!({"payload": "#cloud-config\n"}))
+            .await;
+        assert_eq!(res.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            res.text(),
+            "{\"errors\":[\"no bundled schemas for cloud-init 9.1\"]}"
+        );
+
+        // The request body takes precedence over the query.
+        let res = client
+            .post("/v1/cloud-config/validate")
+            .add_query_param("cloud_init_version", "9.1")
+            .json(&json!({"payload": "#cloud-config\n", "cloud_init_version": "23.1"}))
+            .await;
+        assert_eq!(res.status_code(), StatusCode::OK);
+    }
+
+    #[cfg(feature = "release-23-1")]
+    #[tokio::test]
+    async fn release_source() {
+        // 23.1 ships no network-config schemas, which are vendored instead.
+        let api = create_api(Config {
+            schema_source: Arc::new(*Release::get("23.1").unwrap()),
+            ..Config::default()
+        })
+        .await;
+        let client = TestServer::new(api).unwrap();
+        let res = client
+            .post("/v1/network-config/validate")
+            .json(&json!({"payload": "version: 2\nethernets:\n  eth0:\n    dhcp4: true\n"}))
+            .await;
+        assert_eq!(res.status_code(), StatusCode::OK);
+        assert!(res.text().contains("\"is_valid\":true"));
+    }
+
     #[tokio::test]
     async fn invalid_yaml() {
         let client = test_client().await;
@@ -178,6 +404,150 @@ mod test {
         assert_eq!(res.text(), "{\"errors\":[\"found unexpected end of stream at line 1 column 3, while scanning a quoted scalar\"]}");
     }
 
+    #[tokio::test]
+    async fn user_data_types() {
+        let client = test_client().await;
+        let res = client
+            .post("/v1/cloud-config/validate")
+            .json(&json!({"payload": "#cloud-config\npackages: [vim]\n"}))
+            .await;
+        assert_eq!(res.status_code(), StatusCode::OK);
+        assert_eq!(
+            res.text(),
+            "{\"annotations\":[],\"content_type\":\"text/cloud-config\",\"errors\":[],\"is_valid\":true}"
+        );
+
+        let res = client
+            .post("/v1/cloud-config/validate")
+            .json(&json!({"payload": "IyEvYmluL3NoCmVjaG8gaGkK"}))
+            .await;
+        assert_eq!(res.status_code(), StatusCode::OK);
+        assert_eq!(
+            res.text(),
+            "{\"annotations\":[],\"content_type\":\"text/x-shellscript\",\"encodings\":[\"base64\"],\"errors\":[],\"is_valid\":true}"
+        );
+    }
+
+    #[tokio::test]
+    async fn merged_with_vendor_data() {
+        let client = test_client().await;
+        let res = client
+            .post("/v1/cloud-config/validate")
+            .json(&json!({
+                "payload": "#cloud-config\nruncmd: [ls]\n",
+                "vendor_data": "#cloud-config\nruncmd: [pwd]\npackage_update: 1\n",
+            }))
+            .await;
+        assert_eq!(res.status_code(), StatusCode::OK);
+        let res: serde_json::Value = serde_json::from_str(&res.text()).unwrap();
+        assert_eq!(res["is_valid"], json!(false));
+        assert_eq!(
+            res["merged"]["config"],
+            json!({"runcmd": ["ls"], "package_update": 1})
+        );
+        assert_eq!(
+            res["merged"]["validation"]["errors"][0]["instance_path"],
+            json!("/package_update")
+        );
+    }
+
+    #[tokio::test]
+    async fn typed_response() {
+        let client = test_client().await;
+        let res = client
+            .post("/v1/cloud-config/validate")
+            .json(&json!({
+                "payload": "#cloud-config\npower_state:\n  mode: reboto\nssh_pwauth: no\n",
+                "merged": true,
+            }))
+            .await;
+        assert_eq!(res.status_code(), StatusCode::OK);
+        let validation: ccv_core::validator::Validation =
+            serde_json::from_str(&res.text()).unwrap();
+        let error = validation.errors().next().unwrap();
+        assert_eq!(Code::Enum, error.code());
+        assert_eq!(Some(&json!("reboto")), error.value());
+        assert_eq!("reboot", error.suggestions()[0].candidates[0]);
+        assert_eq!(
+            vec![Code::YamlType],
+            validation
+                .with_severity(Severity::Warning)
+                .map(|diagnostic| diagnostic.code())
+                .collect::<Vec<_>>()
+        );
+        assert!(!validation.merged().unwrap().validation.is_valid);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&res.text()).unwrap(),
+            serde_json::to_value(&validation).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn deprecation_policy() {
+        let client = test_client().await;
+        let payload = "#cloud-config\napt_update: 1\n";
+        let res = client
+            .post("/v1/cloud-config/validate")
+            .json(&json!({"payload": payload}))
+            .await;
+        assert_eq!(res.status_code(), StatusCode::OK);
+        let res: serde_json::Value = serde_json::from_str(&res.text()).unwrap();
+        assert_eq!(res["errors"][0]["instance_path"], json!("/apt_update"));
+        assert_eq!(res["annotations"][0]["code"], json!("CCV-W-DEPRECATED"));
+        assert_eq!(res["annotations"][0]["deprecated_version"], json!("22.2"));
+        assert_eq!(res["annotations"][0]["removal_version"], json!("27.2"));
+        assert_eq!(
+            res["annotations"][0]["replacement"],
+            json!("package_update")
+        );
+
+        let res = client
+            .post("/v1/cloud-config/validate")
+            .json(&json!({"payload": "#cloud-config\napt_update: true\n", "deprecations": "error"}))
+            .await;
+        assert_eq!(res.status_code(), StatusCode::OK);
+        let res: serde_json::Value = serde_json::from_str(&res.text()).unwrap();
+        assert_eq!(res["is_valid"], json!(false));
+        assert_eq!(res["errors"][0]["code"], json!("CCV-W-DEPRECATED"));
+        assert_eq!(res["errors"][0]["severity"], json!("error"));
+
+        let res = client
+            .post("/v1/cloud-config/validate")
+            .json(&json!({"payload": payload, "deprecations": "ignore"}))
+            .await;
+        let res: serde_json::Value = serde_json::from_str(&res.text()).unwrap();
+        assert_eq!(res["annotations"], json!([]));
+
+        let res = client
+            .post("/v1/cloud-config/validate")
+            .json(&json!({"payload": payload, "deprecations": "bogus"}))
+            .await;
+        assert_eq!(res.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn templated_user_data() {
+        let client = test_client().await;
+        let res = client
+            .post("/v1/cloud-config/validate")
+            .json(&json!({
+                "payload": "## template: jinja\n#cloud-config\npackage_update: {{ v1.region }}\n",
+                "instance_data": {"v1": {"region": 1}},
+            }))
+            .await;
+        assert_eq!(res.status_code(), StatusCode::OK);
+        let res: serde_json::Value = serde_json::from_str(&res.text()).unwrap();
+        assert_eq!(res["is_valid"], json!(false));
+        assert_eq!(res["content_type"], json!("text/jinja2"));
+        assert_eq!(res["errors"][0]["instance_path"], json!("/package_update"));
+
+        let res = client
+            .post("/v1/cloud-config/validate")
+            .json(&json!({"payload": "## template: jinja\n", "instance_data": []}))
+            .await;
+        assert_eq!(res.status_code(), StatusCode::BAD_REQUEST);
+    }
+
     #[tokio::test]
     async fn nc_valid_yaml() {
         let client = test_client().await;
@@ -187,11 +557,12 @@ network:
   config:
   - type: bond
     name: a
-    mac_address: aa:bb
-    mtu: 1
+    mac_address: aa:bb:cc:dd:ee:ff
+    mtu: 1500
     subnets:
-    - type: dhcp6
+    - type: static
       control: manual
+      address: 10.0.0.2
       netmask: 255.255.255.0
       gateway: 10.0.0.1
       dns_nameservers:
@@ -200,8 +571,8 @@ network:
       - find.me
       routes:
       - type: route
-        destination: 10.20.0.0/8
-        gateway: a.b.c.d
+        destination: 10.0.0.0/8
+        gateway: 10.0.0.1
         metric: 200"#;
         let res = client
             .post("/v1/network-config/validate")
@@ -213,4 +584,107 @@ network:
             "{\"annotations\":[],\"errors\":[],\"is_valid\":true}"
         );
     }
+
+    #[tokio::test]
+    async fn nc_semantic_checks() {
+        let client = test_client().await;
+        let network_config = r#"
+network:
+  version: 1
+  config:
+  - type: bond
+    name: a
+    mac_address: aa:bb
+    mtu: 1
+    subnets:
+    - type: dhcp6
+      netmask: 255.255.255.0
+      gateway: 10.0.0.1
+      routes:
+      - type: route
+        destination: 10.20.0.0/8
+        gateway: a.b.c.d"#;
+        let res = client
+            .post("/v1/network-config/validate")
+            .json(&json!({"payload": network_config}))
+            .await;
+        assert_eq!(res.status_code(), StatusCode::OK);
+        let validation: serde_json::Value = serde_json::from_str(&res.text()).unwrap();
+        assert_eq!(validation["is_valid"], false);
+        let codes_and_paths = |key: &str| -> Vec<(String, String)> {
+            validation[key]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| {
+                    (
+                        item["code"].as_str().unwrap().to_string(),
+                        item["instance_path"].as_str().unwrap().to_string(),
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(
+            vec![
+                (
+                    "CCV-E-MALFORMED".to_string(),
+                    "/network/config/0/mac_address".to_string()
+                ),
+                (
+                    "CCV-E-MALFORMED".to_string(),
+                    "/network/config/0/subnets/0/routes/0/destination".to_string()
+                ),
+                (
+                    "CCV-E-MALFORMED".to_string(),
+                    "/network/config/0/subnets/0/routes/0/gateway".to_string()
+                ),
+                (
+                    "CCV-E-RANGE".to_string(),
+                    "/network/config/0/mtu".to_string()
+                ),
+            ],
+            codes_and_paths("errors")
+        );
+        assert_eq!(
+            vec![
+                (
+                    "CCV-W-ADDRESS-FAMILY".to_string(),
+                    "/network/config/0/subnets/0/netmask".to_string()
+                ),
+                (
+                    "CCV-W-ADDRESS-FAMILY".to_string(),
+                    "/network/config/0/subnets/0/gateway".to_string()
+                ),
+            ],
+            codes_and_paths("annotations")
+        );
+    }
+
+    #[tokio::test]
+    async fn nc_v2() {
+        let client = test_client().await;
+        let network_config = "version: 2\nethernets:\n  eth0:\n    dhcp4: yes\n";
+        let res = client
+            .post("/v1/network-config/validate")
+            .json(&json!({"payload": network_config}))
+            .await;
+        assert_eq!(res.status_code(), StatusCode::OK);
+        assert_eq!(
+            res.text(),
+            "{\"annotations\":[],\"errors\":[],\"is_valid\":true}"
+        );
+
+        let res = client
+            .post("/v1/network-config/validate")
+            .json(&json!({"payload": network_config, "version": 1}))
+            .await;
+        assert_eq!(res.status_code(), StatusCode::OK);
+        assert!(res.text().contains("\"is_valid\":false"));
+
+        let res = client
+            .post("/v1/network-config/validate")
+            .json(&json!({"payload": network_config, "version": 3}))
+            .await;
+        assert_eq!(res.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
 }
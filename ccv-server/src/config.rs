@@ -0,0 +1,178 @@
+use std::{env, sync::Arc, time::Duration};
+
+use ccv_core::{
+    cache::SchemaCache,
+    schema::{source_from_spec, ConfigKind, SchemaSource, UrlSource, VendoredSource},
+};
+
+/// Environment variable selecting the schema source, see [`source_from_spec`].
+const SCHEMA_SOURCE_VAR: &str = "CCV_SCHEMA_SOURCE";
+/// Environment variables selecting the schema source of network-configs, by version.
+const NETWORK_CONFIG_SCHEMA_SOURCE_VAR: &str = "CCV_NETWORK_CONFIG_SCHEMA_SOURCE";
+const NETWORK_CONFIG_V2_SCHEMA_SOURCE_VAR: &str = "CCV_NETWORK_CONFIG_V2_SCHEMA_SOURCE";
+/// Environment variable overriding the directory of the schema cache.
+const CACHE_DIR_VAR: &str = "CCV_CACHE_DIR";
+/// Environment variable overriding the TTL of the schema cache, in seconds.
+const CACHE_TTL_VAR: &str = "CCV_CACHE_TTL";
+/// Environment variable overriding the number of release validators kept loaded.
+const RELEASE_VALIDATORS_VAR: &str = "CCV_RELEASE_VALIDATORS";
+
+/// Validators of bundled releases kept loaded by default.
+const DEFAULT_RELEASE_VALIDATORS: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Where the JSON Schemas are loaded and refreshed from.
+    pub schema_source: Arc<dyn SchemaSource>,
+    /// Where the network-config JSON Schemas are loaded from instead, by version.
+    pub network_config_source: Option<Arc<dyn SchemaSource>>,
+    pub network_config_v2_source: Option<Arc<dyn SchemaSource>>,
+    /// Cache of the JSON Schemas fetched over HTTP.
+    pub cache: SchemaCache,
+    /// Number of validators of bundled releases, by release and kind, kept loaded.
+    pub release_validators: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            schema_source: Arc::new(UrlSource::default()),
+            network_config_source: None,
+            network_config_v2_source: None,
+            cache: SchemaCache::default(),
+            release_validators: DEFAULT_RELEASE_VALIDATORS,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration from the environment, falling back to the defaults.
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_vars(|name| env::var(name).ok())
+    }
+
+    /// Reads the configuration from the variables `var` returns, falling back to the defaults.
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let source =
+            |name: &str, spec: &str| source_from_spec(spec).map_err(|e| format!("{}: {}", name, e));
+        let mut config = Self::default();
+        if let Some(spec) = var(SCHEMA_SOURCE_VAR) {
+            config.schema_source = source(SCHEMA_SOURCE_VAR, &spec)?;
+        }
+        if let Some(spec) = var(NETWORK_CONFIG_SCHEMA_SOURCE_VAR) {
+            config.network_config_source = Some(source(NETWORK_CONFIG_SCHEMA_SOURCE_VAR, &spec)?);
+        }
+        if let Some(spec) = var(NETWORK_CONFIG_V2_SCHEMA_SOURCE_VAR) {
+            config.network_config_v2_source =
+                Some(source(NETWORK_CONFIG_V2_SCHEMA_SOURCE_VAR, &spec)?);
+        }
+        // A single schema would be used for every kind.
+        if !config.schema_source.is_per_kind() {
+            for (name, source) in [
+                (
+                    NETWORK_CONFIG_SCHEMA_SOURCE_VAR,
+                    &config.network_config_source,
+                ),
+                (
+                    NETWORK_CONFIG_V2_SCHEMA_SOURCE_VAR,
+                    &config.network_config_v2_source,
+                ),
+            ] {
+                if source.is_none() {
+                    return Err(format!(
+                        "{}: a schema file or URL is the cloud-config schema only, set {} too",
+                        SCHEMA_SOURCE_VAR, name
+                    ));
+                }
+            }
+        }
+        if let Some(dir) = var(CACHE_DIR_VAR) {
+            config.cache = SchemaCache::new(dir).with_ttl(config.cache.ttl());
+        }
+        if let Some(ttl) = var(CACHE_TTL_VAR) {
+            let ttl = ttl
+                .parse()
+                .map_err(|e| format!("{}: {}", CACHE_TTL_VAR, e))?;
+            config.cache = config.cache.with_ttl(Duration::from_secs(ttl));
+        }
+        if let Some(count) = var(RELEASE_VALIDATORS_VAR) {
+            config.release_validators = count
+                .parse()
+                .map_err(|e| format!("{}: {}", RELEASE_VALIDATORS_VAR, e))?;
+        }
+        Ok(config)
+    }
+
+    /// Where the JSON Schema of `kind` is loaded and refreshed from.
+    pub fn schema_source(&self, kind: &ConfigKind) -> &dyn SchemaSource {
+        let source = match kind {
+            ConfigKind::CloudConfig => None,
+            ConfigKind::NetworkConfig => self.network_config_source.as_ref(),
+            ConfigKind::NetworkConfigV2 => self.network_config_v2_source.as_ref(),
+        };
+        let source = source.unwrap_or(&self.schema_source).as_ref();
+        // Releases predating the network-config schemas ship none, which are vendored instead.
+        if source.ships(kind) {
+            source
+        } else {
+            &VendoredSource
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn from_vars(vars: &[(&str, &str)]) -> Result<Config, String> {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        Config::from_vars(|name| vars.get(name).map(|value| value.to_string()))
+    }
+
+    #[test]
+    fn schema_sources() {
+        let source = |config: &Config, kind| format!("{:?}", config.schema_source(&kind));
+
+        let config = from_vars(&[("CCV_SCHEMA_SOURCE", "vendored")]).unwrap();
+        assert_eq!(
+            "VendoredSource",
+            source(&config, ConfigKind::NetworkConfigV2)
+        );
+
+        let config = from_vars(&[
+            ("CCV_SCHEMA_SOURCE", "cloud-config.json"),
+            ("CCV_NETWORK_CONFIG_SCHEMA_SOURCE", "network-config.json"),
+            ("CCV_NETWORK_CONFIG_V2_SCHEMA_SOURCE", "vendored"),
+        ])
+        .unwrap();
+        assert!(source(&config, ConfigKind::CloudConfig).contains("cloud-config.json"));
+        assert!(source(&config, ConfigKind::NetworkConfig).contains("network-config.json"));
+        assert_eq!(
+            "VendoredSource",
+            source(&config, ConfigKind::NetworkConfigV2)
+        );
+
+        let error = from_vars(&[
+            ("CCV_SCHEMA_SOURCE", "https://example.com/cloud-config.json"),
+            ("CCV_NETWORK_CONFIG_SCHEMA_SOURCE", "vendored"),
+        ])
+        .unwrap_err();
+        assert_eq!(
+            "CCV_SCHEMA_SOURCE: a schema file or URL is the cloud-config schema only, set \
+             CCV_NETWORK_CONFIG_V2_SCHEMA_SOURCE too",
+            error
+        );
+    }
+
+    #[cfg(feature = "release-23-1")]
+    #[test]
+    fn unshipped_schemas() {
+        let config = from_vars(&[("CCV_SCHEMA_SOURCE", "vendored:23.1")]).unwrap();
+        let source = |kind| format!("{:?}", config.schema_source(&kind));
+        assert!(source(ConfigKind::CloudConfig).contains("23.1"));
+        assert_eq!("VendoredSource", source(ConfigKind::NetworkConfig));
+        assert_eq!("VendoredSource", source(ConfigKind::NetworkConfigV2));
+    }
+}
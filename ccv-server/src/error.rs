@@ -7,15 +7,17 @@ impl From<Error> for ApiError {
     fn from(err: Error) -> Self {
         let (status, error) = match err {
             Error::InvalidYaml(e) => (StatusCode::BAD_REQUEST, e.to_string()),
+            Error::YamlLoad { .. } => (StatusCode::BAD_REQUEST, err.to_string()),
             Error::InvalidJson(e) => (StatusCode::BAD_REQUEST, e.to_string()),
+            Error::InvalidInstanceData(_) => (StatusCode::BAD_REQUEST, err.to_string()),
             Error::InvalidSchema(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
             Error::RequestError(_) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-            Error::LocalSchemaRefNotFound { .. } => {
-                (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
-            }
-            Error::LocalSchemaRefInvalidName { .. } => {
-                (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
-            }
+            Error::ReadSchema { .. } => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+            Error::SchemaRefNotFound { .. } => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+            Error::InvalidSchemaRef { .. } => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+            Error::UnknownRelease(_) => (StatusCode::BAD_REQUEST, err.to_string()),
+            Error::UnshippedSchema { .. } => (StatusCode::BAD_REQUEST, err.to_string()),
+            Error::NoBundledRelease => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
         };
         Self { status, error }
     }
@@ -1,5 +1,7 @@
 #[doc = include_str!("../README.md")]
 pub mod api;
+pub mod config;
 pub mod error; // only public for benches
+pub mod releases;
 pub use ccv_core::schema;
 pub use ccv_core::validator; // only public for benches
@@ -25,7 +25,7 @@ mod telemetry {
     }
 }
 
-use ccv_server::api::create_api;
+use ccv_server::{api::create_api, config::Config};
 use std::net::SocketAddr;
 use tokio::net::TcpListener;
 
@@ -33,7 +33,8 @@ use tokio::net::TcpListener;
 async fn main() {
     let subscriber = telemetry::get_subscriber();
     telemetry::init_subscriber(subscriber);
-    let api = create_api().await;
+    let config = Config::from_env().expect("invalid configuration");
+    let api = create_api(config).await;
 
     let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
     let listener = TcpListener::bind(addr).await.expect("cannot bind addr");
@@ -0,0 +1,156 @@
+//! Validators of the cloud-init releases bundled with the server, loaded on demand.
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use ccv_core::error::Result;
+use ccv_core::lifecycle::CloudInitVersion;
+use ccv_core::schema::{ConfigKind, Release};
+use ccv_core::validator::Validator;
+
+type Key = (CloudInitVersion, ConfigKind);
+
+/// Validators of the bundled releases by release and kind, of which the least recently used are
+/// evicted once there are more than `capacity`.
+#[derive(Debug)]
+pub struct ReleaseValidators {
+    capacity: usize,
+    /// Least recently used first.
+    validators: Mutex<VecDeque<(Key, Arc<Validator>)>>,
+}
+
+impl ReleaseValidators {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            validators: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// The validator of `kind` of the bundled release `version`, such as `23.4`, loading it if it
+    /// is not already.
+    pub fn get(&self, version: &str, kind: ConfigKind) -> Result<Arc<Validator>> {
+        let release = Release::get(version)?;
+        self.get_or_load((release.version(), kind), |key| {
+            tracing::info!("loading the {} jsonschema of cloud-init {}", key.1, key.0);
+            Ok(Arc::new(Validator::for_release(version, key.1.clone())?))
+        })
+    }
+
+    /// The validator of `key`, loading it with `load` if it is not already.
+    fn get_or_load(
+        &self,
+        key: Key,
+        load: impl FnOnce(&Key) -> Result<Arc<Validator>>,
+    ) -> Result<Arc<Validator>> {
+        if let Some(validator) = self.touch(&key) {
+            return Ok(validator);
+        }
+
+        // Compiling the schema takes a while: do it unlocked, at the risk of compiling it twice
+        // for concurrent requests.
+        let validator = load(&key)?;
+        let mut validators = self.lock();
+        validators.retain(|(loaded, _)| *loaded != key);
+        validators.push_back((key, validator.clone()));
+        while validators.len() > self.capacity {
+            validators.pop_front();
+        }
+        Ok(validator)
+    }
+
+    /// Marks the validator of `key`, if loaded, as the most recently used.
+    fn touch(&self, key: &Key) -> Option<Arc<Validator>> {
+        let mut validators = self.lock();
+        let index = validators.iter().position(|(loaded, _)| loaded == key)?;
+        let entry = validators.remove(index)?;
+        let validator = entry.1.clone();
+        validators.push_back(entry);
+        Some(validator)
+    }
+
+    /// The releases and kinds of the loaded validators, least recently used first.
+    pub fn loaded(&self) -> Vec<(CloudInitVersion, ConfigKind)> {
+        self.lock().iter().map(|(key, _)| key.clone()).collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<(Key, Arc<Validator>)>> {
+        self.validators
+            .lock()
+            .expect("error locking the release validators")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ccv_core::schema::VendoredSource;
+
+    fn version(version: &str) -> CloudInitVersion {
+        version.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn lru() {
+        let validators = ReleaseValidators::new(2);
+        let loaded = Arc::new(
+            Validator::from_source(&VendoredSource, ConfigKind::NetworkConfig)
+                .await
+                .unwrap(),
+        );
+        let load = |_: &Key| Ok(loaded.clone());
+        let cached = |_: &Key| -> Result<Arc<Validator>> { panic!("loaded again") };
+
+        validators
+            .get_or_load((version("22.4"), ConfigKind::CloudConfig), load)
+            .unwrap();
+        validators
+            .get_or_load((version("23.1"), ConfigKind::CloudConfig), load)
+            .unwrap();
+        validators
+            .get_or_load((version("22.4"), ConfigKind::CloudConfig), cached)
+            .unwrap();
+        assert_eq!(
+            vec![
+                (version("23.1"), ConfigKind::CloudConfig),
+                (version("22.4"), ConfigKind::CloudConfig),
+            ],
+            validators.loaded()
+        );
+
+        // The least recently used is evicted.
+        validators
+            .get_or_load((version("23.1"), ConfigKind::NetworkConfig), load)
+            .unwrap();
+        assert_eq!(
+            vec![
+                (version("22.4"), ConfigKind::CloudConfig),
+                (version("23.1"), ConfigKind::NetworkConfig),
+            ],
+            validators.loaded()
+        );
+
+        // Failed loads are not kept.
+        let error = |_: &Key| Err(ccv_core::error::Error::NoBundledRelease);
+        assert!(validators
+            .get_or_load((version("23.2"), ConfigKind::CloudConfig), error)
+            .is_err());
+        assert_eq!(2, validators.loaded().len());
+    }
+
+    #[cfg(feature = "release-23-1")]
+    #[test]
+    fn bundled() {
+        let validators = ReleaseValidators::new(2);
+
+        validators.get("23.1", ConfigKind::CloudConfig).unwrap();
+        validators.get("23.1.1", ConfigKind::CloudConfig).unwrap();
+        assert_eq!(
+            vec![(version("23.1"), ConfigKind::CloudConfig)],
+            validators.loaded()
+        );
+
+        assert!(validators.get("23.1", ConfigKind::NetworkConfig).is_err());
+        assert!(validators.get("9.1", ConfigKind::CloudConfig).is_err());
+        assert_eq!(1, validators.loaded().len());
+    }
+}
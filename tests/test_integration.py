@@ -38,7 +38,12 @@ runcmd:
         json={"format": "yaml", "payload": cloud_config},
     )
     assert resp.status_code == 200, resp.content
-    assert resp.json() == {"annotations": [], "errors": [], "is_valid": True}
+    assert resp.json() == {
+        "annotations": [],
+        "content_type": "text/cloud-config",
+        "errors": [],
+        "is_valid": True,
+    }
 
 
 def get_test_cases(file: Path):